@@ -0,0 +1,973 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::Instruction;
+use crate::disasm::BYTES_COLUMN;
+
+/*
+ * A small assembler for the same Cowgod style mnemonics the disassembler prints, so a listing from
+ * `chip8-disasm` can be fed back in (its address and bytes columns are skipped). One statement per
+ * line, case doesn't matter for mnemonics and registers, and everything after a `;` is a comment:
+ *
+ *         SPEED = 3              ; constants, also written `SPEED EQU 3`
+ *         include "font.asm"     ; paths are relative to the including file
+ * start:  LD V0, SPEED           ; labels end with a colon and can share the line
+ *         LD I, sprite
+ *         DRW V0, V1, 4
+ *         JP start
+ * sprite: db #F0, $90, 0b10010000, %11110000
+ *         dw #1234, start + 2
+ *
+ * Numbers can be decimal, hex (`#1F`, `$1F`, `0x1F`) or binary (`%101`, `0b101`), and anywhere a
+ * number is expected we also take labels, constants and sums or differences of them.
+ * XO-CHIP's four byte `F000 NNNN` is written `LD I, LONG addr`.
+ *
+ * It works in two passes: the first one reads every line and works out the address of each
+ * statement (every instruction has a fixed size, so labels can be used before they are defined),
+ * the second one evaluates the operands and produces the bytes.
+ */
+
+// The result of assembling a program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Assembly {
+    // Address the first byte is meant to be loaded at
+    pub origin: u16,
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+    // Where every instruction and data directive came from, in address order
+    pub source_map: Vec<SourceLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceLine {
+    pub addr: u16,
+    pub file: PathBuf,
+    // 1-based, like the line numbers in errors
+    pub line: usize,
+}
+
+impl Assembly {
+    // The source line that produced the byte at `addr`, if any
+    pub fn line_for_addr(&self, addr: u16) -> Option<&SourceLine> {
+        let idx = self.source_map.partition_point(|entry| entry.addr <= addr);
+        idx.checked_sub(1).map(|idx| &self.source_map[idx])
+    }
+}
+
+// Something in the source we couldn't assemble, with the position where it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: PathBuf,
+    // Both 1-based
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}:{}: {}",
+            self.file.display(),
+            self.line,
+            self.column,
+            self.message
+        )
+    }
+}
+
+impl Error for AsmError {}
+
+// Name used in errors for source that didn't come from a file
+const INPUT_NAME: &str = "<input>";
+
+// Deep enough for any sane program
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/*
+ * Assemble `source` for a program loaded at `origin` (normally `START_ADDR`). Included files are
+ * looked up relative to the current directory.
+ */
+pub fn assemble(source: &str, origin: u16) -> Result<Assembly, AsmError> {
+    let mut read = |path: &Path| fs::read_to_string(path);
+    assemble_with(source, Path::new(INPUT_NAME), origin, &mut read)
+}
+
+// Assemble the file at `path`, with includes relative to the directory it's in
+pub fn assemble_file(path: &Path, origin: u16) -> Result<Assembly, AsmError> {
+    let source = fs::read_to_string(path).map_err(|err| AsmError {
+        file: path.to_path_buf(),
+        line: 0,
+        column: 0,
+        message: format!("unable to read file: {}", err),
+    })?;
+    let mut read = |path: &Path| fs::read_to_string(path);
+    assemble_with(&source, path, origin, &mut read)
+}
+
+/*
+ * The general version of the two above: `file` is the name used in errors and the base for
+ * relative includes, and `read` is asked for the contents of every included file. Useful to
+ * assemble from memory, e.g. in tests or from an editor buffer.
+ */
+pub fn assemble_with(
+    source: &str,
+    file: &Path,
+    origin: u16,
+    read: &mut dyn FnMut(&Path) -> io::Result<String>,
+) -> Result<Assembly, AsmError> {
+    let mut asm = Assembler {
+        origin,
+        addr: origin as u32,
+        statements: Vec::new(),
+        labels: HashMap::new(),
+        constants: HashMap::new(),
+        include_stack: Vec::new(),
+    };
+    asm.read_source(source, file, read)?;
+    asm.generate()
+}
+
+/*
+ * Lexer
+ */
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Tok {
+    Ident(String),
+    Num(i64),
+    Str(String),
+    Comma,
+    Colon,
+    Plus,
+    Minus,
+    Equals,
+    LBracket,
+    RBracket,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    tok: Tok,
+    col: usize,
+}
+
+// Splits one line into tokens. Errors are (column, message)
+fn lex(line: &str) -> Result<Vec<Token>, (usize, String)> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = listing_columns(&chars);
+
+    while i < chars.len() {
+        let c = chars[i];
+        let col = i + 1;
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c == ';' {
+            break;
+        }
+
+        let single = match c {
+            ',' => Some(Tok::Comma),
+            ':' => Some(Tok::Colon),
+            '+' => Some(Tok::Plus),
+            '-' => Some(Tok::Minus),
+            '=' => Some(Tok::Equals),
+            '[' => Some(Tok::LBracket),
+            ']' => Some(Tok::RBracket),
+            _ => None,
+        };
+        if let Some(tok) = single {
+            tokens.push(Token { tok, col });
+            i += 1;
+            continue;
+        }
+
+        if c == '"' {
+            let start = i + 1;
+            let Some(len) = chars[start..].iter().position(|&c| c == '"') else {
+                return Err((col, "unterminated string".to_string()));
+            };
+            let text = chars[start..start + len].iter().collect();
+            tokens.push(Token {
+                tok: Tok::Str(text),
+                col,
+            });
+            i = start + len + 1;
+            continue;
+        }
+
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '.';
+        let start = i;
+        if c == '#' || c == '$' || c == '%' {
+            i += 1;
+        }
+        while i < chars.len() && is_word(chars[i]) {
+            i += 1;
+        }
+        if i == start {
+            return Err((col, format!("unexpected character '{}'", c)));
+        }
+        let word: String = chars[start..i].iter().collect();
+
+        let tok = if c.is_ascii_digit() || c == '#' || c == '$' || c == '%' {
+            Tok::Num(parse_number(&word).ok_or((col, format!("invalid number '{}'", word)))?)
+        } else {
+            Tok::Ident(word)
+        };
+        tokens.push(Token { tok, col });
+    }
+    Ok(tokens)
+}
+
+/*
+ * A line of a `chip8-disasm` listing starts with the address and the bytes of the statement, the
+ * bytes padded to a fixed width:
+ *
+ * 200: 00E0              CLS
+ *
+ * Returns where the statement starts on such a line, 0 on any other. The padding is what tells
+ * the columns apart from a label followed by something that happens to look like hex.
+ */
+fn listing_columns(chars: &[char]) -> usize {
+    let addr_len = chars.iter().take_while(|c| c.is_ascii_hexdigit()).count();
+    let bytes_start = addr_len + 2;
+    let text_start = bytes_start + BYTES_COLUMN + 2;
+    if !(3..=4).contains(&addr_len)
+        || chars.len() < text_start
+        || chars[addr_len..bytes_start] != [':', ' ']
+    {
+        return 0;
+    }
+    let column = &chars[bytes_start..text_start];
+    let hex = column.iter().take_while(|c| c.is_ascii_hexdigit()).count();
+    if hex == 0 || hex % 2 != 0 || column[hex..].iter().any(|&c| c != ' ') {
+        return 0;
+    }
+    text_start
+}
+
+fn parse_number(word: &str) -> Option<i64> {
+    let lower = word.to_ascii_lowercase();
+    let (digits, radix) = if let Some(hex) = lower
+        .strip_prefix("0x")
+        .or_else(|| lower.strip_prefix('#'))
+        .or_else(|| lower.strip_prefix('$'))
+    {
+        (hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b").or_else(|| lower.strip_prefix('%')) {
+        (bin, 2)
+    } else {
+        (lower.as_str(), 10)
+    };
+    i64::from_str_radix(digits, radix).ok()
+}
+
+/*
+ * Operands
+ */
+
+#[derive(Debug, Clone)]
+enum Term {
+    Num(i64),
+    Name(String),
+}
+
+// A sum of terms, e.g. `sprite + 5 - 1`
+#[derive(Debug, Clone)]
+struct Expr {
+    terms: Vec<(bool, Term)>,
+    col: usize,
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(u8),
+    // VX - VY, for `SAVE`/`LOAD`
+    Range(u8, u8),
+    I,
+    // [I]
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Expr(Expr),
+}
+
+fn register(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+        _ => None,
+    }
+}
+
+fn keyword(name: &str) -> Option<Operand> {
+    match name.to_ascii_uppercase().as_str() {
+        "I" => Some(Operand::I),
+        "DT" => Some(Operand::Dt),
+        "ST" => Some(Operand::St),
+        "K" => Some(Operand::K),
+        "F" => Some(Operand::F),
+        "HF" => Some(Operand::Hf),
+        "B" => Some(Operand::B),
+        "R" => Some(Operand::R),
+        _ => None,
+    }
+}
+
+// Names that can't be used for labels or constants
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some() || keyword(name).is_some() || name.eq_ignore_ascii_case("long")
+}
+
+fn parse_expr(tokens: &[Token]) -> Result<Expr, (usize, String)> {
+    let col = tokens[0].col;
+    let mut terms = Vec::new();
+    let mut i = 0;
+
+    loop {
+        let mut negative = false;
+        match tokens.get(i).map(|t| &t.tok) {
+            Some(Tok::Minus) => {
+                negative = true;
+                i += 1;
+            }
+            Some(Tok::Plus) => i += 1,
+            _ => (),
+        }
+
+        let term = match tokens.get(i) {
+            Some(Token {
+                tok: Tok::Num(n), ..
+            }) => Term::Num(*n),
+            Some(Token {
+                tok: Tok::Ident(name),
+                col,
+            }) => {
+                if is_reserved(name) {
+                    return Err((*col, format!("'{}' can't be used in an expression", name)));
+                }
+                Term::Name(name.clone())
+            }
+            Some(token) => return Err((token.col, "expected a number or a name".to_string())),
+            None => {
+                let col = tokens.last().map_or(col, |t| t.col + 1);
+                return Err((col, "expected a number or a name".to_string()));
+            }
+        };
+        terms.push((negative, term));
+        i += 1;
+
+        match tokens.get(i) {
+            None => return Ok(Expr { terms, col }),
+            Some(Token {
+                tok: Tok::Plus | Tok::Minus,
+                ..
+            }) => (),
+            Some(token) => return Err((token.col, "expected '+', '-' or ','".to_string())),
+        }
+    }
+}
+
+fn parse_operand(tokens: &[Token]) -> Result<Operand, (usize, String)> {
+    let ident = |idx: usize| match tokens.get(idx).map(|t| &t.tok) {
+        Some(Tok::Ident(name)) => Some(name.as_str()),
+        _ => None,
+    };
+
+    // [I]
+    if let [
+        Token {
+            tok: Tok::LBracket, ..
+        },
+        Token {
+            tok: Tok::Ident(name),
+            ..
+        },
+        Token {
+            tok: Tok::RBracket, ..
+        },
+    ] = tokens
+        && name.eq_ignore_ascii_case("i")
+    {
+        return Ok(Operand::IndirectI);
+    }
+
+    if tokens.len() == 1
+        && let Some(name) = ident(0)
+    {
+        if let Some(x) = register(name) {
+            return Ok(Operand::Reg(x));
+        }
+        if let Some(operand) = keyword(name) {
+            return Ok(operand);
+        }
+    }
+
+    // VX - VY
+    if tokens.len() == 3
+        && matches!(tokens[1].tok, Tok::Minus)
+        && let (Some(x), Some(y)) = (ident(0).and_then(register), ident(2).and_then(register))
+    {
+        return Ok(Operand::Range(x, y));
+    }
+
+    if ident(0).is_some_and(|name| name.eq_ignore_ascii_case("long")) {
+        if tokens.len() == 1 {
+            return Err((
+                tokens[0].col + 4,
+                "expected an address after LONG".to_string(),
+            ));
+        }
+        return Ok(Operand::Long(parse_expr(&tokens[1..])?));
+    }
+
+    parse_expr(tokens).map(Operand::Expr)
+}
+
+/*
+ * First pass
+ */
+
+#[derive(Debug, Clone)]
+enum Body {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+    Bytes(Vec<Expr>),
+    Words(Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+struct Statement {
+    addr: u16,
+    file: PathBuf,
+    line: usize,
+    // Column of the mnemonic or directive
+    col: usize,
+    body: Body,
+}
+
+struct Constant {
+    expr: Expr,
+    file: PathBuf,
+    line: usize,
+}
+
+struct Assembler {
+    origin: u16,
+    // Address of the next statement. Wider than u16 so running past the end of memory is caught
+    addr: u32,
+    statements: Vec<Statement>,
+    labels: HashMap<String, u16>,
+    constants: HashMap<String, Constant>,
+    include_stack: Vec<PathBuf>,
+}
+
+fn error_at(file: &Path, line: usize, (column, message): (usize, String)) -> AsmError {
+    AsmError {
+        file: file.to_path_buf(),
+        line,
+        column,
+        message,
+    }
+}
+
+impl Assembler {
+    fn read_source(
+        &mut self,
+        source: &str,
+        file: &Path,
+        read: &mut dyn FnMut(&Path) -> io::Result<String>,
+    ) -> Result<(), AsmError> {
+        self.include_stack.push(file.to_path_buf());
+        for (idx, text) in source.lines().enumerate() {
+            let line = idx + 1;
+            let tokens = lex(text).map_err(|err| error_at(file, line, err))?;
+            self.read_line(tokens, file, line, read)?;
+        }
+        self.include_stack.pop();
+        Ok(())
+    }
+
+    fn read_line(
+        &mut self,
+        mut tokens: Vec<Token>,
+        file: &Path,
+        line: usize,
+        read: &mut dyn FnMut(&Path) -> io::Result<String>,
+    ) -> Result<(), AsmError> {
+        let err = |col: usize, message: String| error_at(file, line, (col, message));
+
+        // `name:` at the start of the line
+        if let [
+            Token {
+                tok: Tok::Ident(name),
+                col,
+            },
+            Token {
+                tok: Tok::Colon, ..
+            },
+            ..,
+        ] = tokens.as_slice()
+        {
+            self.define_name(name, *col, file, line)?;
+            let addr = self.current_addr().map_err(|msg| err(*col, msg))?;
+            self.labels.insert(name.clone(), addr);
+            tokens.drain(..2);
+        }
+
+        let Some(first) = tokens.first() else {
+            return Ok(());
+        };
+        let Tok::Ident(word) = &first.tok else {
+            return Err(err(
+                first.col,
+                "expected an instruction or a directive".to_string(),
+            ));
+        };
+        let col = first.col;
+
+        // `NAME = value` and `NAME EQU value`
+        let is_constant = match tokens.get(1).map(|t| &t.tok) {
+            Some(Tok::Equals) => true,
+            Some(Tok::Ident(equ)) => equ.eq_ignore_ascii_case("equ"),
+            _ => false,
+        };
+        if is_constant {
+            if tokens.len() < 3 {
+                return Err(err(tokens[1].col, "expected a value".to_string()));
+            }
+            let expr = parse_expr(&tokens[2..]).map_err(|e| error_at(file, line, e))?;
+            self.define_name(word, col, file, line)?;
+            self.constants.insert(
+                word.clone(),
+                Constant {
+                    expr,
+                    file: file.to_path_buf(),
+                    line,
+                },
+            );
+            return Ok(());
+        }
+
+        let mnemonic = word.to_ascii_uppercase();
+        let rest = &tokens[1..];
+
+        if mnemonic == "INCLUDE" {
+            let [
+                Token {
+                    tok: Tok::Str(name),
+                    ..
+                },
+            ] = rest
+            else {
+                return Err(err(col, "expected a file name in quotes".to_string()));
+            };
+            return self.include(name, file, line, col, read);
+        }
+
+        let operands = split_operands(rest, col + word.len())
+            .map_err(|e| error_at(file, line, e))?
+            .iter()
+            .map(|group| parse_operand(group))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| error_at(file, line, e))?;
+
+        let (body, size) = match mnemonic.as_str() {
+            "DB" | "DW" => {
+                let exprs = operands
+                    .into_iter()
+                    .map(|operand| match operand {
+                        Operand::Expr(expr) => Ok(expr),
+                        _ => Err(err(col, format!("{} only takes numbers", mnemonic))),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if exprs.is_empty() {
+                    return Err(err(col, format!("{} needs at least one value", mnemonic)));
+                }
+                if mnemonic == "DB" {
+                    let size = exprs.len();
+                    (Body::Bytes(exprs), size)
+                } else {
+                    let size = exprs.len() * 2;
+                    (Body::Words(exprs), size)
+                }
+            }
+            _ => {
+                // Only `LD I, LONG addr` is bigger than two bytes
+                let size = match operands.as_slice() {
+                    [Operand::I, Operand::Long(_)] => 4,
+                    _ => 2,
+                };
+                (Body::Instruction { mnemonic, operands }, size)
+            }
+        };
+
+        let addr = self.current_addr().map_err(|msg| err(col, msg))?;
+        self.addr += size as u32;
+        if self.addr > 0x10000 {
+            return Err(err(col, "program doesn't fit in memory".to_string()));
+        }
+        self.statements.push(Statement {
+            addr,
+            file: file.to_path_buf(),
+            line,
+            col,
+            body,
+        });
+        Ok(())
+    }
+
+    fn include(
+        &mut self,
+        name: &str,
+        file: &Path,
+        line: usize,
+        col: usize,
+        read: &mut dyn FnMut(&Path) -> io::Result<String>,
+    ) -> Result<(), AsmError> {
+        let err = |message: String| error_at(file, line, (col, message));
+
+        let path = match file.parent() {
+            Some(dir) if file != Path::new(INPUT_NAME) => dir.join(name),
+            _ => PathBuf::from(name),
+        };
+        if self.include_stack.contains(&path) {
+            return Err(err(format!("{} includes itself", path.display())));
+        }
+        if self.include_stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(err("includes are nested too deeply".to_string()));
+        }
+
+        let source =
+            read(&path).map_err(|e| err(format!("unable to include {}: {}", path.display(), e)))?;
+        self.read_source(&source, &path, read)
+    }
+
+    fn current_addr(&self) -> Result<u16, String> {
+        u16::try_from(self.addr).map_err(|_| "program doesn't fit in memory".to_string())
+    }
+
+    fn define_name(
+        &self,
+        name: &str,
+        col: usize,
+        file: &Path,
+        line: usize,
+    ) -> Result<(), AsmError> {
+        let message = if is_reserved(name) {
+            format!("'{}' is a reserved name", name)
+        } else if name.starts_with(|c: char| c.is_ascii_digit()) {
+            format!("'{}' can't start with a digit", name)
+        } else if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            format!("'{}' is already defined", name)
+        } else {
+            return Ok(());
+        };
+        Err(error_at(file, line, (col, message)))
+    }
+
+    /*
+     * Second pass
+     */
+
+    fn generate(&self) -> Result<Assembly, AsmError> {
+        let mut bytes = Vec::new();
+        let mut source_map = Vec::new();
+
+        for statement in &self.statements {
+            let err = |e: (usize, String)| error_at(&statement.file, statement.line, e);
+
+            match &statement.body {
+                Body::Instruction { mnemonic, operands } => {
+                    let instruction = self
+                        .instruction(mnemonic, operands, statement.col)
+                        .map_err(err)?;
+                    let encoded = instruction.encode();
+
+                    // Whatever we write has to mean the same thing to the emulator
+                    if Instruction::decode_at(&encoded, 0) != Some(instruction) {
+                        return Err(err((
+                            statement.col,
+                            format!("{} can't be encoded", instruction),
+                        )));
+                    }
+                    bytes.extend(encoded);
+                }
+                Body::Bytes(exprs) => {
+                    for expr in exprs {
+                        let value = self.eval(expr).map_err(err)?;
+                        bytes.push(
+                            in_range(value, -0x80, 0xFF, "byte", expr.col).map_err(err)? as u8
+                        );
+                    }
+                }
+                Body::Words(exprs) => {
+                    for expr in exprs {
+                        let value = self.eval(expr).map_err(err)?;
+                        let word =
+                            in_range(value, -0x8000, 0xFFFF, "word", expr.col).map_err(err)?;
+                        bytes.extend((word as u16).to_be_bytes());
+                    }
+                }
+            }
+
+            source_map.push(SourceLine {
+                addr: statement.addr,
+                file: statement.file.clone(),
+                line: statement.line,
+            });
+        }
+
+        Ok(Assembly {
+            origin: self.origin,
+            bytes,
+            labels: self
+                .labels
+                .iter()
+                .map(|(name, addr)| (name.clone(), *addr))
+                .collect(),
+            source_map,
+        })
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, (usize, String)> {
+        self.eval_nested(expr, &mut Vec::new())
+    }
+
+    // `evaluating` holds the constants we are in the middle of, to catch `A = B` and `B = A`
+    fn eval_nested<'a>(
+        &'a self,
+        expr: &Expr,
+        evaluating: &mut Vec<&'a str>,
+    ) -> Result<i64, (usize, String)> {
+        let mut total: i64 = 0;
+        for (negative, term) in &expr.terms {
+            let value = match term {
+                Term::Num(n) => *n,
+                Term::Name(name) => match (
+                    self.labels.get_key_value(name),
+                    self.constants.get_key_value(name),
+                ) {
+                    (Some((_, addr)), _) => *addr as i64,
+                    (None, Some((name, constant))) => {
+                        if evaluating.contains(&name.as_str()) {
+                            return Err((
+                                expr.col,
+                                format!("'{}' is defined in terms of itself", name),
+                            ));
+                        }
+
+                        // Errors inside a constant are reported where it's used, saying where
+                        // the constant was defined
+                        let outermost = evaluating.is_empty();
+                        evaluating.push(name);
+                        let value =
+                            self.eval_nested(&constant.expr, evaluating)
+                                .map_err(|(_, msg)| {
+                                    if outermost {
+                                        let file = constant.file.display();
+                                        let msg = format!(
+                                            "{} (in '{}', defined at {}:{})",
+                                            msg, name, file, constant.line
+                                        );
+                                        (expr.col, msg)
+                                    } else {
+                                        (expr.col, msg)
+                                    }
+                                })?;
+                        evaluating.pop();
+                        value
+                    }
+                    (None, None) => return Err((expr.col, format!("unknown name '{}'", name))),
+                },
+            };
+            total = if *negative {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            }
+            .ok_or((expr.col, "number too large".to_string()))?;
+        }
+        Ok(total)
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[Operand],
+        col: usize,
+    ) -> Result<Instruction, (usize, String)> {
+        let byte = |e: &Expr| -> Result<u8, (usize, String)> {
+            Ok(in_range(self.eval(e)?, -0x80, 0xFF, "byte", e.col)? as u8)
+        };
+        let nibble = |e: &Expr| -> Result<u8, (usize, String)> {
+            Ok(in_range(self.eval(e)?, 0, 0xF, "nibble", e.col)? as u8)
+        };
+        let addr = |e: &Expr| -> Result<u16, (usize, String)> {
+            Ok(in_range(self.eval(e)?, 0, 0xFFF, "address", e.col)? as u16)
+        };
+        let long = |e: &Expr| -> Result<u16, (usize, String)> {
+            Ok(in_range(self.eval(e)?, 0, 0xFFFF, "address", e.col)? as u16)
+        };
+
+        use Operand::{B, Dt, F, Hf, I, IndirectI, K, Long, R, Range, Reg, St};
+        let instruction = match (mnemonic, operands) {
+            ("NOP", []) => Instruction::Nop,
+            ("CLS", []) => Instruction::ClearScreen,
+            ("RET", []) => Instruction::Return,
+            ("SCD", [Operand::Expr(n)]) => Instruction::ScrollDown(nibble(n)?),
+            ("SCU", [Operand::Expr(n)]) => Instruction::ScrollUp(nibble(n)?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("JP", [Operand::Expr(e)]) => Instruction::Jump(addr(e)?),
+            ("JP", [Reg(x), Operand::Expr(e)]) => {
+                // `BXNN` takes X from the address itself, so only V0 or that register make sense
+                let nnn = addr(e)?;
+                if *x != 0 && *x as u16 != nnn >> 8 {
+                    return Err((
+                        e.col,
+                        format!(
+                            "JP V{:X}, addr needs an address in #{:X}00-#{:X}FF",
+                            x, x, x
+                        ),
+                    ));
+                }
+                Instruction::JumpOffset {
+                    x: (nnn >> 8) as u8,
+                    nnn,
+                }
+            }
+            ("CALL", [Operand::Expr(e)]) => Instruction::Call(addr(e)?),
+            ("SE", [Reg(x), Reg(y)]) => Instruction::SkipIfRegEq { x: *x, y: *y },
+            ("SE", [Reg(x), Operand::Expr(e)]) => Instruction::SkipIfEq {
+                x: *x,
+                nn: byte(e)?,
+            },
+            ("SNE", [Reg(x), Reg(y)]) => Instruction::SkipIfRegNe { x: *x, y: *y },
+            ("SNE", [Reg(x), Operand::Expr(e)]) => Instruction::SkipIfNe {
+                x: *x,
+                nn: byte(e)?,
+            },
+            ("SAVE", [Range(x, y)]) => Instruction::SaveRange { x: *x, y: *y },
+            ("LOAD", [Range(x, y)]) => Instruction::LoadRange { x: *x, y: *y },
+            ("LD", [Reg(x), Reg(y)]) => Instruction::SetReg { x: *x, y: *y },
+            ("LD", [Reg(x), Operand::Expr(e)]) => Instruction::SetImm {
+                x: *x,
+                nn: byte(e)?,
+            },
+            ("LD", [I, Operand::Expr(e)]) => Instruction::SetI(addr(e)?),
+            ("LD", [I, Long(e)]) => Instruction::SetILong(long(e)?),
+            ("LD", [Reg(x), Dt]) => Instruction::GetDelay { x: *x },
+            ("LD", [Reg(x), K]) => Instruction::WaitKey { x: *x },
+            ("LD", [Dt, Reg(x)]) => Instruction::SetDelay { x: *x },
+            ("LD", [St, Reg(x)]) => Instruction::SetSound { x: *x },
+            ("LD", [F, Reg(x)]) => Instruction::Font { x: *x },
+            ("LD", [Hf, Reg(x)]) => Instruction::BigFont { x: *x },
+            ("LD", [B, Reg(x)]) => Instruction::Bcd { x: *x },
+            ("LD", [IndirectI, Reg(x)]) => Instruction::Store { x: *x },
+            ("LD", [Reg(x), IndirectI]) => Instruction::Load { x: *x },
+            ("LD", [R, Reg(x)]) => Instruction::SaveFlags { x: *x },
+            ("LD", [Reg(x), R]) => Instruction::LoadFlags { x: *x },
+            ("ADD", [Reg(x), Reg(y)]) => Instruction::Add { x: *x, y: *y },
+            ("ADD", [Reg(x), Operand::Expr(e)]) => Instruction::AddImm {
+                x: *x,
+                nn: byte(e)?,
+            },
+            ("ADD", [I, Reg(x)]) => Instruction::AddI { x: *x },
+            ("OR", [Reg(x), Reg(y)]) => Instruction::Or { x: *x, y: *y },
+            ("AND", [Reg(x), Reg(y)]) => Instruction::And { x: *x, y: *y },
+            ("XOR", [Reg(x), Reg(y)]) => Instruction::Xor { x: *x, y: *y },
+            ("SUB", [Reg(x), Reg(y)]) => Instruction::Sub { x: *x, y: *y },
+            ("SUBN", [Reg(x), Reg(y)]) => Instruction::SubReversed { x: *x, y: *y },
+            ("SHR", [Reg(x)]) => Instruction::ShiftRight { x: *x, y: *x },
+            ("SHR", [Reg(x), Reg(y)]) => Instruction::ShiftRight { x: *x, y: *y },
+            ("SHL", [Reg(x)]) => Instruction::ShiftLeft { x: *x, y: *x },
+            ("SHL", [Reg(x), Reg(y)]) => Instruction::ShiftLeft { x: *x, y: *y },
+            ("RND", [Reg(x), Operand::Expr(e)]) => Instruction::Random {
+                x: *x,
+                nn: byte(e)?,
+            },
+            ("DRW", [Reg(x), Reg(y), Operand::Expr(n)]) => Instruction::Draw {
+                x: *x,
+                y: *y,
+                n: nibble(n)?,
+            },
+            ("SKP", [Reg(x)]) => Instruction::SkipIfKey { x: *x },
+            ("SKNP", [Reg(x)]) => Instruction::SkipIfNotKey { x: *x },
+            ("PLANE", [Operand::Expr(n)]) => Instruction::SelectPlanes(nibble(n)?),
+            ("AUDIO", []) => Instruction::LoadAudio,
+            ("PITCH", [Reg(x)]) => Instruction::SetPitch { x: *x },
+            _ if KNOWN_MNEMONICS.contains(&mnemonic) => {
+                return Err((col, format!("invalid operands for {}", mnemonic)));
+            }
+            _ => return Err((col, format!("unknown instruction '{}'", mnemonic))),
+        };
+        Ok(instruction)
+    }
+}
+
+const KNOWN_MNEMONICS: &[&str] = &[
+    "NOP", "CLS", "RET", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE",
+    "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SUBN", "SHR", "SHL", "RND",
+    "DRW", "SKP", "SKNP", "PLANE", "AUDIO", "PITCH",
+];
+
+// Split the tokens after the mnemonic on commas. `end_col` is where the mnemonic ends
+fn split_operands(tokens: &[Token], end_col: usize) -> Result<Vec<&[Token]>, (usize, String)> {
+    if tokens.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut groups = Vec::new();
+    let mut start = 0;
+    for (idx, token) in tokens.iter().enumerate() {
+        if token.tok == Tok::Comma {
+            if idx == start {
+                return Err((token.col, "missing operand".to_string()));
+            }
+            groups.push(&tokens[start..idx]);
+            start = idx + 1;
+        }
+    }
+    if start == tokens.len() {
+        let col = tokens.last().map_or(end_col, |t| t.col + 1);
+        return Err((col, "missing operand".to_string()));
+    }
+    groups.push(&tokens[start..]);
+    Ok(groups)
+}
+
+fn in_range(
+    value: i64,
+    min: i64,
+    max: i64,
+    what: &str,
+    col: usize,
+) -> Result<i64, (usize, String)> {
+    if (min..=max).contains(&value) {
+        // Negative bytes and words are stored as two's complement
+        Ok(value & max)
+    } else {
+        Err((col, format!("{} {} is out of range", what, value)))
+    }
+}
@@ -0,0 +1,86 @@
+use chip8_core::START_ADDR;
+use chip8_core::asm;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str =
+    "Usage: chip8-asm [--origin ADDR] [-o path/to/game.ch8] [--symbols] path/to/source.asm";
+
+/*
+ * Assemble a source file into a ROM. The output goes next to the source with a `.ch8` extension
+ * unless `-o` says otherwise, and `--symbols` prints the address of every label afterwards.
+ */
+fn main() -> ExitCode {
+    let mut origin = START_ADDR;
+    let mut output = None;
+    let mut symbols = false;
+    let mut input = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--origin" => match args.next().as_deref().map(parse_addr) {
+                Some(Some(addr)) => origin = addr,
+                _ => return fail("--origin needs an address such as 0x200"),
+            },
+            "-o" | "--output" => match args.next() {
+                Some(path) => output = Some(PathBuf::from(path)),
+                None => return fail("-o needs a path"),
+            },
+            "--symbols" => symbols = true,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if input.is_none() && !arg.starts_with('-') => input = Some(PathBuf::from(arg)),
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(input) = input else {
+        return fail("missing source path");
+    };
+    let output = output.unwrap_or_else(|| input.with_extension("ch8"));
+
+    let assembly = match asm::assemble_file(&input, origin) {
+        Ok(assembly) => assembly,
+        Err(err) => {
+            eprintln!("{}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Err(err) = fs::write(&output, &assembly.bytes) {
+        eprintln!("chip8-asm: unable to write {}: {}", output.display(), err);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "Wrote {} bytes to {}",
+        assembly.bytes.len(),
+        output.display()
+    );
+
+    if symbols {
+        let mut labels: Vec<_> = assembly.labels.iter().collect();
+        labels.sort_by_key(|(_, addr)| **addr);
+        for (name, addr) in labels {
+            println!("{:03X} {}", addr, name);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("chip8-asm: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
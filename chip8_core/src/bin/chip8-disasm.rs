@@ -0,0 +1,101 @@
+use chip8_core::START_ADDR;
+use chip8_core::coverage::Coverage;
+use chip8_core::disasm::{self, Syntax};
+use std::env;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: chip8-disasm [--syntax cowgod|octo] [--origin ADDR] [--coverage FILE]... path/to/game.ch8";
+
+/*
+ * Print a listing of a ROM: address, raw bytes and mnemonic for every instruction, with labels
+ * on the jump and call targets. The origin defaults to 0x200 and can be given in decimal or as
+ * 0x-prefixed hex (e.g. `--origin 0x600` for ETI-660 programs).
+ *
+ * With `--coverage` (given as many times as needed, the maps are merged) code and data are told
+ * apart by what the program did while the maps were recorded, each line says how it was used and
+ * a summary of how much of the ROM was reached comes at the end.
+ */
+fn main() -> ExitCode {
+    let mut syntax = Syntax::Cowgod;
+    let mut origin = START_ADDR;
+    let mut path = None;
+    let mut coverage: Option<Coverage> = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--syntax" => match args.next().map(|s| s.parse()) {
+                Some(Ok(s)) => syntax = s,
+                Some(Err(err)) => return fail(&err),
+                None => return fail("--syntax needs a value"),
+            },
+            "--octo" => syntax = Syntax::Octo,
+            "--coverage" => {
+                let Some(file) = args.next() else {
+                    return fail("--coverage needs a file");
+                };
+                let map = match File::open(&file) {
+                    Ok(input) => Coverage::read(BufReader::new(input)).map_err(|e| e.to_string()),
+                    Err(err) => Err(err.to_string()),
+                };
+                match map {
+                    Ok(map) => coverage.get_or_insert_default().merge(&map),
+                    Err(err) => return fail(&format!("unable to read {}: {}", file, err)),
+                }
+            }
+            "--origin" => match args.next().as_deref().map(parse_addr) {
+                Some(Some(addr)) => origin = addr,
+                _ => return fail("--origin needs an address such as 0x200"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(path) = path else {
+        return fail("missing ROM path");
+    };
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => return fail(&format!("unable to read {}: {}", path, err)),
+    };
+
+    let Some(coverage) = coverage else {
+        print!("{}", disasm::disassemble(&rom, origin).render(syntax));
+        return ExitCode::SUCCESS;
+    };
+    let listing = disasm::disassemble_with_coverage(&rom, origin, &coverage);
+    print!("{}", listing.render_with_coverage(syntax, &coverage));
+    let summary = coverage.summary(origin, rom.len());
+    let percent = |n: usize| 100.0 * n as f64 / rom.len().max(1) as f64;
+    println!(
+        "; {} bytes: {} run ({:.1}%), {} data ({:.1}%), {} never used ({:.1}%)",
+        rom.len(),
+        summary.code,
+        percent(summary.code),
+        summary.data,
+        percent(summary.data),
+        summary.untouched,
+        percent(summary.untouched)
+    );
+    ExitCode::SUCCESS
+}
+
+fn parse_addr(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("chip8-disasm: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
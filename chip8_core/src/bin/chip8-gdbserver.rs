@@ -0,0 +1,109 @@
+use chip8_core::debug::Debugger;
+use chip8_core::gdb::{GdbStub, SessionEnd};
+use chip8_core::{Emu, Platform};
+use std::env;
+use std::fs;
+use std::net::TcpListener;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: chip8-gdbserver [--platform vip|chip48|schip|xochip] [--port PORT] \
+    [--ticks N] path/to/game.ch8";
+
+const DEFAULT_PORT: u16 = 1234;
+
+/*
+ * Load a ROM and wait for a GDB remote protocol client on localhost, e.g. `target remote :1234`
+ * from GDB. The ROM starts stopped at its first instruction. There's no window and no keypad: the
+ * target runs headless at the normal speed (`--ticks` instructions per 60 Hz frame).
+ *
+ * After a client detaches or disconnects the machine is kept as it is and the next client can
+ * attach to it. Killing the target from the client ends the server.
+ */
+fn main() -> ExitCode {
+    // Plain `Emu::new()` quirks unless a platform is asked for, like the other frontends
+    let mut platform: Option<Platform> = None;
+    let mut port = DEFAULT_PORT;
+    let mut ticks = None;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => match args.next().map(|s| s.parse()) {
+                Some(Ok(p)) => platform = Some(p),
+                Some(Err(err)) => return fail(&err),
+                None => return fail("--platform needs a value"),
+            },
+            "--port" => match args.next().map(|s| s.parse()) {
+                Some(Ok(p)) => port = p,
+                _ => return fail("--port needs a port number"),
+            },
+            "--ticks" => match args.next().map(|s| s.parse()) {
+                Some(Ok(t)) => ticks = Some(t),
+                _ => return fail("--ticks needs a number of instructions"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(path) = path else {
+        return fail("missing ROM path");
+    };
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => return fail(&format!("unable to read {}: {}", path, err)),
+    };
+    let mut emu = platform.map_or_else(Emu::new, Emu::for_platform);
+    if let Err(err) = emu.load(&rom) {
+        return fail(&format!("unable to load {}: {}", path, err));
+    }
+    let mut debugger = Debugger::new(emu);
+
+    // Only local clients: the protocol has no authentication and can rewrite memory
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => return fail(&format!("unable to listen on port {}: {}", port, err)),
+    };
+    println!("Listening on 127.0.0.1:{} for a GDB client", port);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(err) => {
+                eprintln!("chip8-gdbserver: {}", err);
+                continue;
+            }
+        };
+        let _ = stream.set_nodelay(true);
+        let peer = stream
+            .peer_addr()
+            .map(|a| a.to_string())
+            .unwrap_or_default();
+        println!("Client connected from {}", peer);
+
+        let mut stub = GdbStub::new(stream);
+        if let Some(ticks) = ticks {
+            stub.set_ticks_per_frame(ticks);
+        }
+        match stub.serve(&mut debugger) {
+            Ok(SessionEnd::Killed) => {
+                println!("Target killed by the client");
+                return ExitCode::SUCCESS;
+            }
+            Ok(end) => println!("Client {:?}, waiting for the next one", end),
+            Err(err) => eprintln!("chip8-gdbserver: connection lost: {}", err),
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("chip8-gdbserver: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
@@ -0,0 +1,137 @@
+use chip8_core::trace::{self, TraceEntry, TraceError, TraceReader};
+use std::collections::VecDeque;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: chip8-tracediff [--ignore FIELD,...] [--context N] a.trace b.trace
+
+Fields: cycle, PC, opcode, V0-VF, I, SP, DT, ST. `DT` ignores the delay timer before and after
+each instruction, `DT after` only after.
+
+Exit status: 0 if the traces match, 1 if they diverge, 2 for bad arguments or traces.";
+
+const DEFAULT_CONTEXT: usize = 3;
+
+const EXIT_DIVERGED: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+
+/*
+ * Compare two instruction traces (text or binary, see `trace.rs`) entry by entry and report the
+ * first one where they disagree, with the entries leading up to it. Traces from other emulators
+ * rarely agree on everything (some count cycles differently, some tick the timers at other
+ * times), so fields can be left out of the comparison with `--ignore`.
+ */
+fn main() -> ExitCode {
+    let mut ignore = Vec::new();
+    let mut context = DEFAULT_CONTEXT;
+    let mut paths = Vec::new();
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ignore" => match args.next() {
+                Some(fields) => ignore.extend(fields.split(',').map(|f| f.trim().to_string())),
+                None => return fail("--ignore needs a list of fields"),
+            },
+            "--context" => match args.next().map(|s| s.parse()) {
+                Some(Ok(n)) => context = n,
+                _ => return fail("--context needs a number"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if paths.len() < 2 && !arg.starts_with('-') => paths.push(arg),
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+    let [a_path, b_path] = paths.as_slice() else {
+        return fail("expected two traces");
+    };
+
+    let mut a = match open(a_path) {
+        Ok(reader) => reader,
+        Err(err) => return fail(&format!("{}: {}", a_path, err)),
+    };
+    let mut b = match open(b_path) {
+        Ok(reader) => reader,
+        Err(err) => return fail(&format!("{}: {}", b_path, err)),
+    };
+
+    // The last `context` entries both traces agreed on
+    let mut recent: VecDeque<TraceEntry> = VecDeque::with_capacity(context + 1);
+    let mut count = 0;
+    loop {
+        let (left, right) = match (a.next().transpose(), b.next().transpose()) {
+            (Err(err), _) => return fail(&format!("{}: {}", a_path, err)),
+            (_, Err(err)) => return fail(&format!("{}: {}", b_path, err)),
+            (Ok(left), Ok(right)) => (left, right),
+        };
+        let (left, right) = match (left, right) {
+            (None, None) => {
+                println!("Traces match ({} entries)", count);
+                return ExitCode::SUCCESS;
+            }
+            (Some(left), Some(right)) => (left, right),
+            (left, right) => {
+                let (longer, shorter) = match left.is_some() {
+                    true => (a_path, b_path),
+                    false => (b_path, a_path),
+                };
+                println!(
+                    "{} ends after {} entries, {} goes on",
+                    shorter, count, longer
+                );
+                print_context(&recent);
+                if let Some(entry) = left {
+                    println!("< {}", entry);
+                }
+                if let Some(entry) = right {
+                    println!("> {}", entry);
+                }
+                return ExitCode::from(EXIT_DIVERGED);
+            }
+        };
+
+        let mismatches = trace::compare(&left, &right, &ignore);
+        if !mismatches.is_empty() {
+            println!("Traces diverge at entry {}:", count);
+            for mismatch in &mismatches {
+                println!(
+                    "  {}: {} vs {}",
+                    mismatch.field, mismatch.left, mismatch.right
+                );
+            }
+            print_context(&recent);
+            println!("< {}", left);
+            println!("> {}", right);
+            return ExitCode::from(EXIT_DIVERGED);
+        }
+
+        count += 1;
+        if context > 0 {
+            if recent.len() == context {
+                recent.pop_front();
+            }
+            recent.push_back(left);
+        }
+    }
+}
+
+fn open(path: &str) -> Result<TraceReader<BufReader<File>>, TraceError> {
+    TraceReader::new(BufReader::new(File::open(path)?))
+}
+
+fn print_context(recent: &VecDeque<TraceEntry>) {
+    for entry in recent {
+        println!("  {}", entry);
+    }
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("chip8-tracediff: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::from(EXIT_USAGE)
+}
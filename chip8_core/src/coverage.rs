@@ -0,0 +1,183 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::{AccessKind, MemoryAccess};
+
+/*
+ * Which bytes of RAM a program used and how: run as code, read as data (sprites, `FX65`...) or
+ * written. It's filled from the access log (see `Emu::set_access_log`) after every tick, and
+ * once saved, maps from several sessions can be merged to see how much of a ROM was reached.
+ *
+ * The disassembler uses it to tell code from data, which it can't do on its own.
+ *
+ * Saved maps are text, one range of bytes used the same way per line:
+ *
+ *   # chip8 coverage v1
+ *   0200 0227 x
+ *   0228 022F r
+ *   0230 0231 rw
+ *
+ * with the first and last address in hex and `x` (run), `r` (read) and `w` (written).
+ */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Coverage {
+    // One set of `FETCHED | READ | WRITTEN` bits per byte of RAM
+    bytes: Vec<u8>,
+}
+
+pub const FETCHED: u8 = 1;
+pub const READ: u8 = 2;
+pub const WRITTEN: u8 = 4;
+
+const HEADER: &str = "# chip8 coverage v1";
+const LETTERS: [(u8, char); 3] = [(FETCHED, 'x'), (READ, 'r'), (WRITTEN, 'w')];
+
+impl Coverage {
+    pub fn new(ram_size: usize) -> Self {
+        Coverage {
+            bytes: vec![0; ram_size],
+        }
+    }
+
+    // Add the accesses of one tick, usually `emu.last_accesses()`
+    pub fn record(&mut self, accesses: &[MemoryAccess]) {
+        for access in accesses {
+            let flag = match access.kind {
+                AccessKind::Fetch => FETCHED,
+                AccessKind::Read => READ,
+                AccessKind::Write => WRITTEN,
+            };
+            self.mark(access.addr as usize, access.len, flag);
+        }
+    }
+
+    fn mark(&mut self, start: usize, len: usize, flags: u8) {
+        let end = start + len;
+        if self.bytes.len() < end {
+            self.bytes.resize(end, 0);
+        }
+        for byte in &mut self.bytes[start..end] {
+            *byte |= flags;
+        }
+    }
+
+    // The `FETCHED`, `READ` and `WRITTEN` bits of `addr`, 0 if it was never touched
+    pub fn get(&self, addr: u16) -> u8 {
+        self.bytes.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    // Everything used by either map
+    pub fn merge(&mut self, other: &Coverage) {
+        if self.bytes.len() < other.bytes.len() {
+            self.bytes.resize(other.bytes.len(), 0);
+        }
+        for (byte, other) in self.bytes.iter_mut().zip(&other.bytes) {
+            *byte |= other;
+        }
+    }
+
+    // How many of the `len` bytes from `start` were run, used only as data, and never touched
+    pub fn summary(&self, start: u16, len: usize) -> Summary {
+        let mut summary = Summary::default();
+        for addr in start as usize..start as usize + len {
+            match self.bytes.get(addr).copied().unwrap_or(0) {
+                0 => summary.untouched += 1,
+                flags if flags & FETCHED != 0 => summary.code += 1,
+                _ => summary.data += 1,
+            }
+        }
+        summary
+    }
+
+    pub fn write(&self, out: &mut impl Write) -> io::Result<()> {
+        writeln!(out, "{}", HEADER)?;
+        let mut start = 0;
+        while start < self.bytes.len() {
+            let flags = self.bytes[start];
+            let end = self.bytes[start..]
+                .iter()
+                .position(|&f| f != flags)
+                .map_or(self.bytes.len(), |len| start + len);
+            if flags != 0 {
+                writeln!(out, "{:04X} {:04X} {}", start, end - 1, letters(flags))?;
+            }
+            start = end;
+        }
+        Ok(())
+    }
+
+    pub fn read(input: impl BufRead) -> Result<Self, CoverageError> {
+        let mut coverage = Coverage::default();
+        for (n, line) in input.lines().enumerate() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let bad = || CoverageError::Invalid {
+                line: n + 1,
+                text: line.to_string(),
+            };
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [start, end, kinds] = fields[..] else {
+                return Err(bad());
+            };
+            let start = u16::from_str_radix(start, 16).map_err(|_| bad())? as usize;
+            let end = u16::from_str_radix(end, 16).map_err(|_| bad())? as usize;
+            let mut flags = 0;
+            for c in kinds.chars() {
+                let (flag, _) = LETTERS.iter().find(|(_, l)| *l == c).ok_or_else(bad)?;
+                flags |= flag;
+            }
+            if end < start {
+                return Err(bad());
+            }
+            coverage.mark(start, end - start + 1, flags);
+        }
+        Ok(coverage)
+    }
+}
+
+// `xrw` letters for a set of flags, as in saved maps
+pub fn letters(flags: u8) -> String {
+    LETTERS
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, letter)| letter)
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub code: usize,
+    pub data: usize,
+    pub untouched: usize,
+}
+
+#[derive(Debug)]
+pub enum CoverageError {
+    Io(io::Error),
+    // A line that isn't `START END KINDS` (`line` counts from 1)
+    Invalid { line: usize, text: String },
+}
+
+impl fmt::Display for CoverageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CoverageError::Io(err) => write!(f, "{}", err),
+            CoverageError::Invalid { line, text } => {
+                write!(f, "line {}: expected START END KINDS, got '{}'", line, text)
+            }
+        }
+    }
+}
+
+impl Error for CoverageError {}
+
+impl From<io::Error> for CoverageError {
+    fn from(err: io::Error) -> Self {
+        CoverageError::Io(err)
+    }
+}
@@ -0,0 +1,361 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use crate::{AccessKind, Emu, EmuError, Instruction, MemoryAccess, StepOutcome};
+
+/*
+ * A debugging layer around `Emu`. It owns the machine and runs it one instruction at a time,
+ * checking after each one whether it should stop: a breakpoint on the next PC, a watched range of
+ * memory that was touched, a watched register that changed, or the end of a step.
+ *
+ * It never ticks the timers: like a normal frontend, whoever drives the debugger decides how many
+ * instructions make a frame and calls `tick_timers` on `emu_mut()` in between.
+ */
+pub struct Debugger {
+    emu: Emu,
+    breakpoints: BTreeSet<u16>,
+    watchpoints: Vec<Watchpoint>,
+    watched_registers: BTreeSet<Register>,
+}
+
+// A range of RAM to watch and which kind of access stops execution
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addr: u16,
+    pub len: usize,
+    pub kind: WatchKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WatchKind {
+    Read,
+    Write,
+    // Either of them
+    Access,
+}
+
+impl Watchpoint {
+    fn matches(&self, access: &MemoryAccess) -> bool {
+        let kind = match (self.kind, access.kind) {
+            (_, AccessKind::Fetch) => false,
+            (WatchKind::Access, _) => true,
+            (WatchKind::Read, AccessKind::Read) => true,
+            (WatchKind::Write, AccessKind::Write) => true,
+            _ => false,
+        };
+        kind && access.overlaps(self.addr, self.len)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Register {
+    // V0 to VF
+    V(u8),
+    I,
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(x) => write!(f, "V{:X}", x),
+            Register::I => f.write_str("I"),
+        }
+    }
+}
+
+/*
+ * Where a step over or a step out ends, worked out from where it starts. Frontends that run a few
+ * instructions per frame get one from `step_over_target` or `step_out_target` and pass it to
+ * `run_to` every frame until it's reached.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepTarget {
+    // Back at `addr` with the stack `depth` deep, so a recursive call passing by doesn't count
+    Return { addr: u16, depth: usize },
+    // The stack shallower than `depth`
+    Out { depth: usize },
+}
+
+impl StepTarget {
+    pub fn reached(&self, emu: &Emu) -> bool {
+        match *self {
+            StepTarget::Return { addr, depth } => emu.pc() == addr && emu.stack().len() == depth,
+            StepTarget::Out { depth } => emu.stack().len() < depth,
+        }
+    }
+}
+
+// Why `step`, `run` and friends gave control back
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReason {
+    // The step finished, or `run_until` reached its address
+    Done,
+    // The next instruction to run is on a breakpoint
+    Breakpoint(u16),
+    // The instruction at `pc` touched a watched range of RAM, the first watchpoint that matched
+    Watchpoint {
+        pc: u16,
+        access: MemoryAccess,
+        watchpoint: Watchpoint,
+    },
+    // The instruction at `pc` changed a watched register
+    RegisterChanged {
+        pc: u16,
+        register: Register,
+        old: u16,
+        new: u16,
+    },
+    // The instruction at the PC failed, the machine is as it was before it
+    Error(EmuError),
+    // The program ran `00FD`
+    Exited,
+    // The instruction budget ran out before anything else happened
+    Limit,
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            StopReason::Done => f.write_str("step finished"),
+            StopReason::Breakpoint(addr) => write!(f, "breakpoint at {:#05X}", addr),
+            StopReason::Watchpoint { pc, access, .. } => {
+                let kind = match access.kind {
+                    AccessKind::Fetch => "fetch",
+                    AccessKind::Read => "read",
+                    AccessKind::Write => "write",
+                };
+                write!(
+                    f,
+                    "{} of {} bytes at {:#05X} by the instruction at {:#05X}",
+                    kind, access.len, access.addr, pc
+                )
+            }
+            StopReason::RegisterChanged {
+                pc,
+                register,
+                old,
+                new,
+            } => write!(
+                f,
+                "{} changed from {:#X} to {:#X} by the instruction at {:#05X}",
+                register, old, new, pc
+            ),
+            StopReason::Error(err) => write!(f, "{}", err),
+            StopReason::Exited => f.write_str("program exited"),
+            StopReason::Limit => f.write_str("instruction limit reached"),
+        }
+    }
+}
+
+impl Debugger {
+    pub fn new(mut emu: Emu) -> Self {
+        emu.set_access_log(true);
+        Debugger {
+            emu,
+            breakpoints: BTreeSet::new(),
+            watchpoints: Vec::new(),
+            watched_registers: BTreeSet::new(),
+        }
+    }
+
+    pub fn emu(&self) -> &Emu {
+        &self.emu
+    }
+
+    pub fn emu_mut(&mut self) -> &mut Emu {
+        &mut self.emu
+    }
+
+    pub fn into_emu(mut self) -> Emu {
+        self.emu.set_access_log(false);
+        self.emu
+    }
+
+    // Returns false if there already was a breakpoint at `addr`
+    pub fn add_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.insert(addr)
+    }
+
+    // Returns false if there was no breakpoint at `addr`
+    pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+        self.breakpoints.remove(&addr)
+    }
+
+    pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+        self.breakpoints.iter().copied()
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    pub fn remove_watchpoint(&mut self, watchpoint: Watchpoint) -> bool {
+        let before = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != before
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    pub fn watch_register(&mut self, register: Register) {
+        self.watched_registers.insert(register);
+    }
+
+    pub fn unwatch_register(&mut self, register: Register) -> bool {
+        self.watched_registers.remove(&register)
+    }
+
+    pub fn watched_registers(&self) -> impl Iterator<Item = Register> + '_ {
+        self.watched_registers.iter().copied()
+    }
+
+    // Run exactly one instruction (a call is entered, not skipped)
+    pub fn step(&mut self) -> StopReason {
+        self.execute_one().unwrap_or(StopReason::Done)
+    }
+
+    /*
+     * Like `step`, but a `2NNN` call runs until it returns to the instruction after it (with the
+     * stack back to the same depth, so recursion works). Breakpoints and watchpoints inside the
+     * subroutine still stop it, as does running out of `max_instructions`.
+     */
+    pub fn step_over(&mut self, max_instructions: usize) -> StopReason {
+        match self.step_over_target() {
+            Some(target) => self.run_to(target, max_instructions),
+            None => self.step(),
+        }
+    }
+
+    // Where `step_over` would stop, `None` when the PC isn't on a call and it's a plain step
+    pub fn step_over_target(&self) -> Option<StepTarget> {
+        let pc = self.emu.pc();
+        let Some(Instruction::Call(_)) = Instruction::decode_at(self.emu.ram(), pc as usize) else {
+            return None;
+        };
+        Some(StepTarget::Return {
+            addr: pc.wrapping_add(2),
+            depth: self.emu.stack().len(),
+        })
+    }
+
+    /*
+     * Run until the current subroutine returns, i.e. until a `00EE` leaves the stack shallower
+     * than it is now. At the top level (empty stack) there's nothing to return from, so this
+     * behaves like `run`.
+     */
+    pub fn step_out(&mut self, max_instructions: usize) -> StopReason {
+        match self.step_out_target() {
+            Some(target) => self.run_to(target, max_instructions),
+            None => self.run(max_instructions),
+        }
+    }
+
+    // Where `step_out` would stop, `None` at the top level where it runs like `run`
+    pub fn step_out_target(&self) -> Option<StepTarget> {
+        let depth = self.emu.stack().len();
+        (depth > 0).then_some(StepTarget::Out { depth })
+    }
+
+    // Run until `target` is reached, `Done` when it is
+    pub fn run_to(&mut self, target: StepTarget, max_instructions: usize) -> StopReason {
+        self.run_while(max_instructions, |emu| !target.reached(emu))
+    }
+
+    // Run until something stops execution or `max_instructions` have run
+    pub fn run(&mut self, max_instructions: usize) -> StopReason {
+        self.run_while(max_instructions, |_| true)
+    }
+
+    // Run until the PC reaches `addr` (as if it had a one-off breakpoint)
+    pub fn run_until(&mut self, addr: u16, max_instructions: usize) -> StopReason {
+        self.run_while(max_instructions, |emu| emu.pc() != addr)
+    }
+
+    /*
+     * The loop behind every way of running: execute instructions while `keep_going` holds,
+     * stopping early at breakpoints and watchpoints. The breakpoint at the PC we start from is
+     * ignored, otherwise continuing from a breakpoint would never move.
+     *
+     * Frontends that run a few instructions per frame call this over and over, so when the budget
+     * runs out right in front of a breakpoint it's reported instead of `Limit`. Otherwise the next
+     * call would start on it and skip it.
+     */
+    pub fn run_while(
+        &mut self,
+        max_instructions: usize,
+        keep_going: impl Fn(&Emu) -> bool,
+    ) -> StopReason {
+        for count in 0..max_instructions {
+            let pc = self.emu.pc();
+            if count > 0 && self.breakpoints.contains(&pc) {
+                return StopReason::Breakpoint(pc);
+            }
+            if let Some(reason) = self.execute_one() {
+                return reason;
+            }
+            if !keep_going(&self.emu) {
+                return StopReason::Done;
+            }
+        }
+
+        let pc = self.emu.pc();
+        if max_instructions > 0 && self.breakpoints.contains(&pc) {
+            return StopReason::Breakpoint(pc);
+        }
+        StopReason::Limit
+    }
+
+    // Run one instruction, returning a reason if it should stop execution
+    fn execute_one(&mut self) -> Option<StopReason> {
+        let pc = self.emu.pc();
+        let before: Vec<(Register, u16)> = self
+            .watched_registers
+            .iter()
+            .map(|&register| (register, self.read_register(register)))
+            .collect();
+
+        match self.emu.tick() {
+            Ok(StepOutcome::Exited) => return Some(StopReason::Exited),
+            Ok(_) => (),
+            Err(err) => return Some(StopReason::Error(err)),
+        }
+
+        for access in self.emu.last_accesses() {
+            if let Some(watchpoint) = self.watchpoints.iter().find(|w| w.matches(access)) {
+                return Some(StopReason::Watchpoint {
+                    pc,
+                    access: *access,
+                    watchpoint: *watchpoint,
+                });
+            }
+        }
+
+        for (register, old) in before {
+            let new = self.read_register(register);
+            if new != old {
+                return Some(StopReason::RegisterChanged {
+                    pc,
+                    register,
+                    old,
+                    new,
+                });
+            }
+        }
+        None
+    }
+
+    pub fn read_register(&self, register: Register) -> u16 {
+        match register {
+            Register::V(x) => self.emu.registers()[x as usize & 0xF] as u16,
+            Register::I => self.emu.i_reg(),
+        }
+    }
+}
@@ -0,0 +1,387 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::coverage::{self, Coverage};
+pub use crate::instruction::Instruction;
+
+// Formatting lives here, decoding and encoding are in `instruction.rs`
+impl Instruction {
+    /*
+     * Text for this instruction in the given syntax. Jump and call targets found in `labels` are
+     * printed by name, any other address is printed as a number.
+     */
+    pub fn format(&self, syntax: Syntax, labels: &BTreeMap<u16, String>) -> String {
+        match syntax {
+            Syntax::Cowgod => self.cowgod(labels),
+            Syntax::Octo => self.octo(labels),
+        }
+    }
+
+    /*
+     * The mnemonics from Cowgod's Chip-8 Technical Reference, with the usual extensions for the
+     * SUPER-CHIP and XO-CHIP opcodes. Numbers are written in hex with a `#` prefix.
+     */
+    fn cowgod(&self, labels: &BTreeMap<u16, String>) -> String {
+        let addr = |nnn: u16| match labels.get(&nnn) {
+            Some(label) => label.clone(),
+            None => format!("#{:03X}", nnn),
+        };
+
+        match *self {
+            Instruction::Nop => "NOP".to_string(),
+            Instruction::ClearScreen => "CLS".to_string(),
+            Instruction::Return => "RET".to_string(),
+            Instruction::ScrollDown(n) => format!("SCD {}", n),
+            Instruction::ScrollUp(n) => format!("SCU {}", n),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::LowRes => "LOW".to_string(),
+            Instruction::HighRes => "HIGH".to_string(),
+            Instruction::Jump(nnn) => format!("JP {}", addr(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", addr(nnn)),
+            Instruction::SkipIfEq { x, nn } => format!("SE V{:X}, #{:02X}", x, nn),
+            Instruction::SkipIfNe { x, nn } => format!("SNE V{:X}, #{:02X}", x, nn),
+            Instruction::SkipIfRegEq { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::SaveRange { x, y } => format!("SAVE V{:X} - V{:X}", x, y),
+            Instruction::LoadRange { x, y } => format!("LOAD V{:X} - V{:X}", x, y),
+            Instruction::SetImm { x, nn } => format!("LD V{:X}, #{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => format!("ADD V{:X}, #{:02X}", x, nn),
+            Instruction::SetReg { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReversed { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfRegNe { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI(nnn) => format!("LD I, {}", addr(nnn)),
+            Instruction::JumpOffset { nnn, .. } => format!("JP V0, {}", addr(nnn)),
+            Instruction::Random { x, nn } => format!("RND V{:X}, #{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKey { x } => format!("SKP V{:X}", x),
+            Instruction::SkipIfNotKey { x } => format!("SKNP V{:X}", x),
+            Instruction::SetILong(nnnn) => format!("LD I, LONG #{:04X}", nnnn),
+            Instruction::SelectPlanes(n) => format!("PLANE {}", n),
+            Instruction::LoadAudio => "AUDIO".to_string(),
+            Instruction::GetDelay { x } => format!("LD V{:X}, DT", x),
+            Instruction::WaitKey { x } => format!("LD V{:X}, K", x),
+            Instruction::SetDelay { x } => format!("LD DT, V{:X}", x),
+            Instruction::SetSound { x } => format!("LD ST, V{:X}", x),
+            Instruction::AddI { x } => format!("ADD I, V{:X}", x),
+            Instruction::Font { x } => format!("LD F, V{:X}", x),
+            Instruction::BigFont { x } => format!("LD HF, V{:X}", x),
+            Instruction::Bcd { x } => format!("LD B, V{:X}", x),
+            Instruction::SetPitch { x } => format!("PITCH V{:X}", x),
+            Instruction::Store { x } => format!("LD [I], V{:X}", x),
+            Instruction::Load { x } => format!("LD V{:X}, [I]", x),
+            Instruction::SaveFlags { x } => format!("LD R, V{:X}", x),
+            Instruction::LoadFlags { x } => format!("LD V{:X}, R", x),
+            Instruction::Unknown(op) => format!("DW #{:04X}", op),
+        }
+    }
+
+    /*
+     * Octo, the assembly language most CHIP-8 programs are written in nowadays. Note that Octo's
+     * `if ... then` runs the next instruction when the condition holds, so it's written with the
+     * opposite comparison of the skip: `3XNN` (skip if equal) is `if vX != NN then`.
+     */
+    fn octo(&self, labels: &BTreeMap<u16, String>) -> String {
+        let addr = |nnn: u16| match labels.get(&nnn) {
+            Some(label) => label.clone(),
+            None => format!("0x{:03X}", nnn),
+        };
+
+        match *self {
+            Instruction::Nop => "0x00 0x00".to_string(),
+            Instruction::ClearScreen => "clear".to_string(),
+            Instruction::Return => "return".to_string(),
+            Instruction::ScrollDown(n) => format!("scroll-down {}", n),
+            Instruction::ScrollUp(n) => format!("scroll-up {}", n),
+            Instruction::ScrollRight => "scroll-right".to_string(),
+            Instruction::ScrollLeft => "scroll-left".to_string(),
+            Instruction::Exit => "exit".to_string(),
+            Instruction::LowRes => "lores".to_string(),
+            Instruction::HighRes => "hires".to_string(),
+            Instruction::Jump(nnn) => format!("jump {}", addr(nnn)),
+            // Calling a label is just writing its name
+            Instruction::Call(nnn) => match labels.get(&nnn) {
+                Some(label) => label.clone(),
+                None => format!(":call 0x{:03X}", nnn),
+            },
+            Instruction::SkipIfEq { x, nn } => format!("if v{:x} != 0x{:02X} then", x, nn),
+            Instruction::SkipIfNe { x, nn } => format!("if v{:x} == 0x{:02X} then", x, nn),
+            Instruction::SkipIfRegEq { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            Instruction::SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            Instruction::LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            Instruction::SetImm { x, nn } => format!("v{:x} := 0x{:02X}", x, nn),
+            Instruction::AddImm { x, nn } => format!("v{:x} += 0x{:02X}", x, nn),
+            Instruction::SetReg { x, y } => format!("v{:x} := v{:x}", x, y),
+            Instruction::Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            Instruction::And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Instruction::Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Instruction::Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Instruction::Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Instruction::ShiftRight { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Instruction::SubReversed { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Instruction::ShiftLeft { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            Instruction::SkipIfRegNe { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            Instruction::SetI(nnn) => format!("i := {}", addr(nnn)),
+            Instruction::JumpOffset { nnn, .. } => format!("jump0 {}", addr(nnn)),
+            Instruction::Random { x, nn } => format!("v{:x} := random 0x{:02X}", x, nn),
+            Instruction::Draw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Instruction::SkipIfKey { x } => format!("if v{:x} -key then", x),
+            Instruction::SkipIfNotKey { x } => format!("if v{:x} key then", x),
+            Instruction::SetILong(nnnn) => format!("i := long 0x{:04X}", nnnn),
+            Instruction::SelectPlanes(n) => format!("plane {}", n),
+            Instruction::LoadAudio => "audio".to_string(),
+            Instruction::GetDelay { x } => format!("v{:x} := delay", x),
+            Instruction::WaitKey { x } => format!("v{:x} := key", x),
+            Instruction::SetDelay { x } => format!("delay := v{:x}", x),
+            Instruction::SetSound { x } => format!("buzzer := v{:x}", x),
+            Instruction::AddI { x } => format!("i += v{:x}", x),
+            Instruction::Font { x } => format!("i := hex v{:x}", x),
+            Instruction::BigFont { x } => format!("i := bighex v{:x}", x),
+            Instruction::Bcd { x } => format!("bcd v{:x}", x),
+            Instruction::SetPitch { x } => format!("pitch := v{:x}", x),
+            Instruction::Store { x } => format!("save v{:x}", x),
+            Instruction::Load { x } => format!("load v{:x}", x),
+            Instruction::SaveFlags { x } => format!("saveflags v{:x}", x),
+            Instruction::LoadFlags { x } => format!("loadflags v{:x}", x),
+            Instruction::Unknown(op) => format!("0x{:02X} 0x{:02X}", op >> 8, op & 0xFF),
+        }
+    }
+}
+
+// Cowgod syntax, without labels
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.cowgod(&BTreeMap::new()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Syntax {
+    #[default]
+    Cowgod,
+    Octo,
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("unknown syntax '{}' (expected cowgod or octo)", s)),
+        }
+    }
+}
+
+// One line of a listing: an instruction, or data bytes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    // `None` for data: known to be data from a coverage map, or a lone byte left at the end
+    pub instruction: Option<Instruction>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Listing {
+    pub lines: Vec<Line>,
+    // Names given to the jump and call targets that fall on the start of a line
+    pub labels: BTreeMap<u16, String>,
+}
+
+/*
+ * Disassemble a whole ROM loaded at `origin` (normally `START_ADDR`), going through it two bytes
+ * at a time from the beginning. CHIP-8 programs freely mix code and sprite data, so without
+ * running the program we can't tell them apart: data shows up as (probably odd looking)
+ * instructions, and if some data has an odd length the code after it may come out misaligned.
+ *
+ * Every address that is the target of a `1NNN` jump or a `2NNN` call gets a label, `sub_XXX` for
+ * subroutines and `label_XXX` for the rest.
+ */
+pub fn disassemble(rom: &[u8], origin: u16) -> Listing {
+    disassemble_with(rom, origin, None)
+}
+
+// Most data lines have this many bytes, none has more
+const DATA_PER_LINE: usize = 8;
+
+// Width of the bytes column of a listing, the assembler skips it
+pub(crate) const BYTES_COLUMN: usize = 2 * DATA_PER_LINE;
+
+/*
+ * Same as `disassemble`, but using what a coverage map knows about each byte: bytes that were
+ * run are decoded as instructions starting from where execution went, bytes that were only
+ * read or written are data, and only bytes the map never saw are guessed at as before.
+ */
+pub fn disassemble_with_coverage(rom: &[u8], origin: u16, coverage: &Coverage) -> Listing {
+    disassemble_with(rom, origin, Some(coverage))
+}
+
+fn disassemble_with(rom: &[u8], origin: u16, coverage: Option<&Coverage>) -> Listing {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    let flags = |offset: usize| coverage.map_or(0, |c| c.get(origin.wrapping_add(offset as u16)));
+    let is_data = |offset: usize| flags(offset) != 0 && flags(offset) & coverage::FETCHED == 0;
+
+    while offset < rom.len() {
+        let addr = origin.wrapping_add(offset as u16);
+        if is_data(offset) {
+            let len = (offset..rom.len())
+                .take(DATA_PER_LINE)
+                .take_while(|&at| is_data(at) && flags(at) == flags(offset))
+                .count();
+            lines.push(Line {
+                addr,
+                bytes: rom[offset..offset + len].to_vec(),
+                instruction: None,
+            });
+            offset += len;
+            continue;
+        }
+        // An instruction we'd guess in untouched bytes mustn't swallow the start of a run one
+        if flags(offset) == 0 && (is_data(offset + 1) || flags(offset + 1) & coverage::FETCHED != 0)
+        {
+            lines.push(Line {
+                addr,
+                bytes: vec![rom[offset]],
+                instruction: None,
+            });
+            offset += 1;
+            continue;
+        }
+
+        match Instruction::decode_at(rom, offset) {
+            Some(instruction) => {
+                let len = instruction.size();
+                lines.push(Line {
+                    addr,
+                    bytes: rom[offset..offset + len].to_vec(),
+                    instruction: Some(instruction),
+                });
+                offset += len;
+            }
+            None if offset + 2 <= rom.len() => {
+                // `F000` right at the end, without the address that should follow it
+                let op = u16::from_be_bytes([rom[offset], rom[offset + 1]]);
+                lines.push(Line {
+                    addr,
+                    bytes: rom[offset..offset + 2].to_vec(),
+                    instruction: Some(Instruction::Unknown(op)),
+                });
+                offset += 2;
+            }
+            None => {
+                lines.push(Line {
+                    addr,
+                    bytes: vec![rom[offset]],
+                    instruction: None,
+                });
+                offset += 1;
+            }
+        }
+    }
+
+    // Only targets that start a line can be labelled, anything else stays a plain number
+    let starts: Vec<u16> = lines.iter().map(|line| line.addr).collect();
+    let mut labels = BTreeMap::new();
+    for instruction in lines.iter().filter_map(|line| line.instruction) {
+        if let Some(target) = instruction.target() {
+            if starts.binary_search(&target).is_err() {
+                continue;
+            }
+            // A target that is both called and jumped to is named as a subroutine
+            match instruction {
+                Instruction::Call(_) => {
+                    labels.insert(target, format!("sub_{:03X}", target));
+                }
+                _ => {
+                    labels
+                        .entry(target)
+                        .or_insert_with(|| format!("label_{:03X}", target));
+                }
+            }
+        }
+    }
+
+    Listing { lines, labels }
+}
+
+impl Listing {
+    /*
+     * The listing as text: one instruction per line with its address and raw bytes, and each
+     * label on its own line right before the instruction it names, as `name:` or Octo's `: name`.
+     *
+     * 200: 00E0              CLS
+     * sub_202:
+     * 202: A22A              LD I, #22A
+     */
+    pub fn render(&self, syntax: Syntax) -> String {
+        self.render_with(syntax, None)
+    }
+
+    /*
+     * `render` with a column saying how each line was used according to `coverage`: `x` run,
+     * `r` read, `w` written or `-` never touched.
+     *
+     * 200: 00E0              x    CLS
+     */
+    pub fn render_with_coverage(&self, syntax: Syntax, coverage: &Coverage) -> String {
+        self.render_with(syntax, Some(coverage))
+    }
+
+    fn render_with(&self, syntax: Syntax, coverage: Option<&Coverage>) -> String {
+        let mut out = String::new();
+
+        for line in &self.lines {
+            match (self.labels.get(&line.addr), syntax) {
+                (Some(label), Syntax::Cowgod) => out.push_str(&format!("{}:\n", label)),
+                (Some(label), Syntax::Octo) => out.push_str(&format!(": {}\n", label)),
+                (None, _) => (),
+            }
+
+            // Wide enough for the longest data line, so the text lines up whatever the line holds
+            let bytes: String = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+            let bytes = format!("{:<width$}", bytes, width = BYTES_COLUMN);
+            let text = match line.instruction {
+                Some(instruction) => instruction.format(syntax, &self.labels),
+                None => {
+                    let data: Vec<String> = match syntax {
+                        Syntax::Cowgod => {
+                            line.bytes.iter().map(|b| format!("#{:02X}", b)).collect()
+                        }
+                        Syntax::Octo => line.bytes.iter().map(|b| format!("0x{:02X}", b)).collect(),
+                    };
+                    match syntax {
+                        Syntax::Cowgod => format!("DB {}", data.join(", ")),
+                        Syntax::Octo => data.join(" "),
+                    }
+                }
+            };
+            let Some(coverage) = coverage else {
+                out.push_str(&format!("{:03X}: {}  {}\n", line.addr, bytes, text));
+                continue;
+            };
+            let flags = (0..line.bytes.len()).fold(0, |flags, i| {
+                flags | coverage.get(line.addr.wrapping_add(i as u16))
+            });
+            let used = match flags {
+                0 => "-".to_string(),
+                flags => coverage::letters(flags),
+            };
+            out.push_str(&format!(
+                "{:03X}: {}  {:<3}  {}\n",
+                line.addr, bytes, used, text
+            ));
+        }
+        out
+    }
+}
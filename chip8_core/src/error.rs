@@ -0,0 +1,134 @@
+use std::error::Error;
+use std::fmt;
+
+/*
+ * Everything that can go wrong while the CPU is running a ROM. Every variant carries the address
+ * of the instruction that caused it (`addr`), so a frontend can point at the exact opcode that
+ * failed. When `Emu::tick` returns one of these errors the machine is left exactly as it was
+ * before that instruction started: the PC still points at the faulting opcode and no register or
+ * memory cell has been modified, so the state can be inspected or dumped afterwards.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmuError {
+    // The opcode doesn't match any instruction we know how to execute
+    UnknownOpcode {
+        addr: u16,
+        opcode: u16,
+    },
+
+    // `2NNN` was executed with all 16 stack slots already in use
+    StackOverflow {
+        addr: u16,
+    },
+
+    // `00EE` was executed with an empty stack
+    StackUnderflow {
+        addr: u16,
+    },
+
+    // An instruction tried to read or write `len` bytes of RAM starting at `target`, but part of
+    // that range falls outside of memory
+    MemoryOutOfRange {
+        addr: u16,
+        target: usize,
+        len: usize,
+    },
+
+    // `EX9E`/`EXA1` were asked about a key that doesn't exist (only 0x0 to 0xF are valid)
+    InvalidKey {
+        addr: u16,
+        key: u8,
+    },
+}
+
+impl EmuError {
+    // Address of the instruction that failed
+    pub fn addr(&self) -> u16 {
+        match *self {
+            EmuError::UnknownOpcode { addr, .. }
+            | EmuError::StackOverflow { addr }
+            | EmuError::StackUnderflow { addr }
+            | EmuError::MemoryOutOfRange { addr, .. }
+            | EmuError::InvalidKey { addr, .. } => addr,
+        }
+    }
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            EmuError::UnknownOpcode { addr, opcode } => {
+                write!(f, "unknown opcode {:#06X} at {:#05X}", opcode, addr)
+            }
+            EmuError::StackOverflow { addr } => write!(f, "stack overflow at {:#05X}", addr),
+            EmuError::StackUnderflow { addr } => write!(f, "stack underflow at {:#05X}", addr),
+            EmuError::MemoryOutOfRange { addr, target, len } => write!(
+                f,
+                "memory access out of range at {:#05X} ({} byte(s) from {:#06X})",
+                addr, len, target
+            ),
+            EmuError::InvalidKey { addr, key } => {
+                write!(f, "invalid key index {:#04X} at {:#05X}", key, addr)
+            }
+        }
+    }
+}
+
+impl Error for EmuError {}
+
+/*
+ * Reasons a ROM (or any other block of data) can't be copied into RAM. Loading never writes a
+ * single byte unless the whole segment fits, so a failed load leaves memory untouched.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    // There is nothing to load
+    Empty,
+
+    // The segment would start inside the interpreter area (0x000-0x1FF), where the font lives
+    ReservedAddress {
+        addr: u16,
+    },
+
+    // The segment doesn't fit between `addr` and the end of RAM
+    TooLarge {
+        addr: u16,
+        len: usize,
+        available: usize,
+    },
+
+    // The segment would overwrite part of a segment loaded earlier, starting at `other`
+    Overlap {
+        addr: u16,
+        other: u16,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            LoadError::Empty => write!(f, "ROM is empty"),
+            LoadError::ReservedAddress { addr } => write!(
+                f,
+                "cannot load at {:#05X}: addresses below 0x200 are reserved for the interpreter",
+                addr
+            ),
+            LoadError::TooLarge {
+                addr,
+                len,
+                available,
+            } => write!(
+                f,
+                "{} bytes don't fit at {:#05X}, only {} bytes of RAM are available",
+                len, addr, available
+            ),
+            LoadError::Overlap { addr, other } => write!(
+                f,
+                "segment at {:#05X} overlaps the segment already loaded at {:#05X}",
+                addr, other
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
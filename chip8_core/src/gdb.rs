@@ -0,0 +1,599 @@
+use std::io::{self, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::debug::{Debugger, StopReason, WatchKind, Watchpoint};
+
+/*
+ * A stub for the GDB remote serial protocol, so GDB (a build with multi-arch support), LLDB or
+ * any other tool that speaks it can attach to a running ROM.
+ *
+ * Every message is a packet `$data#xx` where `xx` is the sum of the bytes of `data` modulo 256 in
+ * hex, and each packet is acknowledged with `+` (or `-` to ask for it again) until the client
+ * switches that off with `QStartNoAckMode`. A single 0x03 byte interrupts a running target.
+ *
+ * The client learns about the registers from `target.xml` (see `TARGET_XML`): V0 to VF, I, PC,
+ * SP, DT and ST, in that order. Registers wider than a byte are sent big-endian, the byte order
+ * of the CHIP-8 itself.
+ *
+ * Supported: reading and writing registers and memory, software breakpoints (`Z0`), write, read
+ * and access watchpoints (`Z2`-`Z4`), single-step and continue, plus the queries GDB needs to
+ * attach. Anything else gets the empty reply, which means "not supported".
+ */
+
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chip8.core">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="8" type="uint8"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+// Register numbers in `p`/`P` packets, following the order of `TARGET_XML`
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DT: usize = 19;
+const REG_ST: usize = 20;
+const NUM_GDB_REGS: usize = 21;
+
+// Largest packet we accept, advertised in `qSupported`
+const PACKET_SIZE: usize = 0x4000;
+
+// The byte a client sends to interrupt a running target (Ctrl-C)
+const INTERRUPT: u8 = 0x03;
+
+// Same frame rate and speed as the desktop frontend while the target runs
+const FRAME: Duration = Duration::from_micros(16_667);
+const TICKS_PER_FRAME: usize = 10;
+
+// Signal numbers used in stop replies
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+
+/*
+ * A stream the stub can talk over. Besides reading and writing it has to tell whether there's
+ * input waiting without blocking, which is how a running target notices an interrupt.
+ */
+pub trait Connection: Read + Write {
+    fn has_input(&mut self) -> io::Result<bool>;
+}
+
+impl Connection for TcpStream {
+    fn has_input(&mut self) -> io::Result<bool> {
+        self.set_nonblocking(true)?;
+        let result = match self.peek(&mut [0]) {
+            // 0 bytes means the client closed the connection, let the next read find out
+            Ok(_) => Ok(true),
+            Err(err) if err.kind() == ErrorKind::WouldBlock => Ok(false),
+            Err(err) => Err(err),
+        };
+        self.set_nonblocking(false)?;
+        result
+    }
+}
+
+// How a session ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    // `D`: the client let go, the target could keep running
+    Detached,
+    // `k`: the client asked to kill the target
+    Killed,
+    // The connection closed without saying goodbye
+    Disconnected,
+}
+
+pub struct GdbStub<C: Connection> {
+    conn: C,
+    no_ack: bool,
+    ticks_per_frame: usize,
+}
+
+// What to do after a packet has been answered
+enum Action {
+    Reply(Vec<u8>),
+    Resume { step: bool },
+    End(SessionEnd, Option<Vec<u8>>),
+}
+
+impl<C: Connection> GdbStub<C> {
+    pub fn new(conn: C) -> Self {
+        GdbStub {
+            conn,
+            no_ack: false,
+            ticks_per_frame: TICKS_PER_FRAME,
+        }
+    }
+
+    // Instructions run per 60 Hz frame while the target is running
+    pub fn set_ticks_per_frame(&mut self, ticks: usize) {
+        self.ticks_per_frame = ticks.max(1);
+    }
+
+    /*
+     * Answer packets until the client detaches, kills the target or goes away. The target
+     * starts (and stays) stopped until the client asks it to step or continue.
+     */
+    pub fn serve(&mut self, debugger: &mut Debugger) -> io::Result<SessionEnd> {
+        loop {
+            let Some(packet) = self.read_packet()? else {
+                return Ok(SessionEnd::Disconnected);
+            };
+            match self.handle(debugger, &packet) {
+                Action::Reply(reply) => self.write_packet(&reply)?,
+                Action::Resume { step } => {
+                    let reply = self.resume(debugger, step)?;
+                    self.write_packet(&reply)?;
+                }
+                Action::End(end, reply) => {
+                    if let Some(reply) = reply {
+                        self.write_packet(&reply)?;
+                    }
+                    return Ok(end);
+                }
+            }
+        }
+    }
+
+    fn handle(&mut self, debugger: &mut Debugger, packet: &[u8]) -> Action {
+        let text = String::from_utf8_lossy(packet);
+        let reply = match text.as_bytes().first() {
+            None => Vec::new(),
+            Some(b'?') => stop_reply(&StopReason::Done),
+            Some(b'g') => read_registers(debugger),
+            Some(b'G') => or_error(write_registers(debugger, &text[1..])),
+            Some(b'p') => or_error(read_register(debugger, &text[1..])),
+            Some(b'P') => or_error(write_register(debugger, &text[1..])),
+            Some(b'm') => or_error(read_memory(debugger, &text[1..])),
+            Some(b'M') => or_error(write_memory_hex(debugger, &text[1..])),
+            Some(b'X') => or_error(write_memory_binary(debugger, packet)),
+            Some(b'Z') => or_error(set_breakpoint(debugger, &text[1..], true)),
+            Some(b'z') => or_error(set_breakpoint(debugger, &text[1..], false)),
+            Some(b's') => return resume_at(debugger, &text[1..], true),
+            Some(b'c') => return resume_at(debugger, &text[1..], false),
+            Some(b'v') => return self.handle_v(&text),
+            Some(b'q' | b'Q') => self.handle_query(&text),
+            // Only one thread, so selecting it and asking if it's alive always works
+            Some(b'H' | b'T') => b"OK".to_vec(),
+            Some(b'D') => return Action::End(SessionEnd::Detached, Some(b"OK".to_vec())),
+            // `k` gets no reply
+            Some(b'k') => return Action::End(SessionEnd::Killed, None),
+            Some(_) => Vec::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    fn handle_v(&mut self, text: &str) -> Action {
+        match text {
+            "vCont?" => Action::Reply(b"vCont;c;C;s;S".to_vec()),
+            // With a single thread the first action is the only one that matters
+            _ if text.starts_with("vCont;") => match text.as_bytes().get(6) {
+                Some(b's' | b'S') => Action::Resume { step: true },
+                Some(b'c' | b'C') => Action::Resume { step: false },
+                _ => Action::Reply(b"E01".to_vec()),
+            },
+            "vMustReplyEmpty" => Action::Reply(Vec::new()),
+            // `vKill` and friends
+            _ if text.starts_with("vKill") => Action::End(SessionEnd::Killed, Some(b"OK".to_vec())),
+            _ => Action::Reply(Vec::new()),
+        }
+    }
+
+    fn handle_query(&mut self, text: &str) -> Vec<u8> {
+        if text.starts_with("qSupported") {
+            return format!(
+                "PacketSize={:x};qXfer:features:read+;QStartNoAckMode+;swbreak+;vContSupported+",
+                PACKET_SIZE
+            )
+            .into_bytes();
+        }
+        if let Some(range) = text.strip_prefix("qXfer:features:read:target.xml:") {
+            return or_error(xfer(TARGET_XML.as_bytes(), range));
+        }
+        match text {
+            "QStartNoAckMode" => {
+                // The `OK` itself still gets acknowledged, acks stop after it
+                self.no_ack = true;
+                b"OK".to_vec()
+            }
+            // We're attached to an existing process, so detaching shouldn't kill it
+            "qAttached" => b"1".to_vec(),
+            "qC" => b"QC1".to_vec(),
+            "qfThreadInfo" => b"m1".to_vec(),
+            "qsThreadInfo" => b"l".to_vec(),
+            "qOffsets" => b"Text=0;Data=0;Bss=0".to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    /*
+     * Step once, or run until something stops the target. Running goes at the speed of the
+     * desktop frontend (ticking the timers at 60 Hz) so games behave normally, and checks for an
+     * interrupt from the client between frames.
+     */
+    fn resume(&mut self, debugger: &mut Debugger, step: bool) -> io::Result<Vec<u8>> {
+        if step {
+            return Ok(stop_reply(&debugger.step()));
+        }
+
+        let mut next_frame = Instant::now();
+        loop {
+            if self.conn.has_input()? {
+                let mut byte = [0];
+                if self.conn.read(&mut byte)? == 0 || byte[0] == INTERRUPT {
+                    return Ok(format!("T{:02x}", SIGINT).into_bytes());
+                }
+            }
+
+            let reason = debugger.run(self.ticks_per_frame);
+            if reason != StopReason::Limit {
+                return Ok(stop_reply(&reason));
+            }
+            debugger.emu_mut().tick_timers();
+
+            next_frame += FRAME;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+    }
+
+    /*
+     * Read the next packet, acknowledging it unless acks are off. Anything outside a packet
+     * (stray acks, interrupts while stopped) is skipped. `None` if the connection closed.
+     */
+    fn read_packet(&mut self) -> io::Result<Option<Vec<u8>>> {
+        loop {
+            match self.read_byte()? {
+                None => return Ok(None),
+                Some(b'$') => (),
+                Some(_) => continue,
+            }
+
+            let mut data = Vec::new();
+            let mut sum: u8 = 0;
+            loop {
+                let Some(byte) = self.read_byte()? else {
+                    return Ok(None);
+                };
+                if byte == b'#' {
+                    break;
+                }
+                sum = sum.wrapping_add(byte);
+                // `}` escapes the next byte (XORed with 0x20) in binary data
+                if byte == b'}' {
+                    let Some(escaped) = self.read_byte()? else {
+                        return Ok(None);
+                    };
+                    sum = sum.wrapping_add(escaped);
+                    data.push(escaped ^ 0x20);
+                } else {
+                    data.push(byte);
+                }
+            }
+
+            let (Some(hi), Some(lo)) = (self.read_byte()?, self.read_byte()?) else {
+                return Ok(None);
+            };
+            let checksum = std::str::from_utf8(&[hi, lo])
+                .ok()
+                .and_then(|s| u8::from_str_radix(s, 16).ok());
+
+            if self.no_ack {
+                return Ok(Some(data));
+            }
+            if checksum == Some(sum) {
+                self.conn.write_all(b"+")?;
+                return Ok(Some(data));
+            }
+            self.conn.write_all(b"-")?;
+        }
+    }
+
+    // Send a packet, resending it while the client answers `-`
+    fn write_packet(&mut self, data: &[u8]) -> io::Result<()> {
+        let mut packet = Vec::with_capacity(data.len() + 4);
+        packet.push(b'$');
+        for &byte in data {
+            if matches!(byte, b'$' | b'#' | b'}' | b'*') {
+                packet.extend([b'}', byte ^ 0x20]);
+            } else {
+                packet.push(byte);
+            }
+        }
+        let sum = packet[1..].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        packet.extend(format!("#{:02x}", sum).bytes());
+
+        loop {
+            self.conn.write_all(&packet)?;
+            self.conn.flush()?;
+            if self.no_ack {
+                return Ok(());
+            }
+            match self.read_byte()? {
+                Some(b'-') => continue,
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        loop {
+            match self.conn.read(&mut byte) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(byte[0])),
+                Err(err) if err.kind() == ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+/*
+ * `T05` plus what caused the stop, so the client can tell a breakpoint from a watchpoint (and
+ * which kind it set, whatever the access was). The address is the first watched byte that was
+ * touched, not the start of the access, which for `FX55` and `FX65` can be well before it. A
+ * program that exited with `00FD` reports exit code 0.
+ */
+fn stop_reply(reason: &StopReason) -> Vec<u8> {
+    let reply = match *reason {
+        StopReason::Breakpoint(_) => format!("T{:02x}swbreak:;", SIGTRAP),
+        StopReason::Watchpoint {
+            access, watchpoint, ..
+        } => {
+            let kind = match watchpoint.kind {
+                WatchKind::Write => "watch",
+                WatchKind::Read => "rwatch",
+                WatchKind::Access => "awatch",
+            };
+            let hit = access.addr.max(watchpoint.addr);
+            format!("T{:02x}{}:{:x};", SIGTRAP, kind, hit)
+        }
+        StopReason::Error(_) => format!("T{:02x}", SIGILL),
+        StopReason::Exited => "W00".to_string(),
+        _ => format!("T{:02x}", SIGTRAP),
+    };
+    reply.into_bytes()
+}
+
+/*
+ * Start running, first jumping to the address given with `s ADDR`/`c ADDR` if there's one. An
+ * address outside of RAM is an error rather than cut down to something that fits.
+ */
+fn resume_at(debugger: &mut Debugger, addr: &str, step: bool) -> Action {
+    if !addr.is_empty() {
+        let ram_size = debugger.emu().ram_size();
+        match parse_hex(addr).filter(|&addr| addr < ram_size) {
+            Some(addr) => debugger.emu_mut().set_pc(addr as u16),
+            None => return Action::Reply(b"E01".to_vec()),
+        }
+    }
+    Action::Resume { step }
+}
+
+// Value of register `n` in the byte order we send it in
+fn register_bytes(debugger: &Debugger, n: usize) -> Vec<u8> {
+    let emu = debugger.emu();
+    match n {
+        0..=15 => vec![emu.registers()[n]],
+        REG_I => emu.i_reg().to_be_bytes().to_vec(),
+        REG_PC => emu.pc().to_be_bytes().to_vec(),
+        REG_SP => vec![emu.stack().len() as u8],
+        REG_DT => vec![emu.delay_timer()],
+        _ => vec![emu.sound_timer()],
+    }
+}
+
+fn read_registers(debugger: &Debugger) -> Vec<u8> {
+    let bytes: Vec<u8> = (0..NUM_GDB_REGS)
+        .flat_map(|n| register_bytes(debugger, n))
+        .collect();
+    to_hex(&bytes).into_bytes()
+}
+
+fn read_register(debugger: &Debugger, args: &str) -> Result<Vec<u8>, ()> {
+    let n = parse_hex(args).ok_or(())?;
+    if n >= NUM_GDB_REGS {
+        return Err(());
+    }
+    Ok(to_hex(&register_bytes(debugger, n)).into_bytes())
+}
+
+/*
+ * Whether `bytes` can be written to register `n`: it has to be the register's size, and the
+ * stack pointer can't be set from outside (the stack contents would make no sense), so writing it
+ * is only accepted when the value doesn't change.
+ */
+fn check_register(debugger: &Debugger, n: usize, bytes: &[u8]) -> Result<(), ()> {
+    match n {
+        REG_SP if bytes != [debugger.emu().stack().len() as u8] => Err(()),
+        0..NUM_GDB_REGS if bytes.len() == register_bytes(debugger, n).len() => Ok(()),
+        _ => Err(()),
+    }
+}
+
+fn set_register(debugger: &mut Debugger, n: usize, bytes: &[u8]) -> Result<(), ()> {
+    check_register(debugger, n, bytes)?;
+    let word = || u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    let emu = debugger.emu_mut();
+    match n {
+        0..=15 => emu.set_register(n, bytes[0]),
+        REG_I => emu.set_i_reg(word()),
+        REG_PC => emu.set_pc(word()),
+        REG_DT => emu.set_delay_timer(bytes[0]),
+        REG_ST => emu.set_sound_timer(bytes[0]),
+        _ => (),
+    }
+    Ok(())
+}
+
+fn write_register(debugger: &mut Debugger, args: &str) -> Result<Vec<u8>, ()> {
+    let (n, value) = args.split_once('=').ok_or(())?;
+    let n = parse_hex(n).ok_or(())?;
+    set_register(debugger, n, &from_hex(value).ok_or(())?)?;
+    Ok(b"OK".to_vec())
+}
+
+// `G`: all the registers or none of them, so everything is checked before the first one is set
+fn write_registers(debugger: &mut Debugger, args: &str) -> Result<Vec<u8>, ()> {
+    let bytes = from_hex(args).ok_or(())?;
+    let mut values = Vec::with_capacity(NUM_GDB_REGS);
+    let mut offset = 0;
+    for n in 0..NUM_GDB_REGS {
+        let size = register_bytes(debugger, n).len();
+        let value = bytes.get(offset..offset + size).ok_or(())?;
+        check_register(debugger, n, value)?;
+        values.push(value);
+        offset += size;
+    }
+    for (n, value) in values.into_iter().enumerate() {
+        set_register(debugger, n, value)?;
+    }
+    Ok(b"OK".to_vec())
+}
+
+// `m addr,length`
+fn read_memory(debugger: &Debugger, args: &str) -> Result<Vec<u8>, ()> {
+    let (addr, len) = parse_range(args)?;
+    let data = debugger.emu().read_memory(addr, len).ok_or(())?;
+    Ok(to_hex(data).into_bytes())
+}
+
+// `M addr,length:XX...`
+fn write_memory_hex(debugger: &mut Debugger, args: &str) -> Result<Vec<u8>, ()> {
+    let (range, data) = args.split_once(':').ok_or(())?;
+    let (addr, len) = parse_range(range)?;
+    let data = from_hex(data).ok_or(())?;
+    write_memory(debugger, addr, len, &data)
+}
+
+// `X addr,length:bytes` with the data sent as raw (already unescaped) binary
+fn write_memory_binary(debugger: &mut Debugger, packet: &[u8]) -> Result<Vec<u8>, ()> {
+    let colon = packet.iter().position(|&b| b == b':').ok_or(())?;
+    let range = std::str::from_utf8(&packet[1..colon]).map_err(|_| ())?;
+    let (addr, len) = parse_range(range)?;
+    write_memory(debugger, addr, len, &packet[colon + 1..])
+}
+
+fn write_memory(
+    debugger: &mut Debugger,
+    addr: u16,
+    len: usize,
+    data: &[u8],
+) -> Result<Vec<u8>, ()> {
+    if data.len() != len || !debugger.emu_mut().write_memory(addr, data) {
+        return Err(());
+    }
+    Ok(b"OK".to_vec())
+}
+
+/*
+ * `Z type,addr,kind` sets and `z type,addr,kind` clears a breakpoint (type 0, hardware ones of
+ * type 1 are treated the same) or a write (2), read (3) or access (4) watchpoint. For watchpoints
+ * `kind` is the number of bytes to watch.
+ */
+fn set_breakpoint(debugger: &mut Debugger, args: &str, insert: bool) -> Result<Vec<u8>, ()> {
+    let mut fields = args.split(',');
+    let (Some(kind), Some(addr), Some(len)) = (fields.next(), fields.next(), fields.next()) else {
+        return Err(());
+    };
+    let addr = u16::try_from(parse_hex(addr).ok_or(())?).map_err(|_| ())?;
+    let len = parse_hex(len.split(';').next().unwrap_or(len)).ok_or(())?;
+
+    let watch = match kind {
+        "0" | "1" => {
+            if insert {
+                debugger.add_breakpoint(addr);
+            } else {
+                debugger.remove_breakpoint(addr);
+            }
+            return Ok(b"OK".to_vec());
+        }
+        "2" => WatchKind::Write,
+        "3" => WatchKind::Read,
+        "4" => WatchKind::Access,
+        _ => return Ok(Vec::new()),
+    };
+
+    let watchpoint = Watchpoint {
+        addr,
+        len: len.max(1),
+        kind: watch,
+    };
+    if insert {
+        debugger.add_watchpoint(watchpoint);
+    } else {
+        debugger.remove_watchpoint(watchpoint);
+    }
+    Ok(b"OK".to_vec())
+}
+
+// `offset,length` of `qXfer`: `m` and a chunk if there's more to read, `l` and the rest if not
+fn xfer(data: &[u8], range: &str) -> Result<Vec<u8>, ()> {
+    let (offset, len) = range.split_once(',').ok_or(())?;
+    let offset = parse_hex(offset).ok_or(())?.min(data.len());
+    let len = parse_hex(len).ok_or(())?;
+    let end = offset.saturating_add(len).min(data.len());
+
+    let mut reply = vec![if end < data.len() { b'm' } else { b'l' }];
+    reply.extend_from_slice(&data[offset..end]);
+    Ok(reply)
+}
+
+fn or_error(reply: Result<Vec<u8>, ()>) -> Vec<u8> {
+    reply.unwrap_or_else(|()| b"E01".to_vec())
+}
+
+// `addr,length`, both in hex
+fn parse_range(args: &str) -> Result<(u16, usize), ()> {
+    let (addr, len) = args.split_once(',').ok_or(())?;
+    let addr = u16::try_from(parse_hex(addr).ok_or(())?).map_err(|_| ())?;
+    Ok((addr, parse_hex(len).ok_or(())?))
+}
+
+fn parse_hex(s: &str) -> Option<usize> {
+    usize::from_str_radix(s, 16).ok()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
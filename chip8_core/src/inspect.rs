@@ -0,0 +1,143 @@
+use crate::{Emu, NUM_KEYS, NUM_REGS};
+
+/*
+ * Read and write access to the machine from the outside, for debuggers and tools. Normal
+ * frontends never need any of this: they only load a ROM, press keys and draw the screen.
+ *
+ * Every setter takes effect immediately. Writes to RAM go through `write_memory` so the
+ * predecode cache (see `decode_next`) never runs stale instructions.
+ */
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+    // The CPU read the bytes of an instruction to run it
+    Fetch,
+    // An instruction read data (`DXYN` sprites, `FX65`, `5XY3`, `F002`)
+    Read,
+    // An instruction wrote data (`FX33`, `FX55`, `5XY2`)
+    Write,
+}
+
+// `len` bytes of RAM starting at `addr`, touched by the last instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MemoryAccess {
+    pub kind: AccessKind,
+    pub addr: u16,
+    pub len: usize,
+}
+
+impl MemoryAccess {
+    // Whether any of the bytes from `start` to `start + len` were touched
+    pub fn overlaps(&self, start: u16, len: usize) -> bool {
+        let (a, b) = (self.addr as usize, start as usize);
+        a < b + len && b < a + self.len
+    }
+}
+
+impl Emu {
+    pub fn registers(&self) -> &[u8; NUM_REGS] {
+        &self.v_reg
+    }
+
+    // Panics if `x` isn't 0x0-0xF
+    pub fn set_register(&mut self, x: usize, value: u8) {
+        self.v_reg[x] = value;
+    }
+
+    pub fn i_reg(&self) -> u16 {
+        self.i_reg
+    }
+
+    pub fn set_i_reg(&mut self, value: u16) {
+        self.i_reg = value;
+    }
+
+    pub fn set_pc(&mut self, pc: u16) {
+        self.pc = pc;
+    }
+
+    // Return addresses currently on the stack, oldest first
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp as usize]
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt
+    }
+
+    pub fn set_delay_timer(&mut self, value: u8) {
+        self.dt = value;
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.st
+    }
+
+    pub fn set_sound_timer(&mut self, value: u8) {
+        self.st = value;
+    }
+
+    pub fn keys(&self) -> &[bool; NUM_KEYS] {
+        &self.keys
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    // Bitplanes selected with `FN01`
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    pub fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // `None` if part of the range is outside of RAM
+    pub fn read_memory(&self, addr: u16, len: usize) -> Option<&[u8]> {
+        let start = addr as usize;
+        self.ram.get(start..start.checked_add(len)?)
+    }
+
+    // Returns false (and writes nothing) if part of the range is outside of RAM
+    pub fn write_memory(&mut self, addr: u16, data: &[u8]) -> bool {
+        let start = addr as usize;
+        match self.ram.get_mut(start..start + data.len()) {
+            Some(target) => {
+                target.copy_from_slice(data);
+                self.invalidate_predecode(start, data.len());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /*
+     * With the access log on, every tick records which bytes of RAM the instruction fetched,
+     * read and wrote. Debuggers use it for watchpoints and coverage tools to tell code from
+     * data. It's off by default as it costs a little on every instruction.
+     */
+    pub fn set_access_log(&mut self, enabled: bool) {
+        self.accesses = enabled.then(Vec::new);
+    }
+
+    // Accesses made by the last `tick`, empty if the log is off
+    pub fn last_accesses(&self) -> &[MemoryAccess] {
+        self.accesses.as_deref().unwrap_or(&[])
+    }
+
+    pub(crate) fn record_access(&mut self, kind: AccessKind, addr: usize, len: usize) {
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.push(MemoryAccess {
+                kind,
+                addr: addr as u16,
+                len,
+            });
+        }
+    }
+}
@@ -0,0 +1,355 @@
+/*
+ * Every opcode the emulator understands, already split into its fields. Each opcode is decoded
+ * once into one of these and everything else works on the decoded form: `Emu::execute` runs it,
+ * and tools (the disassembler, the assembler, a debugger...) can ask "is this a jump and where
+ * to?" without repeating the bit twiddling.
+ *
+ * Register numbers (`x`, `y`) are 0x0-0xF, `nn` is an 8-bit immediate, `n` a 4-bit one and
+ * addresses are 12 bits, except for XO-CHIP's `F000 NNNN` which carries a full 16-bit address.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    // 0000
+    Nop,
+    // 00E0
+    ClearScreen,
+    // 00EE
+    Return,
+    // 00CN
+    ScrollDown(u8),
+    // 00DN
+    ScrollUp(u8),
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    LowRes,
+    // 00FF
+    HighRes,
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipIfEq { x: u8, nn: u8 },
+    // 4XNN
+    SkipIfNe { x: u8, nn: u8 },
+    // 5XY0
+    SkipIfRegEq { x: u8, y: u8 },
+    // 5XY2
+    SaveRange { x: u8, y: u8 },
+    // 5XY3
+    LoadRange { x: u8, y: u8 },
+    // 6XNN
+    SetImm { x: u8, nn: u8 },
+    // 7XNN
+    AddImm { x: u8, nn: u8 },
+    // 8XY0
+    SetReg { x: u8, y: u8 },
+    // 8XY1
+    Or { x: u8, y: u8 },
+    // 8XY2
+    And { x: u8, y: u8 },
+    // 8XY3
+    Xor { x: u8, y: u8 },
+    // 8XY4
+    Add { x: u8, y: u8 },
+    // 8XY5
+    Sub { x: u8, y: u8 },
+    // 8XY6
+    ShiftRight { x: u8, y: u8 },
+    // 8XY7
+    SubReversed { x: u8, y: u8 },
+    // 8XYE
+    ShiftLeft { x: u8, y: u8 },
+    // 9XY0
+    SkipIfRegNe { x: u8, y: u8 },
+    // ANNN
+    SetI(u16),
+    // BNNN (or BXNN, depending on the `jump_uses_vx` quirk)
+    JumpOffset { x: u8, nnn: u16 },
+    // CXNN
+    Random { x: u8, nn: u8 },
+    // DXYN
+    Draw { x: u8, y: u8, n: u8 },
+    // EX9E
+    SkipIfKey { x: u8 },
+    // EXA1
+    SkipIfNotKey { x: u8 },
+    // F000 NNNN
+    SetILong(u16),
+    // FN01
+    SelectPlanes(u8),
+    // F002
+    LoadAudio,
+    // FX07
+    GetDelay { x: u8 },
+    // FX0A
+    WaitKey { x: u8 },
+    // FX15
+    SetDelay { x: u8 },
+    // FX18
+    SetSound { x: u8 },
+    // FX1E
+    AddI { x: u8 },
+    // FX29
+    Font { x: u8 },
+    // FX30
+    BigFont { x: u8 },
+    // FX33
+    Bcd { x: u8 },
+    // FX3A
+    SetPitch { x: u8 },
+    // FX55
+    Store { x: u8 },
+    // FX65
+    Load { x: u8 },
+    // FX75
+    SaveFlags { x: u8 },
+    // FX85
+    LoadFlags { x: u8 },
+    // Anything else, usually data that happens to sit between the code
+    Unknown(u16),
+}
+
+impl Instruction {
+    /*
+     * Not the cleanest code, but we need each hex digit separately. From here, we can create a
+     * match statement where we can specify the patterns for all of our opcodes. Anything that
+     * doesn't match is kept as `Unknown`, and only becomes an error if the CPU tries to run it.
+     *
+     * `F000` is the first half of a four byte instruction, so on its own it decodes to
+     * `SetILong(0)`: use `decode_at` when the following word is available.
+     */
+    pub fn decode(op: u16) -> Instruction {
+        let digit1 = (op & 0xF000) >> 12;
+        let digit2 = (op & 0x0F00) >> 8;
+        let digit3 = (op & 0x00F0) >> 4;
+        let digit4 = op & 0x000F;
+
+        let x = digit2 as u8;
+        let y = digit3 as u8;
+        let n = digit4 as u8;
+        let nn = (op & 0xFF) as u8;
+        let nnn = op & 0xFFF;
+
+        match (digit1, digit2, digit3, digit4) {
+            (0, 0, 0, 0) => Instruction::Nop,
+            (0, 0, 0xE, 0) => Instruction::ClearScreen,
+            (0, 0, 0xE, 0xE) => Instruction::Return,
+            (0, 0, 0xC, _) => Instruction::ScrollDown(n),
+            (0, 0, 0xD, _) => Instruction::ScrollUp(n),
+            (0, 0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0, 0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0, 0, 0xF, 0xD) => Instruction::Exit,
+            (0, 0, 0xF, 0xE) => Instruction::LowRes,
+            (0, 0, 0xF, 0xF) => Instruction::HighRes,
+            (1, _, _, _) => Instruction::Jump(nnn),
+            (2, _, _, _) => Instruction::Call(nnn),
+            (3, _, _, _) => Instruction::SkipIfEq { x, nn },
+            (4, _, _, _) => Instruction::SkipIfNe { x, nn },
+            (5, _, _, 0) => Instruction::SkipIfRegEq { x, y },
+            (5, _, _, 2) => Instruction::SaveRange { x, y },
+            (5, _, _, 3) => Instruction::LoadRange { x, y },
+            (6, _, _, _) => Instruction::SetImm { x, nn },
+            (7, _, _, _) => Instruction::AddImm { x, nn },
+            (8, _, _, 0) => Instruction::SetReg { x, y },
+            (8, _, _, 1) => Instruction::Or { x, y },
+            (8, _, _, 2) => Instruction::And { x, y },
+            (8, _, _, 3) => Instruction::Xor { x, y },
+            (8, _, _, 4) => Instruction::Add { x, y },
+            (8, _, _, 5) => Instruction::Sub { x, y },
+            (8, _, _, 6) => Instruction::ShiftRight { x, y },
+            (8, _, _, 7) => Instruction::SubReversed { x, y },
+            (8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (9, _, _, 0) => Instruction::SkipIfRegNe { x, y },
+            (0xA, _, _, _) => Instruction::SetI(nnn),
+            (0xB, _, _, _) => Instruction::JumpOffset { x, nnn },
+            (0xC, _, _, _) => Instruction::Random { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 9, 0xE) => Instruction::SkipIfKey { x },
+            (0xE, _, 0xA, 1) => Instruction::SkipIfNotKey { x },
+            (0xF, 0, 0, 0) => Instruction::SetILong(0),
+            (0xF, _, 0, 1) => Instruction::SelectPlanes(x),
+            (0xF, 0, 0, 2) => Instruction::LoadAudio,
+            (0xF, _, 0, 7) => Instruction::GetDelay { x },
+            (0xF, _, 0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 1, 5) => Instruction::SetDelay { x },
+            (0xF, _, 1, 8) => Instruction::SetSound { x },
+            (0xF, _, 1, 0xE) => Instruction::AddI { x },
+            (0xF, _, 2, 9) => Instruction::Font { x },
+            (0xF, _, 3, 0) => Instruction::BigFont { x },
+            (0xF, _, 3, 3) => Instruction::Bcd { x },
+            (0xF, _, 3, 0xA) => Instruction::SetPitch { x },
+            (0xF, _, 5, 5) => Instruction::Store { x },
+            (0xF, _, 6, 5) => Instruction::Load { x },
+            (0xF, _, 7, 5) => Instruction::SaveFlags { x },
+            (0xF, _, 8, 5) => Instruction::LoadFlags { x },
+            (_, _, _, _) => Instruction::Unknown(op),
+        }
+    }
+
+    /*
+     * Decode the instruction starting at `addr` inside `memory`, reading the extra word of
+     * `F000 NNNN`. Returns `None` if the instruction doesn't fit in what's left of `memory`.
+     */
+    pub fn decode_at(memory: &[u8], addr: usize) -> Option<Instruction> {
+        let word = |at: usize| {
+            let bytes = memory.get(at..at.checked_add(2)?)?;
+            Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+        };
+
+        match Instruction::decode(word(addr)?) {
+            Instruction::SetILong(_) => Some(Instruction::SetILong(word(addr + 2)?)),
+            instruction => Some(instruction),
+        }
+    }
+
+    // Size in bytes, 4 for `F000 NNNN` and 2 for everything else
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetILong(_) => 4,
+            _ => 2,
+        }
+    }
+
+    // The opcode with its operands as letters (`8XY4`, `DXYN`), to group instructions by kind
+    pub fn pattern(&self) -> &'static str {
+        match self {
+            Instruction::Nop => "0000",
+            Instruction::ClearScreen => "00E0",
+            Instruction::Return => "00EE",
+            Instruction::ScrollDown(_) => "00CN",
+            Instruction::ScrollUp(_) => "00DN",
+            Instruction::ScrollRight => "00FB",
+            Instruction::ScrollLeft => "00FC",
+            Instruction::Exit => "00FD",
+            Instruction::LowRes => "00FE",
+            Instruction::HighRes => "00FF",
+            Instruction::Jump(_) => "1NNN",
+            Instruction::Call(_) => "2NNN",
+            Instruction::SkipIfEq { .. } => "3XNN",
+            Instruction::SkipIfNe { .. } => "4XNN",
+            Instruction::SkipIfRegEq { .. } => "5XY0",
+            Instruction::SaveRange { .. } => "5XY2",
+            Instruction::LoadRange { .. } => "5XY3",
+            Instruction::SetImm { .. } => "6XNN",
+            Instruction::AddImm { .. } => "7XNN",
+            Instruction::SetReg { .. } => "8XY0",
+            Instruction::Or { .. } => "8XY1",
+            Instruction::And { .. } => "8XY2",
+            Instruction::Xor { .. } => "8XY3",
+            Instruction::Add { .. } => "8XY4",
+            Instruction::Sub { .. } => "8XY5",
+            Instruction::ShiftRight { .. } => "8XY6",
+            Instruction::SubReversed { .. } => "8XY7",
+            Instruction::ShiftLeft { .. } => "8XYE",
+            Instruction::SkipIfRegNe { .. } => "9XY0",
+            Instruction::SetI(_) => "ANNN",
+            Instruction::JumpOffset { .. } => "BNNN",
+            Instruction::Random { .. } => "CXNN",
+            Instruction::Draw { .. } => "DXYN",
+            Instruction::SkipIfKey { .. } => "EX9E",
+            Instruction::SkipIfNotKey { .. } => "EXA1",
+            Instruction::SetILong(_) => "F000",
+            Instruction::SelectPlanes(_) => "FN01",
+            Instruction::LoadAudio => "F002",
+            Instruction::GetDelay { .. } => "FX07",
+            Instruction::WaitKey { .. } => "FX0A",
+            Instruction::SetDelay { .. } => "FX15",
+            Instruction::SetSound { .. } => "FX18",
+            Instruction::AddI { .. } => "FX1E",
+            Instruction::Font { .. } => "FX29",
+            Instruction::BigFont { .. } => "FX30",
+            Instruction::Bcd { .. } => "FX33",
+            Instruction::SetPitch { .. } => "FX3A",
+            Instruction::Store { .. } => "FX55",
+            Instruction::Load { .. } => "FX65",
+            Instruction::SaveFlags { .. } => "FX75",
+            Instruction::LoadFlags { .. } => "FX85",
+            Instruction::Unknown(_) => "????",
+        }
+    }
+
+    // Where a `1NNN` jump or a `2NNN` call goes. Computed jumps (`BNNN`) aren't known statically
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jump(addr) | Instruction::Call(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /*
+     * The opcode bytes for this instruction, the reverse of `decode_at`. The fields are masked to
+     * their size, so an out of range value (e.g. `x: 0x10`) doesn't spill into the other digits.
+     */
+    pub fn encode(&self) -> Vec<u8> {
+        let xy = |base: u16, x: u8, y: u8, n: u16| {
+            base | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | (n & 0xF)
+        };
+        let xnn = |base: u16, x: u8, nn: u8| base | ((x as u16 & 0xF) << 8) | nn as u16;
+
+        let op = match *self {
+            Instruction::Nop => 0x0000,
+            Instruction::ClearScreen => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            Instruction::ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::Jump(nnn) => 0x1000 | (nnn & 0xFFF),
+            Instruction::Call(nnn) => 0x2000 | (nnn & 0xFFF),
+            Instruction::SkipIfEq { x, nn } => xnn(0x3000, x, nn),
+            Instruction::SkipIfNe { x, nn } => xnn(0x4000, x, nn),
+            Instruction::SkipIfRegEq { x, y } => xy(0x5000, x, y, 0),
+            Instruction::SaveRange { x, y } => xy(0x5000, x, y, 2),
+            Instruction::LoadRange { x, y } => xy(0x5000, x, y, 3),
+            Instruction::SetImm { x, nn } => xnn(0x6000, x, nn),
+            Instruction::AddImm { x, nn } => xnn(0x7000, x, nn),
+            Instruction::SetReg { x, y } => xy(0x8000, x, y, 0),
+            Instruction::Or { x, y } => xy(0x8000, x, y, 1),
+            Instruction::And { x, y } => xy(0x8000, x, y, 2),
+            Instruction::Xor { x, y } => xy(0x8000, x, y, 3),
+            Instruction::Add { x, y } => xy(0x8000, x, y, 4),
+            Instruction::Sub { x, y } => xy(0x8000, x, y, 5),
+            Instruction::ShiftRight { x, y } => xy(0x8000, x, y, 6),
+            Instruction::SubReversed { x, y } => xy(0x8000, x, y, 7),
+            Instruction::ShiftLeft { x, y } => xy(0x8000, x, y, 0xE),
+            Instruction::SkipIfRegNe { x, y } => xy(0x9000, x, y, 0),
+            Instruction::SetI(nnn) => 0xA000 | (nnn & 0xFFF),
+            // X is just the top digit of NNN, so `nnn` alone decides the opcode
+            Instruction::JumpOffset { nnn, .. } => 0xB000 | (nnn & 0xFFF),
+            Instruction::Random { x, nn } => xnn(0xC000, x, nn),
+            Instruction::Draw { x, y, n } => xy(0xD000, x, y, n as u16),
+            Instruction::SkipIfKey { x } => xnn(0xE000, x, 0x9E),
+            Instruction::SkipIfNotKey { x } => xnn(0xE000, x, 0xA1),
+            Instruction::SetILong(nnnn) => {
+                let [hi, lo] = nnnn.to_be_bytes();
+                return vec![0xF0, 0x00, hi, lo];
+            }
+            Instruction::SelectPlanes(n) => xnn(0xF000, n, 0x01),
+            Instruction::LoadAudio => 0xF002,
+            Instruction::GetDelay { x } => xnn(0xF000, x, 0x07),
+            Instruction::WaitKey { x } => xnn(0xF000, x, 0x0A),
+            Instruction::SetDelay { x } => xnn(0xF000, x, 0x15),
+            Instruction::SetSound { x } => xnn(0xF000, x, 0x18),
+            Instruction::AddI { x } => xnn(0xF000, x, 0x1E),
+            Instruction::Font { x } => xnn(0xF000, x, 0x29),
+            Instruction::BigFont { x } => xnn(0xF000, x, 0x30),
+            Instruction::Bcd { x } => xnn(0xF000, x, 0x33),
+            Instruction::SetPitch { x } => xnn(0xF000, x, 0x3A),
+            Instruction::Store { x } => xnn(0xF000, x, 0x55),
+            Instruction::Load { x } => xnn(0xF000, x, 0x65),
+            Instruction::SaveFlags { x } => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags { x } => xnn(0xF000, x, 0x85),
+            Instruction::Unknown(op) => op,
+        };
+        op.to_be_bytes().to_vec()
+    }
+}
@@ -1,16 +1,58 @@
-use std::usize;
-
-use rand::random;
+pub mod asm;
+pub mod coverage;
+pub mod debug;
+pub mod disasm;
+mod error;
+pub mod gdb;
+mod inspect;
+mod instruction;
+pub mod profile;
+mod quirks;
+mod rewind;
+mod rng;
+mod savestate;
+mod scheduler;
+mod timing;
+pub mod trace;
+
+pub use error::{EmuError, LoadError};
+pub use inspect::{AccessKind, MemoryAccess};
+pub use instruction::Instruction;
+pub use quirks::{MemoryIncrement, Platform, Quirks};
+pub use rewind::Rewind;
+pub use rng::{DEFAULT_SEED, RandomSource, XorShiftRng};
+pub use savestate::{STATE_VERSION, StateError};
+pub use scheduler::{Progress, Scheduler};
+pub use timing::{
+    Timing, VIP_CLOCK_HZ, VIP_CLOCKS_PER_CYCLE, VIP_CYCLES_PER_FRAME, VIP_INTERRUPT_CYCLES,
+};
 
+/*
+ * Size of the classic CHIP-8 display. SUPER-CHIP games can switch to a 128x64 high resolution
+ * mode at any time, so these are only the default: frontends should ask the `Emu` for the current
+ * size with `screen_width`/`screen_height` every frame.
+ */
 pub const SCREEN_WIDTH: usize = 64;
 pub const SCREEN_HEIGHT: usize = 32;
+pub const HIRES_SCREEN_WIDTH: usize = 128;
+pub const HIRES_SCREEN_HEIGHT: usize = 64;
 
-const RAM_SIZE: usize = 4096;
+pub const RAM_SIZE: usize = 4096;
+// XO-CHIP can address 64 KiB thanks to `F000 NNNN`
+pub const XO_RAM_SIZE: usize = 0x10000;
 const NUM_REGS: usize = 16;
 const STACK_SIZE: usize = 16;
 const NUM_KEYS: usize = 16;
-const START_ADDR: u16 = 0x200;
+pub const START_ADDR: u16 = 0x200;
+// The ETI-660 reserved more RAM for its interpreter, so its programs start at 0x600
+pub const ETI660_START_ADDR: u16 = 0x600;
 const FONT_SIZE: usize = 80;
+const BIG_FONT_ADDR: usize = FONT_SIZE;
+const BIG_FONT_SIZE: usize = 160;
+const NUM_RPL_FLAGS: usize = 16;
+const AUDIO_PATTERN_SIZE: usize = 16;
+// Pitch at which an XO-CHIP audio pattern plays at 4000 bits per second
+const DEFAULT_PITCH: u8 = 64;
 
 /*
  * The CHIP-8 screen display renders sprites which are stored in memory to the
@@ -55,16 +97,76 @@ const FONTSET: [u8; FONT_SIZE] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/*
+ * SUPER-CHIP adds a second, bigger font for the high resolution mode: 8 pixels wide and 10 rows
+ * tall, so each character takes 10 bytes. The original only had the digits 0-9, the letters A-F
+ * come from XO-CHIP. It is stored right after the small font, starting at 0x50.
+ */
+const BIG_FONTSET: [u8; BIG_FONT_SIZE] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+/*
+ * What happened during a single successful `tick`. Most instructions simply run and move on, but
+ * `FX0A` may leave the CPU parked on the same instruction until a key is pressed, and with the
+ * `display_wait` quirk `DXYN` does the same until the next frame starts. This is useful for a
+ * frontend (or a debugger) to know about.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Executed,
+    WaitingForKey,
+    WaitingForVblank,
+    // The ROM ran `00FD` (SUPER-CHIP exit). The CPU won't run anything else until `reset`
+    Exited,
+}
+
+/*
+ * Summary of a successful load: where the segment ended up and how many bytes of program RAM
+ * (from 0x200 to the end of memory) are still unused once every segment loaded so far is counted.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoadInfo {
+    pub addr: u16,
+    pub len: usize,
+    pub free: usize,
+}
+
 pub struct Emu {
     pc: u16, // program counter
     /*
-     * [u8; RAM_SIZE] esto es un array
+     * Vec<u8> es un array que vive en el heap y cuyo tamaño se decide al crearlo: 4096 bytes
+     * (RAM_SIZE) normalmente, o 64 KiB (XO_RAM_SIZE) para XO-CHIP. Una vez creado el Emu su
+     * tamaño ya no cambia.
+     */
+    ram: Vec<u8>,
+    /*
+     * The buffer is always big enough for the high resolution mode. Only the first
+     * `screen_width() * screen_height()` pixels are used, one row after another, so in low
+     * resolution it's laid out exactly as a 64x32 screen.
      *
-     * u8 -> es el tipo de cada elemento (unsigned int 8bit)
-     * RAM_SIZE -> numero de elementos del array (4096)
+     * XO-CHIP has two bitplanes, so each pixel is a small bitmask rather than a bool: bit 0 is
+     * the first plane and bit 1 the second, which gives four possible colours (0 to 3).
      */
-    ram: [u8; RAM_SIZE],
-    screen: [bool; SCREEN_WIDTH * SCREEN_HEIGHT],
+    screen: [u8; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+    hires: bool,
+    // Bitplanes affected by drawing, clearing and scrolling, selected with `FN01`
+    planes: u8,
     v_reg: [u8; NUM_REGS], // v registers
     i_reg: u16,            // index register
     sp: u16,               // stack pointer
@@ -73,6 +175,29 @@ pub struct Emu {
     keys: [bool; NUM_KEYS],
     dt: u8, // delay timer
     st: u8, // sound timer
+    // SUPER-CHIP "RPL user flags", saved with `FX75` and restored with `FX85`
+    rpl: [u8; NUM_RPL_FLAGS],
+    exited: bool,
+    // XO-CHIP audio: 128 1-bit samples loaded with `F002` and the pitch set with `FX3A`
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    // (start, length) of every block copied into RAM with the `load*` functions
+    segments: Vec<(u16, usize)>,
+    quirks: Quirks,
+    // Where `CXNN` gets its numbers from, see `rng.rs`
+    rng: Box<dyn RandomSource>,
+    // The generator's state when it was seeded or set, where `reset` starts it again
+    rng_start: Vec<u8>,
+    // Set by `tick_timers` at the start of every frame, cleared when `DXYN` draws with the
+    // `display_wait` quirk on
+    vblank: bool,
+    // Flat instructions or COSMAC VIP machine cycles, and the cycles run so far (see `timing.rs`)
+    timing: Timing,
+    cycles: u64,
+    // Decoded instruction for every address already run, when the predecode cache is enabled
+    predecode: Option<Vec<Option<Instruction>>>,
+    // RAM touched by the last instruction, when the access log is enabled (see `inspect.rs`)
+    accesses: Option<Vec<MemoryAccess>>,
 }
 
 /*
@@ -89,14 +214,39 @@ pub struct Emu {
  *
  * let mut emulator = Emu::new()
  *
+ * `new` usa los quirks por defecto. Para emular una maquina concreta se usa `for_platform`, que
+ * tambien elige el tamaño de la RAM, o `with_quirks` para unos quirks a medida:
+ *
+ * let mut emulator = Emu::for_platform(Platform::XoChip)
+ * let mut emulator = Emu::with_quirks(Platform::SuperChip.quirks())
+ *
  */
 
 impl Emu {
     pub fn new() -> Self {
+        Self::with_quirks(Quirks::default())
+    }
+
+    pub fn with_quirks(quirks: Quirks) -> Self {
+        Self::with_memory(quirks, RAM_SIZE)
+    }
+
+    pub fn for_platform(platform: Platform) -> Self {
+        Self::with_memory(platform.quirks(), platform.ram_size())
+    }
+
+    /*
+     * `ram_size` is clamped between the space needed by the interpreter area plus one instruction
+     * and the 64 KiB that a 16-bit I register can address.
+     */
+    pub fn with_memory(quirks: Quirks, ram_size: usize) -> Self {
+        let ram_size = ram_size.clamp(START_ADDR as usize + 2, XO_RAM_SIZE);
         let mut new_emu = Self {
             pc: START_ADDR,
-            ram: [0; RAM_SIZE],
-            screen: [false; SCREEN_WIDTH * SCREEN_HEIGHT],
+            ram: vec![0; ram_size],
+            screen: [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+            hires: false,
+            planes: 1,
             v_reg: [0; NUM_REGS],
             i_reg: 0,
             sp: 0,
@@ -104,6 +254,19 @@ impl Emu {
             keys: [false; NUM_KEYS],
             dt: 0,
             st: 0,
+            rpl: [0; NUM_RPL_FLAGS],
+            exited: false,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            segments: Vec::new(),
+            quirks,
+            rng: Box::new(XorShiftRng::default()),
+            rng_start: XorShiftRng::default().save_state(),
+            vblank: true,
+            timing: Timing::Instructions,
+            cycles: 0,
+            predecode: None,
+            accesses: None,
         };
 
         /*
@@ -120,6 +283,7 @@ impl Emu {
          * .. -> Todo el rango disponible
          */
         new_emu.ram[..FONT_SIZE].copy_from_slice(&FONTSET);
+        new_emu.ram[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE].copy_from_slice(&BIG_FONTSET);
 
         new_emu
     }
@@ -138,11 +302,18 @@ impl Emu {
      * Delay Timer y Sound Timer en 0
      * Volver a poner el FONT_SIZE y el FONTSET en el espacio especificado
      *
+     * Los RPL flags de SUPER-CHIP no se tocan: en la HP48 sobrevivian entre programas.
+     *
+     * El generador de `CXNN` vuelve a donde estaba al sembrarlo, asi que tras un reset el juego
+     * saca los mismos numeros que la primera vez.
+     *
      */
     pub fn reset(&mut self) {
         self.pc = START_ADDR;
-        self.ram = [0; RAM_SIZE];
-        self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+        self.ram.fill(0);
+        self.screen = [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT];
+        self.hires = false;
+        self.planes = 1;
         self.v_reg = [0; NUM_REGS];
         self.i_reg = 0;
         self.sp = 0;
@@ -150,13 +321,75 @@ impl Emu {
         self.keys = [false; NUM_KEYS];
         self.dt = 0;
         self.st = 0;
+        self.exited = false;
+        self.audio_pattern = None;
+        self.pitch = DEFAULT_PITCH;
+        self.segments.clear();
+        self.vblank = true;
+        self.cycles = 0;
         self.ram[..FONT_SIZE].copy_from_slice(&FONTSET);
+        self.ram[BIG_FONT_ADDR..BIG_FONT_ADDR + BIG_FONT_SIZE].copy_from_slice(&BIG_FONTSET);
+        self.rng.restore_state(&self.rng_start);
+        self.clear_predecode();
     }
 
     // Pass a pointer to our screen buffer array up to the frontend, where it can be used to render
-    // to the display
-    pub fn get_display(&self) -> &[bool] {
-        &self.screen
+    // to the display. It holds `screen_width() * screen_height()` pixels, row by row. Each pixel is
+    // a colour index from 0 to 3 (one bit per XO-CHIP bitplane), plain CHIP-8 only uses 0 and 1
+    pub fn get_display(&self) -> &[u8] {
+        &self.screen[..self.screen_width() * self.screen_height()]
+    }
+
+    pub fn screen_width(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_WIDTH
+        } else {
+            SCREEN_WIDTH
+        }
+    }
+
+    pub fn screen_height(&self) -> usize {
+        if self.hires {
+            HIRES_SCREEN_HEIGHT
+        } else {
+            SCREEN_HEIGHT
+        }
+    }
+
+    /*
+     * The RPL flags were stored in the HP48 calculator and survived between games, which some
+     * SUPER-CHIP games use for high scores. Frontends can read them when the game ends and give
+     * them back with `set_rpl_flags` the next time it runs.
+     */
+    pub fn rpl_flags(&self) -> &[u8; NUM_RPL_FLAGS] {
+        &self.rpl
+    }
+
+    pub fn set_rpl_flags(&mut self, flags: &[u8; NUM_RPL_FLAGS]) {
+        self.rpl = *flags;
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    /*
+     * XO-CHIP sound. Once a ROM runs `F002` the buzzer no longer plays a fixed tone: it loops over
+     * the 128 bits of the pattern (most significant bit of the first byte first) at
+     * `audio_sample_rate` bits per second while the sound timer is running. `None` means the ROM
+     * never loaded a pattern, so a plain beep should be used.
+     */
+    pub fn audio_pattern(&self) -> Option<&[u8; AUDIO_PATTERN_SIZE]> {
+        self.audio_pattern.as_ref()
+    }
+
+    pub fn audio_pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    // 4000 * 2^((pitch - 64) / 48), as defined by the XO-CHIP specification
+    pub fn audio_sample_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
     }
 
     /*
@@ -181,11 +414,71 @@ impl Emu {
      * This function copies all the values from our input `data` slice into RAM beginning at 0x200.
      * Remember that the first 512 bytes of RAM aren't to contain game data, and are empty for the
      * character sprite data we store there.
+     *
+     * Nothing is copied unless the whole ROM fits: an empty ROM or one larger than the 3584 bytes
+     * between 0x200 and the end of RAM gives back a `LoadError`.
      */
-    pub fn load(&mut self, data: &[u8]) {
-        let start = START_ADDR as usize;
-        let end = (START_ADDR as usize) + data.len();
+    pub fn load(&mut self, data: &[u8]) -> Result<LoadInfo, LoadError> {
+        self.load_program_at(START_ADDR, data)
+    }
+
+    /*
+     * Same as `load`, but for machines whose programs don't start at 0x200 (the ETI-660 uses
+     * 0x600). The ROM is copied to `addr` and the PC is moved there so execution starts at the
+     * first instruction of the program.
+     */
+    pub fn load_program_at(&mut self, addr: u16, data: &[u8]) -> Result<LoadInfo, LoadError> {
+        let info = self.load_segment(addr, data)?;
+        self.pc = addr;
+        Ok(info)
+    }
+
+    /*
+     * Copy an extra block of data (graphics, level maps...) to a fixed address without touching
+     * the PC. It can be called as many times as needed, after or before loading the program, as
+     * long as the segments don't overlap each other or the interpreter area.
+     */
+    pub fn load_segment(&mut self, addr: u16, data: &[u8]) -> Result<LoadInfo, LoadError> {
+        if data.is_empty() {
+            return Err(LoadError::Empty);
+        }
+        if addr < START_ADDR {
+            return Err(LoadError::ReservedAddress { addr });
+        }
+
+        let start = addr as usize;
+        let available = self.ram.len().saturating_sub(start);
+        if data.len() > available {
+            return Err(LoadError::TooLarge {
+                addr,
+                len: data.len(),
+                available,
+            });
+        }
+
+        let end = start + data.len();
+        for &(other, other_len) in &self.segments {
+            let other_start = other as usize;
+            if start < other_start + other_len && other_start < end {
+                return Err(LoadError::Overlap { addr, other });
+            }
+        }
+
         self.ram[start..end].copy_from_slice(data);
+        self.invalidate_predecode(start, data.len());
+        self.segments.push((addr, data.len()));
+
+        Ok(LoadInfo {
+            addr,
+            len: data.len(),
+            free: self.free_ram(),
+        })
+    }
+
+    // Bytes of program RAM (0x200 and up) not used by any loaded segment
+    pub fn free_ram(&self) -> usize {
+        let used: usize = self.segments.iter().map(|&(_, len)| len).sum();
+        self.ram.len() - START_ADDR as usize - used
     }
 
     /*
@@ -200,54 +493,184 @@ impl Emu {
      *
      * 4. Move the PC to the next instruction and repeat
      *
+     * A broken ROM can ask for things the machine can't do (an opcode that doesn't exist, a return
+     * with an empty stack, a read past the end of RAM...). Instead of panicking, those cases are
+     * reported as an `EmuError`. Every instruction checks everything it needs before touching any
+     * state, so on error we only have to move the PC back to the faulting instruction and the
+     * machine is left exactly as it was before the tick.
      */
-    pub fn tick(&mut self) {
-        // Fetch
-        let op = self.fetch();
+    pub fn tick(&mut self) -> Result<StepOutcome, EmuError> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
+
+        let addr = self.pc;
+        if let Some(accesses) = self.accesses.as_mut() {
+            accesses.clear();
+        }
 
-        // Decode & execute
-        self.execute(op);
+        // Fetch & decode, then execute
+        let result = self.decode_next(addr).and_then(|instruction| {
+            if self.timing == Timing::Instructions {
+                return self.execute(instruction, addr);
+            }
+            // The cost depends on the registers as they were before the instruction
+            let cost = self.vip_cost(instruction);
+            let outcome = self.execute(instruction, addr)?;
+            self.spend_vip_cycles(instruction, addr, outcome, cost);
+            Ok(outcome)
+        });
+        if result.is_err() {
+            self.pc = addr;
+        }
+        result
     }
 
-    fn execute(&mut self, op: u16) {
-        /*
-         * Not the cleanest code, but we need each hex digit separately. From here, we can create a
-         * match statement where we can specify the patterns for all of our opcodes:
-         */
-        let digit1 = (op & 0xF000) >> 12;
-        let digit2 = (op & 0x0F00) >> 8;
-        let digit3 = (op & 0x00F0) >> 4;
-        let digit4 = op & 0x000F;
+    // Address of the next instruction to be executed
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /*
+     * The random numbers for `CXNN` come from a seeded generator, so two runs with the same seed
+     * and the same input behave exactly the same. Every new `Emu` starts with `DEFAULT_SEED`;
+     * frontends that want a different game every time can seed it from the clock.
+     */
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.set_rng(Box::new(XorShiftRng::new(seed)));
+    }
+
+    // Replace the generator with any other source of randomness. `reset` goes back to its state now
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng_start = rng.save_state();
+        self.rng = rng;
+    }
+
+    pub fn rng_state(&self) -> Vec<u8> {
+        self.rng.save_state()
+    }
+
+    // Returns false (and leaves the generator alone) if the state doesn't belong to the current
+    // kind of random source
+    pub fn restore_rng_state(&mut self, state: &[u8]) -> bool {
+        self.rng.restore_state(state)
+    }
+
+    /*
+     * Make sure `len` bytes starting at `start` are inside RAM before an instruction reads or
+     * writes them. `addr` is the address of the instruction doing the access, used for reporting.
+     */
+    fn check_ram(&self, addr: u16, start: usize, len: usize) -> Result<(), EmuError> {
+        if start + len > self.ram.len() {
+            return Err(EmuError::MemoryOutOfRange {
+                addr,
+                target: start,
+                len,
+            });
+        }
+        Ok(())
+    }
+
+    // Look up the state of the key stored in a V register, rejecting anything above 0xF
+    fn key_state(&self, addr: u16, key: u8) -> Result<bool, EmuError> {
+        match self.keys.get(key as usize) {
+            Some(pressed) => Ok(*pressed),
+            None => Err(EmuError::InvalidKey { addr, key }),
+        }
+    }
 
+    fn execute(&mut self, instruction: Instruction, addr: u16) -> Result<StepOutcome, EmuError> {
         /*
-         * Rust `match` statement demands that all possible options be taken into account which is
-         * done with the `_` variable, which captures "everything else". Inside, we'll use
-         * `uninplemented!` macro to cause the program to panic if it reaches that point.
+         * Rust `match` statement demands that all possible options be taken into account. Opcodes
+         * that don't mean anything were decoded as `Instruction::Unknown`, and for those we return
+         * an `UnknownOpcode` error so a bad ROM is reported to the frontend instead of panicking.
          *
          * While a long `match` statement would certainly work for other architectures, it is
          * usually more common to implement instructions in their own functions, and either use a
          * lookup table or programmatically determine which function is correct. CHIP-8 is somewhat
-         * unusual because it stores instruction parameters into the opcode itself, meaning we need
-         * a lot of wild cards to match the instructions. Since there are a relatively small number
-         * of them, a `match` statement works well here.
-         *
+         * unusual because it stores instruction parameters into the opcode itself. Pulling them
+         * out is the job of `Instruction::decode`, so every arm here gets its parameters already
+         * separated. Since there are a relatively small number of instructions, a `match`
+         * statement works well here.
          */
-        match (digit1, digit2, digit3, digit4) {
+        match instruction {
             /*
              * // 0000 // | NOP
              *
              * No opcode. Do nothing. This may seem a silly one, but sometimes it's needed for
              * timing or alignment purposes.
              */
-            (0, 0, 0, 0) => return,
+            Instruction::Nop => {}
 
             /*
              * 00E0 - Clear Screen
              *
              * Clear the screen, which means we need to reset our screen buffer to be empty again
              */
-            (0, 0, 0xE, 0) => {
-                self.screen = [false; SCREEN_WIDTH * SCREEN_HEIGHT];
+            Instruction::ClearScreen => {
+                let planes = self.planes;
+                self.screen.iter_mut().for_each(|pixel| *pixel &= !planes);
+            }
+
+            /*
+             * // 00CN // | Scroll down N pixels (SUPER-CHIP)
+             *
+             * Move the whole display N rows down. The rows that come in at the top are blank and
+             * the ones pushed past the bottom are lost.
+             */
+            Instruction::ScrollDown(n) => {
+                self.scroll(0, n as isize);
+            }
+
+            /*
+             * // 00DN // | Scroll up N pixels (XO-CHIP)
+             */
+            Instruction::ScrollUp(n) => {
+                self.scroll(0, -(n as isize));
+            }
+
+            /*
+             * // 00FB // | Scroll right 4 pixels (SUPER-CHIP)
+             */
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+            }
+
+            /*
+             * // 00FC // | Scroll left 4 pixels (SUPER-CHIP)
+             */
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+            }
+
+            /*
+             * // 00FD // | Exit interpreter (SUPER-CHIP)
+             *
+             * The program is done. We leave the PC after this instruction and stop executing,
+             * every following `tick` reports `Exited` until the emulator is reset.
+             */
+            Instruction::Exit => {
+                self.exited = true;
+                return Ok(StepOutcome::Exited);
+            }
+
+            /*
+             * // 00FE // | Low resolution (SUPER-CHIP)
+             * // 00FF // | High resolution (SUPER-CHIP)
+             *
+             * Switch between the 64x32 and 128x64 display modes. The screen is cleared when the
+             * mode changes, as the old contents don't make sense at the new size.
+             */
+            Instruction::LowRes => {
+                self.set_hires(false);
+            }
+
+            Instruction::HighRes => {
+                self.set_hires(true);
             }
 
             /*
@@ -263,8 +686,8 @@ impl Emu {
              * also allows us to maintain return address for nested subroutines while ensuring they
              * are returned in the correct order.
              */
-            (0, 0, 0xE, 0xE) => {
-                let ret_addr = self.pop();
+            Instruction::Return => {
+                let ret_addr = self.pop(addr)?;
                 self.pc = ret_addr;
             }
 
@@ -284,8 +707,7 @@ impl Emu {
              * De los 16 bits del opcode, quedate solo con los ultimos 12 bits (los menos
              * significativos), porque 0xFFF en binario es: 0000 1111 1111 1111
              */
-            (1, _, _, _) => {
-                let nnn = op & 0xFFF;
+            Instruction::Jump(nnn) => {
                 self.pc = nnn;
             }
 
@@ -295,9 +717,8 @@ impl Emu {
              * The opposite of our 'Return from Subroutine' operation, we are going to add our
              * current PC to the stack, and then jump to the given address.
              */
-            (2, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.push(self.pc);
+            Instruction::Call(nnn) => {
+                self.push(addr, self.pc)?;
                 self.pc = nnn;
             }
 
@@ -315,13 +736,13 @@ impl Emu {
              * a variable, we will reuse it for our 'X' index, although cast to a `usize`, as Rust
              * requires all array index to be done with a `usize` variable. If that value stored in
              * that register equals `nn`, then we skip the next opcode, which is the same as
-             * skipping our PC ahead by two bytes
+             * skipping our PC ahead by two bytes (four if the next one is XO-CHIP's double-sized
+             * `F000 NNNN`, see `skip`)
              */
-            (3, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Instruction::SkipIfEq { x, nn } => {
+                let x = x as usize;
                 if self.v_reg[x] == nn {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
@@ -331,11 +752,10 @@ impl Emu {
              * This opcode is exactly the same as the previous, except we skip if the compared
              * values are not equal
              */
-            (4, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Instruction::SkipIfNe { x, nn } => {
+                let x = x as usize;
                 if self.v_reg[x] != nn {
-                    self.pc += 2
+                    self.skip();
                 }
             }
 
@@ -346,11 +766,40 @@ impl Emu {
              * V Register. You will also notice that the last significant digit is not used in the
              * operation. This opcode requires it to be 0
              */
-            (5, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SkipIfRegEq { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 if self.v_reg[x] == self.v_reg[y] {
-                    self.pc += 2;
+                    self.skip();
+                }
+            }
+
+            /*
+             * // 5XY2 // | Store VX - VY into I (XO-CHIP)
+             * // 5XY3 // | Load VX - VY from I (XO-CHIP)
+             *
+             * Like FX55/FX65 but for any range of registers, which can also go backwards (X > Y).
+             * I is never modified.
+             */
+            Instruction::SaveRange { x, y } => {
+                let regs = Self::register_range(x as usize, y as usize);
+                let i = self.i_reg as usize;
+                self.check_ram(addr, i, regs.len())?;
+                let len = regs.len();
+                for (offset, reg) in regs.into_iter().enumerate() {
+                    self.ram[i + offset] = self.v_reg[reg];
+                }
+                self.invalidate_predecode(i, len);
+                self.record_access(AccessKind::Write, i, len);
+            }
+
+            Instruction::LoadRange { x, y } => {
+                let regs = Self::register_range(x as usize, y as usize);
+                let i = self.i_reg as usize;
+                self.check_ram(addr, i, regs.len())?;
+                self.record_access(AccessKind::Read, i, regs.len());
+                for (offset, reg) in regs.into_iter().enumerate() {
+                    self.v_reg[reg] = self.ram[i + offset];
                 }
             }
 
@@ -359,9 +808,8 @@ impl Emu {
              *
              * Set the V Register specified by the second digit to the value given
              */
-            (6, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Instruction::SetImm { x, nn } => {
+                let x = x as usize;
                 self.v_reg[x] = nn;
             }
 
@@ -373,37 +821,39 @@ impl Emu {
              * operator. Note also that while CHIP-8 has a carry flag, it is not modified by this
              * operation.
              */
-            (7, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
+            Instruction::AddImm { x, nn } => {
+                let x = x as usize;
                 self.v_reg[x] = self.v_reg[x].wrapping_add(nn);
             }
 
             /*
              * // 8XY1 // | Bitwise operation OR
              */
-            (8, _, _, 1) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Or { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] |= self.v_reg[y];
+                self.logic_vf_reset();
             }
 
             /*
              * // 8XY2 // | Bitwise operation AND
              */
-            (8, _, _, 2) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::And { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] &= self.v_reg[y];
+                self.logic_vf_reset();
             }
 
             /*
              * // 8XY3 // | Bitwise operation XOR
              */
-            (8, _, _, 3) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Xor { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] ^= self.v_reg[y];
+                self.logic_vf_reset();
             }
 
             /*
@@ -421,9 +871,9 @@ impl Emu {
              * `overflowing_add` attribute, which will return a tuple of both the wrapped sum, as
              * well as a boolean of wether an overflow occured.
              */
-            (8, _, _, 4) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Add { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (new_vx, carry) = self.v_reg[x].overflowing_add(self.v_reg[y]);
                 let new_vf = if carry { 1 } else { 0 };
@@ -441,9 +891,9 @@ impl Emu {
              * underflow occurs, it is set to 0, and vice versa. The `overflowing_sub` method will
              * be of use to us here.
              */
-            (8, _, _, 5) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::Sub { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (new_vx, borrow) = self.v_reg[x].overflowing_sub(self.v_reg[y]);
                 let new_vf = if borrow { 0 } else { 1 };
@@ -465,11 +915,15 @@ impl Emu {
              *
              *  self.v_reg[x] >>= 1 -> todos los bits se mueven una posicion a la derecha, y el bit
              *  mas significativo se rellena con 0.
+             *
+             *  With the `shift_uses_vy` quirk the value shifted comes from VY instead, and the
+             *  result is still stored in VX.
              */
-            (8, _, _, 6) => {
-                let x = digit2 as usize;
-                let lsb = self.v_reg[x] & 1;
-                self.v_reg[x] >>= 1;
+            Instruction::ShiftRight { x, y } => {
+                let x = x as usize;
+                let src = self.shift_source(x, y as usize);
+                let lsb = src & 1;
+                self.v_reg[x] = src >> 1;
                 self.v_reg[0xF] = lsb;
             }
 
@@ -479,9 +933,9 @@ impl Emu {
              *  This operation works the same as the previous VX -= VY, but with the operands in
              *  the opposite direction
              */
-            (8, _, _, 7) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SubReversed { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 let (new_vx, borrow) = self.v_reg[y].overflowing_sub(self.v_reg[x]);
                 let new_vf = if borrow { 0 } else { 1 };
@@ -509,10 +963,11 @@ impl Emu {
              *  menos significativo se convierte en 0. El bit mas significativo se pierde (que es
              *  el que guardamos antes en msb)
              */
-            (8, _, _, 0xE) => {
-                let x = digit2 as usize;
-                let msb = (self.v_reg[x] >> 7) & 1;
-                self.v_reg[x] <<= 1;
+            Instruction::ShiftLeft { x, y } => {
+                let x = x as usize;
+                let src = self.shift_source(x, y as usize);
+                let msb = (src >> 7) & 1;
+                self.v_reg[x] = src << 1;
                 self.v_reg[0xF] = msb;
             }
 
@@ -520,13 +975,10 @@ impl Emu {
              * // 8XY0 // | VX = VY
              *
              * Like the `VX = NN` operation, but the source value is from the VY register
-             *
-             * Si esta instruccion va primero salta un warning de unreachable code para el resto de
-             * opcodes
              */
-            (8, _, _, _) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SetReg { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
                 self.v_reg[x] = self.v_reg[y];
             }
 
@@ -536,12 +988,12 @@ impl Emu {
              *  Skip the next line if VX != VY. This is the same code as the 5XY0 operation, but
              *  with an inequality
              */
-            (9, _, _, 0) => {
-                let x = digit2 as usize;
-                let y = digit3 as usize;
+            Instruction::SkipIfRegNe { x, y } => {
+                let x = x as usize;
+                let y = y as usize;
 
                 if self.v_reg[x] != self.v_reg[y] {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
@@ -552,8 +1004,7 @@ impl Emu {
              * several additional instructions, primarily as an address pointer to RAM. In this
              * case, we are simply setting it to the 0xNNN value encodede in this opcode
              */
-            (0xA, _, _, _) => {
-                let nnn = op & 0xFFF;
+            Instruction::SetI(nnn) => {
                 self.i_reg = nnn;
             }
 
@@ -563,23 +1014,30 @@ impl Emu {
              *  While previous instructions have used the V Register specified within the opcode,
              *  this instruction always uses the first V0 Register. This operations moves the PC to
              *  the sum of the value stored in V0 and the raw value 0xNNN supplied in the opcode.
+             *
+             *  CHIP-48 and SUPER-CHIP read it as BXNN instead: the register is VX, taken from the
+             *  second digit. That's the `jump_uses_vx` quirk.
              */
-            (0xB, _, _, _) => {
-                let nnn = op & 0xFFF;
-                self.pc = (self.v_reg[0] as u16) + nnn;
+            Instruction::JumpOffset { x, nnn } => {
+                let reg = if self.quirks.jump_uses_vx {
+                    x as usize
+                } else {
+                    0
+                };
+                self.pc = (self.v_reg[reg] as u16) + nnn;
             }
 
             /*
              *  // CXNN // | VX = rand() & NN
              *
              *  This opcode is CHIP-8's random number generation, with a slight twist, in that the
-             *  random number is then AND'd with the lower 8-bits of the opcode. Install rand crate
-             *  in your project to implement this opcode.
+             *  random number is then AND'd with the lower 8-bits of the opcode. The number comes
+             *  from our own seedable generator (see `rng.rs`) instead of the rand crate, so runs
+             *  can be repeated exactly.
              */
-            (0xC, _, _, _) => {
-                let x = digit2 as usize;
-                let nn = (op & 0xFF) as u8;
-                let rng: u8 = random();
+            Instruction::Random { x, nn } => {
+                let x = x as usize;
+                let rng = self.rng.next_byte();
                 self.v_reg[x] = rng & nn;
             }
 
@@ -604,46 +1062,88 @@ impl Emu {
              *  versa, the `VF` is set (and cleared otherwise). With these things in mind, let's
              *  begin.
              *
+             *  With the `display_wait` quirk the draw has to wait for the start of a frame, so if
+             *  this frame already drew something we park the PC here until `tick_timers` runs.
+             *
+             *  The starting coordinates always wrap around the screen. What happens to the pixels
+             *  that fall off the right or bottom edge depends on the `clip_sprites` quirk.
+             *
+             *  SUPER-CHIP adds DXY0: with a height of 0 the sprite is 16x16 pixels, stored as 16
+             *  rows of two bytes each.
+             *
+             *  XO-CHIP draws the sprite on every bitplane selected with `FN01`. With both planes
+             *  selected the data for the first plane comes first, immediately followed by the data
+             *  for the second one. A collision on any plane sets VF.
              */
-            (0xD, _, _, _) => {
-                // Get the (x,y) coords for our sprite
-                let x_coord = self.v_reg[digit2 as usize] as u16;
-                let y_coord = self.v_reg[digit3 as usize] as u16;
+            Instruction::Draw { x, y, n } => {
+                if self.quirks.display_wait && !self.vblank {
+                    self.pc = addr;
+                    return Ok(StepOutcome::WaitingForVblank);
+                }
 
-                // The last digit determines how many rows high our sprite is
-                let num_rows = digit4;
+                let width = self.screen_width();
+                let height = self.screen_height();
+
+                // Get the (x,y) coords for our sprite
+                let x_coord = self.v_reg[x as usize] as usize % width;
+                let y_coord = self.v_reg[y as usize] as usize % height;
+
+                // The last digit determines how many rows high our sprite is, 0 means 16x16
+                let (num_rows, row_bytes) = if n == 0 { (16, 2) } else { (n as usize, 1) };
+                let sprite_len = num_rows * row_bytes;
+                let planes: Vec<u8> = [1, 2]
+                    .into_iter()
+                    .filter(|p| self.planes & p != 0)
+                    .collect();
+                self.check_ram(addr, self.i_reg as usize, sprite_len * planes.len())?;
+                self.record_access(
+                    AccessKind::Read,
+                    self.i_reg as usize,
+                    sprite_len * planes.len(),
+                );
+                self.vblank = false;
 
                 // Keep track if any pixel is flipped
                 let mut flipped = false;
 
-                // Iterate over each ROW of our sprite
-                for y_line in 0..num_rows {
-                    // Determine which memory address our row's data is stored
-                    let addr = self.i_reg + y_line as u16;
-                    let pixels = self.ram[addr as usize];
-
-                    // Iterate over each COLUMN in our row
-                    for x_line in 0..8 {
-                        // Use a mask to fetch current pixel's bit. Only flip if a 1
-                        if (pixels & (0b1000_0000 >> x_line)) != 0 {
-                            // Sprites should wrap around screen, so apply modulo
-                            let x = (x_coord + x_line) as usize % SCREEN_WIDTH;
-                            let y = (y_coord + y_line) as usize % SCREEN_HEIGHT;
-
-                            // Get our pixel's index for our 1D screen array
-                            let idx = x + SCREEN_WIDTH * y;
-                            // Check if we're about to flip the pixel and set
-                            flipped |= self.screen[idx];
-                            self.screen[idx] ^= true;
+                for (plane_idx, plane) in planes.into_iter().enumerate() {
+                    let sprite_addr = self.i_reg as usize + plane_idx * sprite_len;
+
+                    // Iterate over each ROW of our sprite
+                    for y_line in 0..num_rows {
+                        // Determine which memory address our row's data is stored
+                        let row_addr = sprite_addr + y_line * row_bytes;
+                        let pixels = if row_bytes == 2 {
+                            u16::from_be_bytes([self.ram[row_addr], self.ram[row_addr + 1]])
+                        } else {
+                            (self.ram[row_addr] as u16) << 8
+                        };
+
+                        // Iterate over each COLUMN in our row
+                        for x_line in 0..row_bytes * 8 {
+                            // Use a mask to fetch current pixel's bit. Only flip if a 1
+                            if (pixels & (0x8000 >> x_line)) != 0 {
+                                let x = x_coord + x_line;
+                                let y = y_coord + y_line;
+                                if self.quirks.clip_sprites && (x >= width || y >= height) {
+                                    continue;
+                                }
+
+                                // Otherwise sprites wrap around screen, so apply modulo
+                                let x = x % width;
+                                let y = y % height;
+
+                                // Get our pixel's index for our 1D screen array
+                                let idx = x + width * y;
+                                // Check if we're about to flip the pixel and set
+                                flipped |= self.screen[idx] & plane != 0;
+                                self.screen[idx] ^= plane;
+                            }
                         }
                     }
                 }
 
-                if flipped {
-                    self.v_reg[0xF] = 1;
-                } else {
-                    self.v_reg[0xF] = 0;
-                }
+                self.v_reg[0xF] = if flipped { 1 } else { 0 };
             }
 
             /*
@@ -653,12 +1153,12 @@ impl Emu {
              *  mentioned that there are 16 possible keys numbered 0 to 0xF. This instruction
              *  checks if the index stored in VX is pressed, and if so, skips the next instruction.
              */
-            (0xE, _, 9, 0xE) => {
-                let x = digit2 as usize;
+            Instruction::SkipIfKey { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x];
-                let key = self.keys[vx as usize];
+                let key = self.key_state(addr, vx)?;
                 if key {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
@@ -668,15 +1168,50 @@ impl Emu {
              *  Same as the previous instruction, however this time the next instruction is skipped
              *  if the key in question is not being pressed
              */
-            (0xE, _, 0xA, 1) => {
-                let x = digit2 as usize;
+            Instruction::SkipIfNotKey { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x];
-                let key = self.keys[vx as usize];
+                let key = self.key_state(addr, vx)?;
                 if !key {
-                    self.pc += 2;
+                    self.skip();
                 }
             }
 
+            /*
+             *  // F000 NNNN // | I = NNNN (XO-CHIP)
+             *
+             *  The only instruction that takes four bytes: the 16-bit address in the next word is
+             *  loaded into I, so it can point anywhere in the 64 KiB of XO-CHIP memory. The second
+             *  word was already read when decoding (see `decode_next`).
+             */
+            Instruction::SetILong(nnnn) => {
+                self.i_reg = nnnn;
+            }
+
+            /*
+             *  // FN01 // | Select bitplanes (XO-CHIP)
+             *
+             *  N is a bitmask: 1 draws on the first plane, 2 on the second, 3 on both and 0 on
+             *  none.
+             */
+            Instruction::SelectPlanes(n) => {
+                self.planes = n & 0b11;
+            }
+
+            /*
+             *  // F002 // | Load audio pattern (XO-CHIP)
+             *
+             *  Copy the 16 bytes at I into the audio pattern buffer.
+             */
+            Instruction::LoadAudio => {
+                let i = self.i_reg as usize;
+                self.check_ram(addr, i, AUDIO_PATTERN_SIZE)?;
+                self.record_access(AccessKind::Read, i, AUDIO_PATTERN_SIZE);
+                let mut pattern = [0; AUDIO_PATTERN_SIZE];
+                pattern.copy_from_slice(&self.ram[i..i + AUDIO_PATTERN_SIZE]);
+                self.audio_pattern = Some(pattern);
+            }
+
             /*
              *  // FX07 // | VX = DT
              *
@@ -685,8 +1220,8 @@ impl Emu {
              * in the Delay Timer for our game's timing purposes. This instruction does just that,
              * and stores the current value into one of the `V` Registers for us to use
              */
-            (0xF, _, 0, 7) => {
-                let x = digit2 as usize;
+            Instruction::GetDelay { x } => {
+                let x = x as usize;
                 self.v_reg[x] = self.dt;
             }
 
@@ -708,8 +1243,8 @@ impl Emu {
              *  key press code from ever running, causing this loop to never end. Perharps
              *  inefficient, but much simpler  than some sort of asynchronous checking.
              */
-            (0xF, _, 0, 0xA) => {
-                let x = digit2 as usize;
+            Instruction::WaitKey { x } => {
+                let x = x as usize;
                 let mut pressed = false;
                 for i in 0..self.keys.len() {
                     if self.keys[i] {
@@ -720,7 +1255,8 @@ impl Emu {
                 }
                 if !pressed {
                     // Redo opcode
-                    self.pc -= 2;
+                    self.pc = addr;
+                    return Ok(StepOutcome::WaitingForKey);
                 }
             }
 
@@ -731,8 +1267,8 @@ impl Emu {
              *  We need someway to reset the Delay Timer to a value, and this instrucction allows
              *  us to copy over a value from a `V Register` of our choosing.
              */
-            (0xF, _, 1, 5) => {
-                let x = digit2 as usize;
+            Instruction::SetDelay { x } => {
+                let x = x as usize;
                 self.dt = self.v_reg[x];
             }
 
@@ -742,8 +1278,8 @@ impl Emu {
              *  Almost the same exact same instruction as the previous, however this time we are
              *  going to store the value from VX into our `Sound Timer`.
              */
-            (0xF, _, 1, 8) => {
-                let x = digit2 as usize;
+            Instruction::SetSound { x } => {
+                let x = x as usize;
                 self.st = self.v_reg[x];
             }
 
@@ -756,8 +1292,8 @@ impl Emu {
              *  simply roll over back to 0, which we can accomplish with Rust's `wrapping_add`
              *  method.
              */
-            (0xF, _, 1, 0xE) => {
-                let x = digit2 as usize;
+            Instruction::AddI { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x] as u16;
                 self.i_reg = self.i_reg.wrapping_add(vx);
             }
@@ -788,12 +1324,24 @@ impl Emu {
              *  different RAM address, we could still follow this rule, however we'd have to apply
              *  an offset to where the block begins
              */
-            (0xF, _, 2, 9) => {
-                let x = digit2 as usize;
+            Instruction::Font { x } => {
+                let x = x as usize;
                 let c = self.v_reg[x] as u16;
                 self.i_reg = c * 5;
             }
 
+            /*
+             *  // FX30 // | Set I to Big Font Address (SUPER-CHIP)
+             *
+             *  Same idea as FX29, but pointing to the 10-byte high resolution characters, which
+             *  live right after the small ones.
+             */
+            Instruction::BigFont { x } => {
+                let x = x as usize;
+                let c = (self.v_reg[x] & 0xF) as u16;
+                self.i_reg = BIG_FONT_ADDR as u16 + c * 10;
+            }
+
             /*
              *  // FX33 // | BCD of VX
              *
@@ -819,8 +1367,8 @@ impl Emu {
              *  VX stores 8-bit numbers, which are range from 0 to 255, we are always going to end
              *  up with three bytes, even if some are zero
              */
-            (0xF, _, 3, 3) => {
-                let x = digit2 as usize;
+            Instruction::Bcd { x } => {
+                let x = x as usize;
                 let vx = self.v_reg[x] as f32;
 
                 // Fetch the hundreds digit by divigind by 100 and tossing the decimal
@@ -832,9 +1380,13 @@ impl Emu {
                 // Fetch the ones digit by tossing the hundreds and the tens
                 let ones = (vx % 10.0) as u8;
 
-                self.ram[self.i_reg as usize] = hundreds;
-                self.ram[(self.i_reg + 1) as usize] = tens;
-                self.ram[(self.i_reg + 2) as usize] = ones;
+                let i = self.i_reg as usize;
+                self.check_ram(addr, i, 3)?;
+                self.ram[i] = hundreds;
+                self.ram[i + 1] = tens;
+                self.ram[i + 2] = ones;
+                self.invalidate_predecode(i, 3);
+                self.record_access(AccessKind::Write, i, 3);
             }
 
             /*
@@ -844,27 +1396,203 @@ impl Emu {
              *  (inclusive) with the same range of values from RAM, beginning with the address in
              *  the I Register. This first one stores the values into RAM, while the next one will
              *  load them the opposite way
+             *
+             *  Whether I moves afterwards depends on the `memory_increment` quirk.
              */
-            (0xF, _, 5, 5) => {
-                let x = digit2 as usize;
+            Instruction::Store { x } => {
+                let x = x as usize;
                 let i = self.i_reg as usize;
-                for idx in 0..=x {
-                    self.ram[i + idx] = self.v_reg[idx];
-                }
+                self.check_ram(addr, i, x + 1)?;
+                self.ram[i..=i + x].copy_from_slice(&self.v_reg[..=x]);
+                self.invalidate_predecode(i, x + 1);
+                self.record_access(AccessKind::Write, i, x + 1);
+                self.memory_increment(x);
             }
 
             /*
              *  // FX65 // | Load I into V0 - VX
              */
-            (0xF, _, 6, 5) => {
-                let x = digit2 as usize;
+            Instruction::Load { x } => {
+                let x = x as usize;
                 let i = self.i_reg as usize;
-                for idx in 0..=x {
-                    self.v_reg[idx] = self.ram[i + idx];
-                }
+                self.check_ram(addr, i, x + 1)?;
+                self.v_reg[..=x].copy_from_slice(&self.ram[i..=i + x]);
+                self.record_access(AccessKind::Read, i, x + 1);
+                self.memory_increment(x);
+            }
+
+            /*
+             *  // FX3A // | Pitch = VX (XO-CHIP)
+             */
+            Instruction::SetPitch { x } => {
+                let x = x as usize;
+                self.pitch = self.v_reg[x];
+            }
+
+            /*
+             *  // FX75 // | Save V0 - VX to RPL flags (SUPER-CHIP)
+             *  // FX85 // | Load V0 - VX from RPL flags (SUPER-CHIP)
+             *
+             *  The HP48 only had 8 flags, XO-CHIP extended them to 16 so every register fits.
+             */
+            Instruction::SaveFlags { x } => {
+                let x = x as usize;
+                self.rpl[..=x].copy_from_slice(&self.v_reg[..=x]);
             }
 
-            (_, _, _, _) => unimplemented!("Uninplemented opcode: {}", op),
+            Instruction::LoadFlags { x } => {
+                let x = x as usize;
+                self.v_reg[..=x].copy_from_slice(&self.rpl[..=x]);
+            }
+
+            Instruction::Unknown(opcode) => return Err(EmuError::UnknownOpcode { addr, opcode }),
+        }
+
+        Ok(StepOutcome::Executed)
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.screen = [0; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT];
+    }
+
+    /*
+     * Move the selected bitplanes `dx` pixels right and `dy` pixels down (negative values go
+     * left/up) inside the visible area. Pixels pushed past the edge are lost and the gap left
+     * behind is blank. Planes that aren't selected stay where they are.
+     */
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let width = self.screen_width();
+        let height = self.screen_height();
+        let planes = self.planes;
+        let old = self.screen;
+
+        for y in 0..height {
+            for x in 0..width {
+                let src_x = x as isize - dx;
+                let src_y = y as isize - dy;
+                let moved = if (0..width as isize).contains(&src_x)
+                    && (0..height as isize).contains(&src_y)
+                {
+                    old[src_x as usize + width * src_y as usize] & planes
+                } else {
+                    0
+                };
+
+                let idx = x + width * y;
+                self.screen[idx] = (old[idx] & !planes) | moved;
+            }
+        }
+    }
+
+    /*
+     * Skip the next instruction. Normally that's two bytes, but XO-CHIP's `F000 NNNN` is four
+     * bytes long and has to be skipped as a whole, or the CPU would end up executing its address.
+     */
+    fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let next_is_long = pc + 1 < self.ram.len() && self.ram[pc] == 0xF0 && self.ram[pc + 1] == 0;
+        let len = if next_is_long { 4 } else { 2 };
+        self.pc = self.pc.wrapping_add(len);
+    }
+
+    // Registers touched by `5XY2`/`5XY3`, from X to Y in whichever direction they go
+    fn register_range(x: usize, y: usize) -> Vec<usize> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    // Value shifted by `8XY6`/`8XYE`: VX, or VY with the `shift_uses_vy` quirk
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.v_reg[y]
+        } else {
+            self.v_reg[x]
+        }
+    }
+
+    // `8XY1`/`8XY2`/`8XY3` clear VF on the VIP
+    fn logic_vf_reset(&mut self) {
+        if self.quirks.logic_resets_vf {
+            self.v_reg[0xF] = 0;
+        }
+    }
+
+    // Move I after `FX55`/`FX65` copied registers V0 to VX
+    fn memory_increment(&mut self, x: usize) {
+        let step = match self.quirks.memory_increment {
+            MemoryIncrement::Unchanged => 0,
+            MemoryIncrement::ByX => x as u16,
+            MemoryIncrement::ByXPlusOne => x as u16 + 1,
+        };
+        self.i_reg = self.i_reg.wrapping_add(step);
+    }
+
+    /*
+     * Read the instruction at the PC and move the PC past it. Most instructions are a single
+     * opcode, `F000 NNNN` also needs the word after it.
+     *
+     * With the predecode cache on, an address is only decoded the first time it runs, and any
+     * write to RAM forgets the instructions it could have changed. Games spend most of their
+     * time in a handful of loops, so this skips almost all the decoding work.
+     */
+    fn decode_next(&mut self, addr: u16) -> Result<Instruction, EmuError> {
+        let cached = self
+            .predecode
+            .as_ref()
+            .and_then(|cache| cache.get(addr as usize).copied().flatten());
+        if let Some(instruction) = cached {
+            self.pc = self.pc.wrapping_add(instruction.size() as u16);
+            self.record_access(AccessKind::Fetch, addr as usize, instruction.size());
+            return Ok(instruction);
+        }
+
+        let instruction = match Instruction::decode(self.fetch()?) {
+            Instruction::SetILong(_) => {
+                let pc = self.pc as usize;
+                self.check_ram(addr, pc, 2)?;
+                self.pc = self.pc.wrapping_add(2);
+                Instruction::SetILong(u16::from_be_bytes([self.ram[pc], self.ram[pc + 1]]))
+            }
+            instruction => instruction,
+        };
+
+        if let Some(cache) = self.predecode.as_mut() {
+            cache[addr as usize] = Some(instruction);
+        }
+        self.record_access(AccessKind::Fetch, addr as usize, instruction.size());
+        Ok(instruction)
+    }
+
+    /*
+     * Turn the predecode cache on or off. It's off by default: it makes long runs faster (e.g.
+     * headless test runs of millions of instructions) but costs a few bytes per byte of RAM.
+     */
+    pub fn set_predecode(&mut self, enabled: bool) {
+        self.predecode = enabled.then(|| vec![None; self.ram.len()]);
+    }
+
+    pub fn predecode_enabled(&self) -> bool {
+        self.predecode.is_some()
+    }
+
+    // Forget every cached instruction, e.g. after all of RAM was replaced
+    fn clear_predecode(&mut self) {
+        if self.predecode.is_some() {
+            self.set_predecode(true);
+        }
+    }
+
+    // `len` bytes of RAM starting at `start` were written
+    fn invalidate_predecode(&mut self, start: usize, len: usize) {
+        if let Some(cache) = self.predecode.as_mut() {
+            // `F000 NNNN` starting up to 3 bytes earlier includes the first byte written
+            let from = start.saturating_sub(3);
+            let to = (start + len).min(cache.len());
+            cache[from..to].fill(None);
         }
     }
 
@@ -907,34 +1635,44 @@ impl Emu {
      *
      * let result = a | b --> 1010 0010 1111 0000 = 0xA2F0
      */
-    fn fetch(&mut self) -> u16 {
-        let higher_byte = self.ram[self.pc as usize] as u16;
-        let lower_byte = self.ram[(self.pc + 1) as usize] as u16;
+    fn fetch(&mut self) -> Result<u16, EmuError> {
+        let pc = self.pc as usize;
+        self.check_ram(self.pc, pc, 2)?;
+        let higher_byte = self.ram[pc] as u16;
+        let lower_byte = self.ram[pc + 1] as u16;
         let op = (higher_byte << 8) | lower_byte;
-        self.pc += 2;
-        op
+        self.pc = self.pc.wrapping_add(2);
+        Ok(op)
     }
 
     /*
      * While the `tick` function operates once every CPU cycle, these timers are modified instead
      * once every frame, and thus need to be in a separate function. Their behavior is rather
-     * simple, every frame both decrease by one. If the Sound Timer is set to one, the system will
-     * emit a 'beep' noise. If the timers ever hit zero, they do not automatically reset, they will
-     * remain at zero until the game manually resets them to some value.
+     * simple, every frame both decrease by one. While the Sound Timer is above zero, the system
+     * emits a 'beep' noise. If the timers ever hit zero, they do not automatically reset, they
+     * will remain at zero until the game manually resets them to some value.
      *
+     * The core doesn't make any sound itself: the frontend asks `beeper_active` every frame and
+     * plays (or stops) a tone accordingly.
      */
     pub fn tick_timers(&mut self) {
+        // A new frame starts, `DXYN` can draw again
+        self.vblank = true;
+
         if self.dt > 0 {
             self.dt -= 1;
         }
 
         if self.st > 0 {
-            if self.st == 1 {
-                // BEEP
-            }
+            self.st -= 1;
         }
     }
 
+    // Whether the buzzer should be sounding right now
+    pub fn beeper_active(&self) -> bool {
+        self.st > 0
+    }
+
     /*
      * Push al stack:
      *
@@ -952,9 +1690,13 @@ impl Emu {
      * valor guardado
      *
      */
-    fn push(&mut self, val: u16) {
+    fn push(&mut self, addr: u16, val: u16) -> Result<(), EmuError> {
+        if self.sp as usize >= STACK_SIZE {
+            return Err(EmuError::StackOverflow { addr });
+        }
         self.stack[self.sp as usize] = val;
         self.sp += 1;
+        Ok(())
     }
 
     /*
@@ -969,9 +1711,21 @@ impl Emu {
      *
      * Devolvemos el u16. Importante a la hora de devolver valores en
      * Rust no ponemos `;` en el ultimo statement de la funcion
+     *
+     * Si el SP ya esta en 0 devolvemos `StackUnderflow` en vez de dejar que
+     * la resta haga panic.
      */
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self, addr: u16) -> Result<u16, EmuError> {
+        if self.sp == 0 {
+            return Err(EmuError::StackUnderflow { addr });
+        }
         self.sp -= 1;
-        self.stack[self.sp as usize]
+        Ok(self.stack[self.sp as usize])
+    }
+}
+
+impl Default for Emu {
+    fn default() -> Self {
+        Self::new()
     }
 }
@@ -0,0 +1,273 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+use crate::disasm::Syntax;
+use crate::trace::completed;
+use crate::{Emu, EmuError, Instruction, StepOutcome};
+
+/*
+ * Where a ROM spends its time, to know what's worth optimising. The profiler counts completed
+ * instructions (the only cost we have: the CPU runs a fixed number of them per frame), and for
+ * each one remembers:
+ *
+ * - its address and its kind of opcode (`DXYN`, `8XY4`...)
+ * - the subroutines on the stack when it ran. Each return address on `Emu::stack` is turned into
+ *   the subroutine it returns from by looking at the `2NNN` right before it, which gives both the
+ *   inclusive cost of a subroutine (everything run while it was on the stack, callees included)
+ *   and its exclusive cost (only its own instructions), and the folded stacks for flame graphs
+ * - the backward jumps, which close the loops. A loop costs everything run between its target
+ *   and the jump back. Jumps to the start of a subroutine are tail calls rather than loops and
+ *   are left out
+ *
+ * Like the tracer it sits between the frontend and `Emu::tick`.
+ */
+#[derive(Default)]
+pub struct Profiler {
+    total: u64,
+    hits: HashMap<u16, u64>,
+    // Last instruction seen at each address, to print the report
+    instructions: HashMap<u16, Instruction>,
+    classes: HashMap<&'static str, u64>,
+    calls: HashMap<u16, u64>,
+    // Instructions run under each chain of subroutines, outermost first
+    stacks: HashMap<Vec<u16>, u64>,
+    // Times each backward jump was taken, by (target, jump address)
+    loops: HashMap<(u16, u16), u64>,
+    // The stack as of the last instruction: return addresses and the subroutines they belong to
+    returns: Vec<u16>,
+    frames: Vec<u16>,
+    // Instructions run under `frames` not yet added to `stacks`
+    pending: u64,
+}
+
+// Name of the code that isn't in any subroutine, in reports and folded stacks
+const TOP_LEVEL: &str = "top";
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn tick(&mut self, emu: &mut Emu) -> Result<StepOutcome, EmuError> {
+        self.tick_with(emu, Emu::tick)
+    }
+
+    // Same as `tick`, with `step` running the instruction (see `Tracer::tick_with`)
+    pub fn tick_with(
+        &mut self,
+        emu: &mut Emu,
+        step: impl FnOnce(&mut Emu) -> Result<StepOutcome, EmuError>,
+    ) -> Result<StepOutcome, EmuError> {
+        let pc = emu.pc();
+        let exited = emu.has_exited();
+        let instruction = Instruction::decode_at(emu.ram(), pc as usize);
+        self.sync_frames(emu);
+
+        let outcome = step(emu)?;
+        if let (true, Some(instruction)) = (completed(outcome, exited), instruction) {
+            self.total += 1;
+            self.pending += 1;
+            *self.hits.entry(pc).or_default() += 1;
+            *self.classes.entry(instruction.pattern()).or_default() += 1;
+            self.instructions.insert(pc, instruction);
+            match instruction {
+                Instruction::Call(addr) => *self.calls.entry(addr).or_default() += 1,
+                Instruction::Jump(_) | Instruction::JumpOffset { .. } if emu.pc() <= pc => {
+                    *self.loops.entry((emu.pc(), pc)).or_default() += 1
+                }
+                _ => (),
+            }
+        }
+        Ok(outcome)
+    }
+
+    // Instructions profiled so far
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    // Bring `frames` up to date with the stack, keeping the frames that didn't change
+    fn sync_frames(&mut self, emu: &Emu) {
+        let stack = emu.stack();
+        let same = self
+            .returns
+            .iter()
+            .zip(stack)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if same == self.returns.len() && same == stack.len() {
+            return;
+        }
+
+        self.flush();
+        self.returns.truncate(same);
+        self.frames.truncate(same);
+        for &ret in &stack[same..] {
+            self.returns.push(ret);
+            self.frames.push(call_target(emu.ram(), ret));
+        }
+    }
+
+    fn flush(&mut self) {
+        if self.pending > 0 {
+            *self.stacks.entry(self.frames.clone()).or_default() += self.pending;
+            self.pending = 0;
+        }
+    }
+
+    // `stacks` including the instructions run since the stack last changed
+    fn all_stacks(&self) -> HashMap<Vec<u16>, u64> {
+        let mut stacks = self.stacks.clone();
+        if self.pending > 0 {
+            *stacks.entry(self.frames.clone()).or_default() += self.pending;
+        }
+        stacks
+    }
+
+    /*
+     * A plain text report with the hottest addresses, opcode kinds, subroutines and loops, each
+     * sorted by cost and cut to the first `limit` rows. `labels` (e.g. from the assembler) name
+     * addresses in the disassembly and the subroutines, which are `sub_2A4` otherwise.
+     */
+    pub fn report(&self, labels: &BTreeMap<u16, String>, limit: usize) -> String {
+        let mut out = String::new();
+        let percent = |count: u64| 100.0 * count as f64 / self.total.max(1) as f64;
+        let _ = writeln!(out, "Profile of {} instructions", self.total);
+
+        let _ = writeln!(
+            out,
+            "\nHottest instructions\n     count      %  addr   instruction"
+        );
+        for (addr, count) in sorted(&self.hits).into_iter().take(limit) {
+            let text = self.instructions[&addr].format(Syntax::Cowgod, labels);
+            let label = labels.get(&addr).map(|l| format!("{}: ", l));
+            let _ = writeln!(
+                out,
+                "{:>10} {:>5.1}%  {:04X}   {}{}",
+                count,
+                percent(count),
+                addr,
+                label.unwrap_or_default(),
+                text
+            );
+        }
+
+        let _ = writeln!(out, "\nOpcodes\n     count      %  opcode");
+        for (class, count) in sorted(&self.classes).into_iter().take(limit) {
+            let _ = writeln!(out, "{:>10} {:>5.1}%  {}", count, percent(count), class);
+        }
+
+        let mut inclusive: HashMap<Option<u16>, u64> = HashMap::new();
+        let mut exclusive: HashMap<Option<u16>, u64> = HashMap::new();
+        for (frames, count) in self.all_stacks() {
+            *exclusive.entry(frames.last().copied()).or_default() += count;
+            *inclusive.entry(None).or_default() += count;
+            // Recursive subroutines appear more than once but only count once
+            let mut seen = Vec::with_capacity(frames.len());
+            for &frame in &frames {
+                if !seen.contains(&frame) {
+                    seen.push(frame);
+                    *inclusive.entry(Some(frame)).or_default() += count;
+                }
+            }
+        }
+        let _ = writeln!(
+            out,
+            "\nSubroutines\n inclusive      %  exclusive      %     calls  subroutine"
+        );
+        for (frame, count) in sorted(&inclusive).into_iter().take(limit) {
+            let own = exclusive.get(&frame).copied().unwrap_or_default();
+            let calls = frame.and_then(|addr| self.calls.get(&addr)).copied();
+            let _ = writeln!(
+                out,
+                "{:>10} {:>5.1}% {:>10} {:>5.1}% {:>9}  {}",
+                count,
+                percent(count),
+                own,
+                percent(own),
+                calls.map_or("-".to_string(), |calls| calls.to_string()),
+                frame_name(frame, labels)
+            );
+        }
+
+        let mut loops: Vec<_> = self
+            .loops
+            .iter()
+            .filter(|((start, _), _)| !self.calls.contains_key(start))
+            .map(|(&(start, end), &iterations)| {
+                let cost: u64 = (start..=end).filter_map(|addr| self.hits.get(&addr)).sum();
+                (cost, iterations, start, end)
+            })
+            .collect();
+        loops.sort_by(|a, b| b.cmp(a));
+        let _ = writeln!(out, "\nLoops\n      cost      % iterations  range");
+        for (cost, iterations, start, end) in loops.into_iter().take(limit) {
+            let label = labels.get(&start).map(|l| format!(" ({})", l));
+            let _ = writeln!(
+                out,
+                "{:>10} {:>5.1}% {:>10}  {:04X}-{:04X}{}",
+                cost,
+                percent(cost),
+                iterations,
+                start,
+                end,
+                label.unwrap_or_default()
+            );
+        }
+        out
+    }
+
+    /*
+     * The stacks in the "folded" format of flamegraph.pl and most flame graph viewers: one line
+     * per chain of subroutines, `top;sub_2A4;sub_300 1234`, with the instructions run there.
+     */
+    pub fn write_folded(
+        &self,
+        out: &mut impl Write,
+        labels: &BTreeMap<u16, String>,
+    ) -> io::Result<()> {
+        let mut stacks: Vec<_> = self.all_stacks().into_iter().collect();
+        stacks.sort();
+        for (frames, count) in stacks {
+            let mut line = TOP_LEVEL.to_string();
+            for frame in frames {
+                line.push(';');
+                line.push_str(&frame_name(Some(frame), labels));
+            }
+            writeln!(out, "{} {}", line, count)?;
+        }
+        Ok(())
+    }
+}
+
+/*
+ * The subroutine that returns to `ret`, from the `2NNN` right before it. If that's not a call
+ * (the ROM wrote the stack some other way) the return address itself stands for it.
+ */
+fn call_target(ram: &[u8], ret: u16) -> u16 {
+    match ret
+        .checked_sub(2)
+        .and_then(|addr| Instruction::decode_at(ram, addr as usize))
+    {
+        Some(Instruction::Call(addr)) => addr,
+        _ => ret,
+    }
+}
+
+fn frame_name(frame: Option<u16>, labels: &BTreeMap<u16, String>) -> String {
+    match frame {
+        None => TOP_LEVEL.to_string(),
+        Some(addr) => labels
+            .get(&addr)
+            .cloned()
+            .unwrap_or_else(|| format!("sub_{:03X}", addr)),
+    }
+}
+
+// Highest count first, then lowest key
+fn sorted<K: Copy + Ord>(counts: &HashMap<K, u64>) -> Vec<(K, u64)> {
+    let mut counts: Vec<(K, u64)> = counts.iter().map(|(&k, &count)| (k, count)).collect();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    counts
+}
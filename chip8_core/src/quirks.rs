@@ -0,0 +1,175 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::{RAM_SIZE, XO_RAM_SIZE};
+
+/*
+ * The original CHIP-8 specification leaves a few instructions ambiguous, and every interpreter
+ * written since the COSMAC VIP (CHIP-48, SUPER-CHIP, XO-CHIP...) picked its own interpretation.
+ * ROMs are written against one of them, so a game that looks fine with one set of behaviours can
+ * be completely broken with another. `Quirks` collects every one of those choices so they can be
+ * picked when creating the `Emu`.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    // `8XY6`/`8XYE` shift VY and store the result in VX (VIP) instead of shifting VX in place
+    pub shift_uses_vy: bool,
+
+    // What `FX55`/`FX65` do to I once they finish copying registers
+    pub memory_increment: MemoryIncrement,
+
+    // `BNNN` jumps to NNN + VX, with X being the highest nibble of NNN (CHIP-48), instead of V0
+    pub jump_uses_vx: bool,
+
+    // Sprites are cut at the screen edges instead of wrapping around to the other side. The
+    // starting coordinate always wraps, only the pixels that go past the border are affected
+    pub clip_sprites: bool,
+
+    // `8XY1`/`8XY2`/`8XY3` reset VF to 0 (side effect of how the VIP implemented them)
+    pub logic_resets_vf: bool,
+
+    // `DXYN` waits for the next 60Hz display interrupt before drawing, so there can only be one
+    // draw per frame
+    pub display_wait: bool,
+}
+
+/*
+ * How the I register changes after `FX55` (store V0-VX) and `FX65` (load V0-VX).
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryIncrement {
+    // I keeps its value (SUPER-CHIP)
+    Unchanged,
+    // I ends up X bytes further (CHIP-48, which had an off-by-one)
+    ByX,
+    // I ends up pointing right after the last byte copied (COSMAC VIP, XO-CHIP)
+    ByXPlusOne,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increment: MemoryIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        clip_sprites: true,
+        logic_resets_vf: true,
+        display_wait: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increment: MemoryIncrement::ByX,
+        jump_uses_vx: true,
+        clip_sprites: true,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        memory_increment: MemoryIncrement::Unchanged,
+        jump_uses_vx: true,
+        clip_sprites: true,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        memory_increment: MemoryIncrement::ByXPlusOne,
+        jump_uses_vx: false,
+        clip_sprites: false,
+        logic_resets_vf: false,
+        display_wait: false,
+    };
+}
+
+/*
+ * The behaviour this emulator always had before quirks were configurable: shifts in place, I left
+ * alone by `FX55`/`FX65`, `BNNN` using V0, sprites wrapping and VF untouched by logic operations.
+ * It doesn't match any single historic machine, but most modern CHIP-8 ROMs run fine with it.
+ */
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            shift_uses_vy: false,
+            memory_increment: MemoryIncrement::Unchanged,
+            jump_uses_vx: false,
+            clip_sprites: false,
+            logic_resets_vf: false,
+            display_wait: false,
+        }
+    }
+}
+
+/*
+ * Named machines whose quirks are well known, so frontends can offer "run this as a SUPER-CHIP
+ * game" instead of asking the user about every single flag.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Platform {
+    CosmacVip,
+    Chip48,
+    SuperChip,
+    XoChip,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 4] = [
+        Platform::CosmacVip,
+        Platform::Chip48,
+        Platform::SuperChip,
+        Platform::XoChip,
+    ];
+
+    pub fn quirks(self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks::COSMAC_VIP,
+            Platform::Chip48 => Quirks::CHIP_48,
+            Platform::SuperChip => Quirks::SUPER_CHIP,
+            Platform::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    // XO-CHIP is the only platform that can address more than 4 KiB
+    pub fn ram_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_RAM_SIZE,
+            _ => RAM_SIZE,
+        }
+    }
+
+    // Short name used to pick the platform from the command line or a config file
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.to_ascii_lowercase().replace(['-', '_'], "");
+        match name.as_str() {
+            "vip" | "cosmacvip" | "chip8" => Ok(Platform::CosmacVip),
+            "chip48" => Ok(Platform::Chip48),
+            "schip" | "superchip" => Ok(Platform::SuperChip),
+            "xochip" | "xo" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "unknown platform '{}' (expected one of: vip, chip48, schip, xochip)",
+                s
+            )),
+        }
+    }
+}
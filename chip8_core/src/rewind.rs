@@ -0,0 +1,189 @@
+use std::collections::VecDeque;
+
+use crate::{Emu, StateError};
+
+/*
+ * A bounded history of save states that lets a frontend step the machine back in time, one
+ * snapshot per call to `rewind`.
+ *
+ * Keeping a full save state for every frame would cost ~12 KiB per frame for CHIP-8 (more than
+ * 70 KiB for XO-CHIP), but from one frame to the next almost nothing changes: a few registers, a
+ * timer and some pixels. So only the most recent state is kept in full, and for every older one
+ * we store how to get there from the state after it:
+ *
+ * delta = older XOR newer
+ *
+ * which is all zeros except for the bytes that changed, and then squeezed with a simple run-length
+ * encoding of those zeros. Going back is just `older = newer XOR delta`. Dropping the oldest delta
+ * never affects the others, so the history can be trimmed from the front at any time.
+ *
+ * Memory use is bounded by both the number of snapshots and a byte budget for the deltas: once
+ * either limit is reached the oldest snapshots are forgotten.
+ */
+pub struct Rewind {
+    max_snapshots: usize,
+    max_bytes: usize,
+    latest: Option<Vec<u8>>,
+    // Oldest first. The last delta turns `latest` into the snapshot pushed right before it
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+}
+
+impl Rewind {
+    /*
+     * `max_snapshots` is how far back we can go, in calls to `push` (a frontend pushing once per
+     * frame at 60 fps keeps 30 seconds with 1800). `max_bytes` caps the memory used by the
+     * compressed history.
+     */
+    pub fn new(max_snapshots: usize, max_bytes: usize) -> Self {
+        Rewind {
+            max_snapshots: max_snapshots.max(1),
+            max_bytes,
+            latest: None,
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+        }
+    }
+
+    // Record the current state of the machine as the newest snapshot
+    pub fn push(&mut self, emu: &Emu) {
+        let state = emu.save_state();
+
+        if let Some(latest) = self.latest.take() {
+            if latest.len() == state.len() {
+                let delta = compress_delta(&latest, &state);
+                self.delta_bytes += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                // Different machine (e.g. another RAM size), the old history is useless
+                self.clear();
+            }
+        }
+        self.latest = Some(state);
+
+        while self.deltas.len() + 1 > self.max_snapshots
+            || (self.delta_bytes > self.max_bytes && !self.deltas.is_empty())
+        {
+            if let Some(oldest) = self.deltas.pop_front() {
+                self.delta_bytes -= oldest.len();
+            }
+        }
+    }
+
+    /*
+     * Go back one snapshot and load it into `emu`. Returns `Ok(false)` when there is nothing older
+     * left, in which case the machine is restored to the oldest snapshot we have (if any).
+     */
+    pub fn rewind(&mut self, emu: &mut Emu) -> Result<bool, StateError> {
+        let Some(latest) = self.latest.as_mut() else {
+            return Ok(false);
+        };
+
+        let stepped = match self.deltas.pop_back() {
+            Some(delta) => {
+                self.delta_bytes -= delta.len();
+                apply_delta(latest, &delta);
+                true
+            }
+            None => false,
+        };
+
+        emu.load_state(latest)?;
+        Ok(stepped)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.delta_bytes = 0;
+    }
+
+    // Number of snapshots we can still go back to, counting the newest one
+    pub fn len(&self) -> usize {
+        match self.latest {
+            Some(_) => self.deltas.len() + 1,
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.latest.is_none()
+    }
+
+    // Bytes currently used by the history (the newest full state plus every delta)
+    pub fn memory_usage(&self) -> usize {
+        self.latest.as_ref().map_or(0, Vec::len) + self.delta_bytes
+    }
+}
+
+/*
+ * The encoding is a list of (zeros, literals) pairs: `zeros` bytes that didn't change followed by
+ * `literals` bytes copied as they are. Both counts are stored as LEB128 varints, which keeps small
+ * runs to a single byte.
+ */
+fn compress_delta(older: &[u8], newer: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut i = 0;
+
+    while i < older.len() {
+        let zeros_start = i;
+        while i < older.len() && older[i] == newer[i] {
+            i += 1;
+        }
+        let literals_start = i;
+        while i < older.len() && older[i] != newer[i] {
+            i += 1;
+        }
+
+        write_varint(&mut out, literals_start - zeros_start);
+        write_varint(&mut out, i - literals_start);
+        out.extend(
+            older[literals_start..i]
+                .iter()
+                .zip(&newer[literals_start..i])
+                .map(|(a, b)| a ^ b),
+        );
+    }
+    out
+}
+
+fn apply_delta(state: &mut [u8], delta: &[u8]) {
+    let mut pos = 0;
+    let mut d = 0;
+
+    while d < delta.len() {
+        let zeros = read_varint(delta, &mut d);
+        let literals = read_varint(delta, &mut d);
+        pos += zeros;
+        for (byte, xor) in state[pos..pos + literals]
+            .iter_mut()
+            .zip(&delta[d..d + literals])
+        {
+            *byte ^= xor;
+        }
+        pos += literals;
+        d += literals;
+    }
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], pos: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = data[*pos];
+        *pos += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
@@ -0,0 +1,69 @@
+/*
+ * `CXNN` needs random numbers, but "random" makes two runs of the same ROM impossible to compare:
+ * regression tests, input replays and bug reports all need the exact same numbers every time. The
+ * emulator takes its randomness from any type implementing `RandomSource`, and the default one is
+ * a small seedable generator whose whole state can be saved and restored with the machine.
+ */
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+
+    // Everything needed to continue the same sequence later, as opaque bytes
+    fn save_state(&self) -> Vec<u8>;
+
+    // Go back to a state produced by `save_state`. Returns false if the bytes don't make sense
+    fn restore_state(&mut self, state: &[u8]) -> bool;
+}
+
+// Seed used when the frontend doesn't pick one
+pub const DEFAULT_SEED: u64 = 0x0C8_5EED;
+
+/*
+ * xorshift64* (Marsaglia's xorshift with a multiplication on the output). It isn't suitable for
+ * anything serious, but it's fast, has a single u64 of state and plenty of quality for games.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorShiftRng {
+    state: u64,
+}
+
+impl XorShiftRng {
+    pub fn new(seed: u64) -> Self {
+        // A state of 0 would only ever produce zeros
+        let state = if seed == 0 { DEFAULT_SEED } else { seed };
+        XorShiftRng { state }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+}
+
+impl Default for XorShiftRng {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+impl RandomSource for XorShiftRng {
+    fn next_byte(&mut self) -> u8 {
+        // The high bits are the best ones in xorshift*
+        (self.next_u64() >> 56) as u8
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        self.state.to_le_bytes().to_vec()
+    }
+
+    fn restore_state(&mut self, state: &[u8]) -> bool {
+        match <[u8; 8]>::try_from(state) {
+            Ok(bytes) if bytes != [0; 8] => {
+                self.state = u64::from_le_bytes(bytes);
+                true
+            }
+            _ => false,
+        }
+    }
+}
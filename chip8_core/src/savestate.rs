@@ -0,0 +1,402 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{
+    AUDIO_PATTERN_SIZE, Emu, HIRES_SCREEN_HEIGHT, HIRES_SCREEN_WIDTH, MemoryIncrement, NUM_KEYS,
+    NUM_REGS, NUM_RPL_FLAGS, Quirks, STACK_SIZE, START_ADDR, Timing, XO_RAM_SIZE,
+};
+
+/*
+ * Save states are a snapshot of the whole machine turned into bytes, so a game can be restored
+ * exactly where it was. The layout is:
+ *
+ * magic       4 bytes   "C8SS"
+ * version     u16       STATE_VERSION, bumped every time the payload layout changes
+ * length      u32       size of the payload
+ * payload     ...       the machine state, see `write_payload`
+ * checksum    u32       CRC-32 of everything above
+ *
+ * Every number is little endian. A file that doesn't start with the magic, comes from another
+ * version, is cut short or fails the checksum is rejected before anything in the `Emu` changes, so
+ * a bad file never leaves a half-restored machine behind.
+ */
+const MAGIC: &[u8; 4] = b"C8SS";
+pub const STATE_VERSION: u16 = 2;
+const HEADER_SIZE: usize = 4 + 2 + 4;
+const CHECKSUM_SIZE: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StateError {
+    // The data doesn't start with "C8SS", it's not a save state
+    BadMagic,
+    // Made by a build that uses a different payload layout
+    UnsupportedVersion(u16),
+    // The data ends before the state does
+    Truncated,
+    // The data was corrupted somewhere
+    ChecksumMismatch,
+    // The checksum is fine but a value makes no sense (says which one)
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a CHIP-8 save state"),
+            StateError::UnsupportedVersion(version) => write!(
+                f,
+                "save state version {} is not supported (expected {})",
+                version, STATE_VERSION
+            ),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::ChecksumMismatch => write!(f, "save state is corrupted (bad checksum)"),
+            StateError::Invalid(what) => write!(f, "save state has an invalid {}", what),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+impl Emu {
+    // Snapshot of the complete machine, to be given back to `load_state` later
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut payload = Vec::with_capacity(self.ram.len() + self.screen.len() + 256);
+        self.write_payload(&mut payload);
+
+        let mut out = Vec::with_capacity(HEADER_SIZE + payload.len() + CHECKSUM_SIZE);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+        out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(&payload);
+        let checksum = crc32(&out);
+        out.extend_from_slice(&checksum.to_le_bytes());
+        out
+    }
+
+    /*
+     * Restore a snapshot made by `save_state`. The whole state is parsed and validated first and
+     * only copied into the `Emu` at the very end, so on error nothing has changed.
+     */
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Err(StateError::BadMagic);
+        }
+        if data.len() < HEADER_SIZE + CHECKSUM_SIZE {
+            return Err(StateError::Truncated);
+        }
+
+        let version = u16::from_le_bytes([data[4], data[5]]);
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+
+        let len = u32::from_le_bytes([data[6], data[7], data[8], data[9]]) as usize;
+        let end = HEADER_SIZE.checked_add(len).ok_or(StateError::Truncated)?;
+        if data.len() < end + CHECKSUM_SIZE {
+            return Err(StateError::Truncated);
+        }
+
+        let stored = u32::from_le_bytes([data[end], data[end + 1], data[end + 2], data[end + 3]]);
+        if crc32(&data[..end]) != stored {
+            return Err(StateError::ChecksumMismatch);
+        }
+
+        let state = State::read(&mut Reader {
+            data: &data[HEADER_SIZE..end],
+        })?;
+
+        // The generator is the only part that can still refuse the state, so it goes first
+        if !self.rng.restore_state(&state.rng) {
+            return Err(StateError::Invalid("random generator state"));
+        }
+        state.apply(self);
+        Ok(())
+    }
+
+    fn write_payload(&self, out: &mut Vec<u8>) {
+        let q = &self.quirks;
+        out.push(q.shift_uses_vy as u8);
+        out.push(match q.memory_increment {
+            MemoryIncrement::Unchanged => 0,
+            MemoryIncrement::ByX => 1,
+            MemoryIncrement::ByXPlusOne => 2,
+        });
+        out.push(q.jump_uses_vx as u8);
+        out.push(q.clip_sprites as u8);
+        out.push(q.logic_resets_vf as u8);
+        out.push(q.display_wait as u8);
+        out.push(match self.timing {
+            Timing::Instructions => 0,
+            Timing::CosmacVip => 1,
+        });
+        out.extend_from_slice(&self.cycles.to_le_bytes());
+
+        out.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.ram);
+        out.extend_from_slice(&self.screen);
+        out.push(self.hires as u8);
+        out.push(self.planes);
+
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.v_reg);
+        out.extend_from_slice(&self.i_reg.to_le_bytes());
+        out.extend_from_slice(&self.sp.to_le_bytes());
+        for addr in &self.stack {
+            out.extend_from_slice(&addr.to_le_bytes());
+        }
+
+        let keys = self
+            .keys
+            .iter()
+            .enumerate()
+            .fold(0u16, |mask, (i, &pressed)| mask | ((pressed as u16) << i));
+        out.extend_from_slice(&keys.to_le_bytes());
+        out.push(self.dt);
+        out.push(self.st);
+
+        out.extend_from_slice(&self.rpl);
+        out.push(self.exited as u8);
+        out.push(self.vblank as u8);
+        match &self.audio_pattern {
+            Some(pattern) => {
+                out.push(1);
+                out.extend_from_slice(pattern);
+            }
+            None => {
+                out.push(0);
+                out.extend_from_slice(&[0; AUDIO_PATTERN_SIZE]);
+            }
+        }
+        out.push(self.pitch);
+
+        out.extend_from_slice(&(self.segments.len() as u16).to_le_bytes());
+        for &(addr, len) in &self.segments {
+            out.extend_from_slice(&addr.to_le_bytes());
+            out.extend_from_slice(&(len as u32).to_le_bytes());
+        }
+
+        let rng = self.rng.save_state();
+        out.extend_from_slice(&(rng.len() as u16).to_le_bytes());
+        out.extend_from_slice(&rng);
+    }
+}
+
+// Everything read back from a payload, waiting to be copied into the `Emu`
+struct State {
+    quirks: Quirks,
+    timing: Timing,
+    cycles: u64,
+    ram: Vec<u8>,
+    screen: [u8; HIRES_SCREEN_WIDTH * HIRES_SCREEN_HEIGHT],
+    hires: bool,
+    planes: u8,
+    pc: u16,
+    v_reg: [u8; NUM_REGS],
+    i_reg: u16,
+    sp: u16,
+    stack: [u16; STACK_SIZE],
+    keys: [bool; NUM_KEYS],
+    dt: u8,
+    st: u8,
+    rpl: [u8; NUM_RPL_FLAGS],
+    exited: bool,
+    vblank: bool,
+    audio_pattern: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    segments: Vec<(u16, usize)>,
+    rng: Vec<u8>,
+}
+
+impl State {
+    fn read(r: &mut Reader) -> Result<State, StateError> {
+        let quirks = Quirks {
+            shift_uses_vy: r.flag()?,
+            memory_increment: match r.u8()? {
+                0 => MemoryIncrement::Unchanged,
+                1 => MemoryIncrement::ByX,
+                2 => MemoryIncrement::ByXPlusOne,
+                _ => return Err(StateError::Invalid("memory increment quirk")),
+            },
+            jump_uses_vx: r.flag()?,
+            clip_sprites: r.flag()?,
+            logic_resets_vf: r.flag()?,
+            display_wait: r.flag()?,
+        };
+        let timing = match r.u8()? {
+            0 => Timing::Instructions,
+            1 => Timing::CosmacVip,
+            _ => return Err(StateError::Invalid("timing")),
+        };
+        let cycles = r.u64()?;
+
+        let ram_len = r.u32()? as usize;
+        if !(START_ADDR as usize + 2..=XO_RAM_SIZE).contains(&ram_len) {
+            return Err(StateError::Invalid("RAM size"));
+        }
+        let ram = r.bytes(ram_len)?.to_vec();
+        let screen = r.array()?;
+        let hires = r.flag()?;
+        let planes = r.u8()?;
+        if planes > 0b11 {
+            return Err(StateError::Invalid("bitplane selection"));
+        }
+
+        let pc = r.u16()?;
+        let v_reg = r.array()?;
+        let i_reg = r.u16()?;
+        let sp = r.u16()?;
+        if sp as usize > STACK_SIZE {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        let mut stack = [0; STACK_SIZE];
+        for addr in stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+
+        let key_mask = r.u16()?;
+        let mut keys = [false; NUM_KEYS];
+        for (i, key) in keys.iter_mut().enumerate() {
+            *key = key_mask & (1 << i) != 0;
+        }
+        let dt = r.u8()?;
+        let st = r.u8()?;
+
+        let rpl = r.array()?;
+        let exited = r.flag()?;
+        let vblank = r.flag()?;
+        let has_pattern = r.flag()?;
+        let pattern = r.array()?;
+        let audio_pattern = if has_pattern { Some(pattern) } else { None };
+        let pitch = r.u8()?;
+
+        let num_segments = r.u16()? as usize;
+        let mut segments = Vec::with_capacity(num_segments);
+        for _ in 0..num_segments {
+            let addr = r.u16()?;
+            let len = r.u32()? as usize;
+            if addr as usize + len > ram_len {
+                return Err(StateError::Invalid("loaded segment"));
+            }
+            segments.push((addr, len));
+        }
+
+        let rng_len = r.u16()? as usize;
+        let rng = r.bytes(rng_len)?.to_vec();
+
+        if !r.data.is_empty() {
+            return Err(StateError::Invalid("payload length"));
+        }
+
+        Ok(State {
+            quirks,
+            timing,
+            cycles,
+            ram,
+            screen,
+            hires,
+            planes,
+            pc,
+            v_reg,
+            i_reg,
+            sp,
+            stack,
+            keys,
+            dt,
+            st,
+            rpl,
+            exited,
+            vblank,
+            audio_pattern,
+            pitch,
+            segments,
+            rng,
+        })
+    }
+
+    fn apply(self, emu: &mut Emu) {
+        emu.quirks = self.quirks;
+        emu.timing = self.timing;
+        emu.cycles = self.cycles;
+        emu.ram = self.ram;
+        emu.clear_predecode();
+        emu.screen = self.screen;
+        emu.hires = self.hires;
+        emu.planes = self.planes;
+        emu.pc = self.pc;
+        emu.v_reg = self.v_reg;
+        emu.i_reg = self.i_reg;
+        emu.sp = self.sp;
+        emu.stack = self.stack;
+        emu.keys = self.keys;
+        emu.dt = self.dt;
+        emu.st = self.st;
+        emu.rpl = self.rpl;
+        emu.exited = self.exited;
+        emu.vblank = self.vblank;
+        emu.audio_pattern = self.audio_pattern;
+        emu.pitch = self.pitch;
+        emu.segments = self.segments;
+    }
+}
+
+// Walks through the payload, failing with `Truncated` if it runs out of bytes
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut out = [0; N];
+        out.copy_from_slice(self.bytes(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn flag(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Invalid("boolean flag")),
+        }
+    }
+
+    fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+/*
+ * CRC-32 (the one used by zip and PNG), computed bit by bit. It's slower than a table-driven
+ * version, but save states are only a few KiB and this keeps it dependency free.
+ */
+pub(crate) fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
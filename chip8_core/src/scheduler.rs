@@ -0,0 +1,184 @@
+use std::time::Duration;
+
+use crate::{
+    Emu, EmuError, StepOutcome, Timing, VIP_CLOCK_HZ, VIP_CLOCKS_PER_CYCLE, VIP_CYCLES_PER_FRAME,
+};
+
+/*
+ * Runs the machine in real time: the CPU at a set number of instructions per second and the
+ * timers at exactly 60 Hz, however often the frontend gets to call us. A frontend tied to vsync
+ * passes the time since its last frame to `run_for`, and the game runs at the same speed on a
+ * 60 Hz screen, a 144 Hz one or one that keeps dropping frames.
+ *
+ * Time is kept exactly with integers, so nothing drifts however long the game runs. The unit is
+ * 1/60 of a nanosecond, which makes a 60 Hz frame a whole number of units, and then each counter
+ * grows by `elapsed * rate` and pays a full second (`UNITS_PER_SECOND`) for every event:
+ *
+ *   cpu   += elapsed * instructions_per_second   one instruction per UNITS_PER_SECOND
+ *   timer += elapsed * 60                        one timer tick per UNITS_PER_SECOND
+ *
+ * The leftovers carry over to the next call. Instructions and timer ticks are interleaved in the
+ * order they'd happen on the real machine, so a game polling the delay timer sees it change in
+ * the middle of a long `run_for`.
+ *
+ * With `Timing::CosmacVip` the speed is the VIP's clock instead and the `Emu` ticks its own
+ * timers: `cpu` grows by `elapsed * VIP_CLOCK_HZ`, and instructions run for as long as the machine
+ * cycles paid for are ahead of those they cost. An instruction can cost more than what was left
+ * (a `DXYN` waiting for the next frame), and the debt is paid on the next call.
+ */
+pub struct Scheduler {
+    instructions_per_second: u64,
+    cpu: u128,
+    timer: u128,
+    // VIP machine cycles paid for but not run yet, negative when the last instruction overran
+    cycles_owed: i64,
+}
+
+// What a call to `run_for` or `run_frame` did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Progress {
+    // Ticks of the CPU, including those spent waiting for a key or the next frame
+    pub instructions: u64,
+    // Times the timers went down (60 per second)
+    pub frames: u64,
+    // The program ran `00FD`, nothing more will run until the `Emu` is reset
+    pub exited: bool,
+}
+
+const TIMER_HZ: u128 = 60;
+const NANOS_PER_SECOND: u128 = 1_000_000_000;
+const UNITS_PER_SECOND: u128 = NANOS_PER_SECOND * TIMER_HZ;
+const UNITS_PER_FRAME: u128 = UNITS_PER_SECOND / TIMER_HZ;
+
+impl Scheduler {
+    // 10 instructions per 60 Hz frame, what the frontends always ran
+    pub const DEFAULT_SPEED: u64 = 600;
+
+    /*
+     * After a long stall (the window was being dragged, the machine slept) we don't try to run
+     * everything that was missed at once: anything beyond this is forgotten and the game simply
+     * continues from where it was.
+     */
+    pub const MAX_CATCH_UP: Duration = Duration::from_millis(250);
+
+    pub fn new(instructions_per_second: u64) -> Self {
+        Scheduler {
+            instructions_per_second: instructions_per_second.max(1),
+            cpu: 0,
+            timer: 0,
+            cycles_owed: 0,
+        }
+    }
+
+    // Ignored with `Timing::CosmacVip`, where the speed is the VIP's
+    pub fn speed(&self) -> u64 {
+        self.instructions_per_second
+    }
+
+    // The part of an instruction already paid for is dropped, which nobody will notice
+    pub fn set_speed(&mut self, instructions_per_second: u64) {
+        self.instructions_per_second = instructions_per_second.max(1);
+        self.cpu = 0;
+    }
+
+    // Run whatever should have happened in `elapsed` of real time
+    pub fn run_for(&mut self, emu: &mut Emu, elapsed: Duration) -> Result<Progress, EmuError> {
+        self.run_for_each_frame(emu, elapsed, |_| ())
+    }
+
+    /*
+     * Like `run_for`, calling `on_frame` with the machine as it is at the end of every frame, right
+     * after its timer tick. For whatever has to happen once per emulated frame however many of
+     * them a call runs, like taking rewind snapshots.
+     */
+    pub fn run_for_each_frame(
+        &mut self,
+        emu: &mut Emu,
+        elapsed: Duration,
+        mut on_frame: impl FnMut(&Emu),
+    ) -> Result<Progress, EmuError> {
+        let nanos = elapsed.min(Self::MAX_CATCH_UP).as_nanos();
+        self.advance(emu, nanos * TIMER_HZ, &mut on_frame)
+    }
+
+    // Run exactly one 60 Hz frame: one timer tick and a frame's worth of instructions
+    pub fn run_frame(&mut self, emu: &mut Emu) -> Result<Progress, EmuError> {
+        self.advance(emu, UNITS_PER_FRAME, &mut |_| ())
+    }
+
+    fn advance(
+        &mut self,
+        emu: &mut Emu,
+        mut units: u128,
+        on_frame: &mut dyn FnMut(&Emu),
+    ) -> Result<Progress, EmuError> {
+        if emu.timing() == Timing::CosmacVip {
+            return self.advance_vip(emu, units, on_frame);
+        }
+        let mut progress = Progress::default();
+        let ips = self.instructions_per_second as u128;
+
+        while units > 0 {
+            // Up to the next timer tick, or all that's left if it comes later
+            let to_timer = (UNITS_PER_SECOND - self.timer).div_ceil(TIMER_HZ);
+            let step = units.min(to_timer);
+            units -= step;
+
+            self.cpu += step * ips;
+            while self.cpu >= UNITS_PER_SECOND {
+                self.cpu -= UNITS_PER_SECOND;
+                progress.instructions += 1;
+                if emu.tick()? == StepOutcome::Exited {
+                    progress.exited = true;
+                    return Ok(progress);
+                }
+            }
+
+            self.timer += step * TIMER_HZ;
+            if self.timer >= UNITS_PER_SECOND {
+                self.timer -= UNITS_PER_SECOND;
+                progress.frames += 1;
+                emu.tick_timers();
+                on_frame(emu);
+            }
+        }
+        Ok(progress)
+    }
+
+    fn advance_vip(
+        &mut self,
+        emu: &mut Emu,
+        units: u128,
+        on_frame: &mut dyn FnMut(&Emu),
+    ) -> Result<Progress, EmuError> {
+        let mut progress = Progress::default();
+        let units_per_cycle = VIP_CLOCKS_PER_CYCLE as u128 * UNITS_PER_SECOND;
+        self.cpu += units * VIP_CLOCK_HZ as u128;
+        self.cycles_owed += (self.cpu / units_per_cycle) as i64;
+        self.cpu %= units_per_cycle;
+
+        while self.cycles_owed > 0 {
+            let before = emu.cycles();
+            let outcome = emu.tick()?;
+            progress.instructions += 1;
+            // One interrupt, so one timer tick, at every frame boundary
+            let frames = emu.cycles() / VIP_CYCLES_PER_FRAME - before / VIP_CYCLES_PER_FRAME;
+            progress.frames += frames;
+            for _ in 0..frames {
+                on_frame(emu);
+            }
+            self.cycles_owed -= (emu.cycles() - before) as i64;
+            if outcome == StepOutcome::Exited {
+                progress.exited = true;
+                return Ok(progress);
+            }
+        }
+        Ok(progress)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SPEED)
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{Emu, Instruction, StepOutcome};
+
+/*
+ * How time passes for the CPU. By default it doesn't: every instruction is one `tick`, the
+ * frontend decides how many of them make a frame and calls `tick_timers` in between, like almost
+ * every CHIP-8 interpreter since the HP48.
+ *
+ * `CosmacVip` instead charges each instruction the machine cycles the original interpreter spent
+ * on it, and runs the VIP's display interrupt on its own schedule:
+ *
+ * - the 1802 runs at 1.7609 MHz and a machine cycle is 8 clocks, so a 60 Hz frame is
+ *   `VIP_CYCLES_PER_FRAME` machine cycles
+ * - at the start of every frame the interrupt fires: the timers go down and the display DMA
+ *   steals `VIP_INTERRUPT_CYCLES` from the interpreter, whatever it was doing
+ * - `DXYN` waits for that interrupt before drawing, which is why drawing is so slow on the VIP
+ * - `FX0A` polls the keypad over and over, each time as expensive as a cheap instruction
+ *
+ * The costs are approximations of the VIP interpreter's routines, close enough for games that
+ * count on it being slow but not to the cycle. Instructions the VIP didn't have (SUPER-CHIP and
+ * XO-CHIP) only pay for the dispatch.
+ *
+ * In this mode the timers belong to the `Emu`: the frontend must not call `tick_timers`, it runs
+ * instructions until `cycles` reaches where it should be (`Scheduler` does it).
+ */
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Timing {
+    #[default]
+    Instructions,
+    CosmacVip,
+}
+
+pub const VIP_CLOCK_HZ: u64 = 1_760_900;
+pub const VIP_CLOCKS_PER_CYCLE: u64 = 8;
+pub const VIP_CYCLES_PER_FRAME: u64 = 3668;
+// The interrupt routine and the DMA of the 128 lines of the display
+pub const VIP_INTERRUPT_CYCLES: u64 = 1068;
+
+// Fetch, decode and jump to the routine of the instruction, paid by all of them
+const DISPATCH: u64 = 40;
+// Extra for a skip that is taken, the interpreter adds 2 to its PC
+const SKIP_TAKEN: u64 = 4;
+
+impl Emu {
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    // Machine cycles since the `Emu` was created or reset, always 0 without `Timing::CosmacVip`
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+
+    // Machine cycles `instruction` will cost run now, not counting a taken skip
+    pub(crate) fn vip_cost(&self, instruction: Instruction) -> u64 {
+        let vx = |x: u8| self.v_reg[x as usize] as u64;
+        let cost = match instruction {
+            // Zeroes the 256 bytes of the display, six cycles each
+            Instruction::ClearScreen => 1536,
+            Instruction::Return => 10,
+            Instruction::Jump(_) => 12,
+            Instruction::Call(_) => 26,
+            Instruction::SkipIfEq { .. } | Instruction::SkipIfNe { .. } => 10,
+            Instruction::SkipIfRegEq { .. } | Instruction::SkipIfRegNe { .. } => 14,
+            Instruction::SetImm { .. } => 6,
+            Instruction::AddImm { .. } => 10,
+            // The interpreter builds the 1802 instruction in RAM and runs it there
+            Instruction::SetReg { .. }
+            | Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::Add { .. }
+            | Instruction::Sub { .. }
+            | Instruction::ShiftRight { .. }
+            | Instruction::SubReversed { .. }
+            | Instruction::ShiftLeft { .. } => 44,
+            Instruction::SetI(_) => 12,
+            Instruction::JumpOffset { .. } => 22,
+            Instruction::Random { .. } => 36,
+            // Each row is shifted into place one bit at a time, and a sprite that isn't on a
+            // byte boundary spills into a second byte
+            Instruction::Draw { x, n, .. } => {
+                let rows = if n == 0 { 16 } else { n as u64 };
+                let shift = vx(x) % 8;
+                let per_row = if shift == 0 { 14 } else { 30 + 4 * shift };
+                26 + rows * per_row
+            }
+            Instruction::SkipIfKey { .. } | Instruction::SkipIfNotKey { .. } => 16,
+            Instruction::GetDelay { .. }
+            | Instruction::SetDelay { .. }
+            | Instruction::SetSound { .. } => 10,
+            Instruction::AddI { .. } | Instruction::Font { .. } => 20,
+            // Repeated subtraction, one round per unit of each digit
+            Instruction::Bcd { x } => {
+                let value = vx(x);
+                60 + 8 * (value / 100 + value / 10 % 10 + value % 10)
+            }
+            Instruction::Store { x } | Instruction::Load { x } => 14 + 14 * (x as u64 + 1),
+            _ => 0,
+        };
+        DISPATCH + cost
+    }
+
+    // Charge a tick that ran `instruction` from `addr` with `outcome`, `cost` being its `vip_cost`
+    pub(crate) fn spend_vip_cycles(
+        &mut self,
+        instruction: Instruction,
+        addr: u16,
+        outcome: StepOutcome,
+        cost: u64,
+    ) {
+        match outcome {
+            StepOutcome::WaitingForKey => return self.spend(DISPATCH),
+            // Only if the timing was switched on halfway through a frame
+            StepOutcome::WaitingForVblank => return self.wait_for_interrupt(),
+            _ => (),
+        }
+        if let Instruction::Draw { .. } = instruction {
+            self.wait_for_interrupt();
+        }
+        let skip = matches!(
+            instruction,
+            Instruction::SkipIfEq { .. }
+                | Instruction::SkipIfNe { .. }
+                | Instruction::SkipIfRegEq { .. }
+                | Instruction::SkipIfRegNe { .. }
+                | Instruction::SkipIfKey { .. }
+                | Instruction::SkipIfNotKey { .. }
+        );
+        let skipped = skip && self.pc != addr.wrapping_add(2);
+        self.spend(cost + if skipped { SKIP_TAKEN } else { 0 });
+    }
+
+    // Let `cycles` pass, running the interrupt at every frame boundary crossed
+    fn spend(&mut self, cycles: u64) {
+        let mut next = (self.cycles / VIP_CYCLES_PER_FRAME + 1) * VIP_CYCLES_PER_FRAME;
+        self.cycles += cycles;
+        while self.cycles >= next {
+            self.cycles += VIP_INTERRUPT_CYCLES;
+            self.tick_timers();
+            next += VIP_CYCLES_PER_FRAME;
+        }
+    }
+
+    fn wait_for_interrupt(&mut self) {
+        let next = (self.cycles / VIP_CYCLES_PER_FRAME + 1) * VIP_CYCLES_PER_FRAME;
+        self.spend(next - self.cycles);
+    }
+}
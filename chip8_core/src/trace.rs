@@ -0,0 +1,521 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+use std::str::FromStr;
+
+use crate::disasm::Syntax;
+use crate::{Emu, Platform};
+use crate::{EmuError, Instruction, NUM_REGS, StepOutcome};
+
+/*
+ * Instruction traces: one entry for every instruction the CPU completes, with the registers
+ * before and after it. Two traces of the same ROM can then be compared entry by entry to find
+ * the first instruction where two builds (or this emulator and another one) stop agreeing.
+ *
+ * The text format is one line per entry, meant to be read, grepped and produced by scripts from
+ * the logs of other emulators:
+ *
+ *   12 0206 8014 V=0A14000000000000000000000000000F I=022A SP=1 DT=3C ST=00 -> V=1E14...
+ *      ... I=022A SP=1 DT=3C ST=00 ; ADD V0, V1
+ *
+ * that is the cycle (instructions completed before this one, from 0), PC and opcode in hex, the
+ * state before and after (V0 to VF as 32 hex digits, I, the stack depth and both timers) and the
+ * mnemonic after a `;`, which is only there for people and ignored when reading. Lines starting
+ * with `#` are comments.
+ *
+ * The binary format holds the same fields in a fraction of the size, for long runs:
+ *
+ * magic       4 bytes   "C8TR"
+ * version     u16       TRACE_VERSION
+ * entries     ...       cycle u64, PC u16, opcode length u8 (2 or 4), opcode bytes, before and
+ *                       after as V0-VF, I u16, SP u8, DT u8, ST u8
+ *
+ * with every number little endian, like save states.
+ *
+ * Instructions that wait (`FX0A` with no key down, `DXYN` with `display_wait` before the frame
+ * starts) haven't completed and aren't traced until they do, and an instruction that fails
+ * isn't traced at all, so the trace doesn't depend on how many times the frontend called `tick`.
+ */
+const MAGIC: &[u8; 4] = b"C8TR";
+pub const TRACE_VERSION: u16 = 1;
+
+// Registers saved around every traced instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuState {
+    pub v: [u8; NUM_REGS],
+    pub i: u16,
+    pub sp: u8,
+    pub dt: u8,
+    pub st: u8,
+}
+
+impl CpuState {
+    pub fn of(emu: &Emu) -> Self {
+        CpuState {
+            v: *emu.registers(),
+            i: emu.i_reg(),
+            sp: emu.stack().len() as u8,
+            dt: emu.delay_timer(),
+            st: emu.sound_timer(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub cycle: u64,
+    pub pc: u16,
+    pub instruction: Instruction,
+    pub before: CpuState,
+    pub after: CpuState,
+}
+
+impl TraceEntry {
+    /*
+     * Every field by name and as text, in the order of the text format. The state fields are
+     * called `V0`, `I`, `DT`... followed by `before` or `after`.
+     */
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            ("cycle".to_string(), self.cycle.to_string()),
+            ("PC".to_string(), format!("{:04X}", self.pc)),
+            ("opcode".to_string(), hex(&self.instruction.encode())),
+        ];
+        for (side, state) in [("before", &self.before), ("after", &self.after)] {
+            for (x, value) in state.v.iter().enumerate() {
+                fields.push((format!("V{:X} {}", x, side), format!("{:02X}", value)));
+            }
+            fields.push((format!("I {}", side), format!("{:04X}", state.i)));
+            fields.push((format!("SP {}", side), state.sp.to_string()));
+            fields.push((format!("DT {}", side), format!("{:02X}", state.dt)));
+            fields.push((format!("ST {}", side), format!("{:02X}", state.st)));
+        }
+        fields
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = |state: &CpuState| {
+            format!(
+                "V={} I={:04X} SP={} DT={:02X} ST={:02X}",
+                hex(&state.v),
+                state.i,
+                state.sp,
+                state.dt,
+                state.st
+            )
+        };
+        write!(
+            f,
+            "{} {:04X} {} {} -> {} ; {}",
+            self.cycle,
+            self.pc,
+            hex(&self.instruction.encode()),
+            state(&self.before),
+            state(&self.after),
+            self.instruction.format(Syntax::Cowgod, &Default::default())
+        )
+    }
+}
+
+/*
+ * A field that differs between two entries: its name as in `TraceEntry::fields` and the value
+ * on each side.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub field: String,
+    pub left: String,
+    pub right: String,
+}
+
+/*
+ * The fields of `a` and `b` that differ, leaving out those in `ignore`. A name in `ignore`
+ * without `before` or `after` (`DT`, `V3`) leaves out both.
+ */
+pub fn compare(a: &TraceEntry, b: &TraceEntry, ignore: &[String]) -> Vec<Mismatch> {
+    let ignored = |field: &str| {
+        let name = field.split(' ').next().unwrap_or(field);
+        ignore
+            .iter()
+            .any(|i| i.eq_ignore_ascii_case(field) || i.eq_ignore_ascii_case(name))
+    };
+    a.fields()
+        .into_iter()
+        .zip(b.fields())
+        .filter(|((field, left), (_, right))| left != right && !ignored(field))
+        .map(|((field, left), (_, right))| Mismatch { field, left, right })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "binary" => Ok(Format::Binary),
+            _ => Err(format!("unknown trace format '{}' (text, binary)", s)),
+        }
+    }
+}
+
+/*
+ * Runs the machine one instruction at a time like `Emu::tick`, writing an entry for each one to
+ * `out`. Frontends that want a trace call `tracer.tick(&mut emu)` where they would have called
+ * `emu.tick()`.
+ *
+ * Tracing must never stop the game, so a failed write doesn't show up in `tick`: the tracer stops
+ * writing and `finish` reports the error at the end.
+ */
+pub struct Tracer<W: Write> {
+    out: W,
+    format: Format,
+    cycle: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    // `platform` only goes into the header of text traces, as a reminder of how they were made
+    pub fn new(mut out: W, format: Format, platform: Option<Platform>) -> Self {
+        let header = match format {
+            Format::Text => writeln!(
+                out,
+                "# chip8 trace v{}, {}\n# cycle PC opcode state -> state ; mnemonic",
+                TRACE_VERSION,
+                platform.map_or("default quirks", Platform::name)
+            ),
+            Format::Binary => out
+                .write_all(MAGIC)
+                .and_then(|_| out.write_all(&TRACE_VERSION.to_le_bytes())),
+        };
+        Tracer {
+            out,
+            format,
+            cycle: 0,
+            error: header.err(),
+        }
+    }
+
+    pub fn tick(&mut self, emu: &mut Emu) -> Result<StepOutcome, EmuError> {
+        self.tick_with(emu, Emu::tick)
+    }
+
+    /*
+     * Same as `tick`, but the instruction is run by `step`, which must call `Emu::tick` once. It
+     * lets the tracer be stacked with another tool that wraps `tick`, like the profiler.
+     */
+    pub fn tick_with(
+        &mut self,
+        emu: &mut Emu,
+        step: impl FnOnce(&mut Emu) -> Result<StepOutcome, EmuError>,
+    ) -> Result<StepOutcome, EmuError> {
+        let pc = emu.pc();
+        let exited = emu.has_exited();
+        let before = CpuState::of(emu);
+        let instruction = Instruction::decode_at(emu.ram(), pc as usize);
+
+        let outcome = step(emu)?;
+        if let (true, Some(instruction)) = (completed(outcome, exited), instruction) {
+            let entry = TraceEntry {
+                cycle: self.cycle,
+                pc,
+                instruction,
+                before,
+                after: CpuState::of(emu),
+            };
+            self.cycle += 1;
+            self.write(&entry);
+        }
+        Ok(outcome)
+    }
+
+    // Instructions traced so far
+    pub fn cycles(&self) -> u64 {
+        self.cycle
+    }
+
+    // Flush what's left and give the writer back, or the first error writing the trace
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(err) = self.error.take() {
+            return Err(err);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+
+    fn write(&mut self, entry: &TraceEntry) {
+        if self.error.is_some() {
+            return;
+        }
+        let result = match self.format {
+            Format::Text => writeln!(self.out, "{}", entry),
+            Format::Binary => self.out.write_all(&encode(entry)),
+        };
+        self.error = result.err();
+    }
+}
+
+/*
+ * Whether a tick that ended with `outcome` ran an instruction to the end, rather than waiting
+ * for a key or the next frame or reporting an exit that already happened (`exited`).
+ */
+pub(crate) fn completed(outcome: StepOutcome, exited: bool) -> bool {
+    match outcome {
+        StepOutcome::Executed => true,
+        StepOutcome::Exited => !exited,
+        StepOutcome::WaitingForKey | StepOutcome::WaitingForVblank => false,
+    }
+}
+
+fn encode(entry: &TraceEntry) -> Vec<u8> {
+    let opcode = entry.instruction.encode();
+    let mut out = Vec::with_capacity(8 + 2 + 1 + opcode.len() + 2 * STATE_SIZE);
+    out.extend_from_slice(&entry.cycle.to_le_bytes());
+    out.extend_from_slice(&entry.pc.to_le_bytes());
+    out.push(opcode.len() as u8);
+    out.extend_from_slice(&opcode);
+    for state in [&entry.before, &entry.after] {
+        out.extend_from_slice(&state.v);
+        out.extend_from_slice(&state.i.to_le_bytes());
+        out.extend_from_slice(&[state.sp, state.dt, state.st]);
+    }
+    out
+}
+
+const STATE_SIZE: usize = NUM_REGS + 2 + 3;
+
+#[derive(Debug)]
+pub enum TraceError {
+    Io(io::Error),
+    // A binary trace from a build with a different entry layout
+    UnsupportedVersion(u16),
+    // Entry `entry` (counting from 1, the line number for text traces) makes no sense
+    Invalid { entry: usize, message: String },
+}
+
+impl fmt::Display for TraceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceError::Io(err) => write!(f, "{}", err),
+            TraceError::UnsupportedVersion(version) => write!(
+                f,
+                "trace version {} is not supported (expected {})",
+                version, TRACE_VERSION
+            ),
+            TraceError::Invalid { entry, message } => write!(f, "entry {}: {}", entry, message),
+        }
+    }
+}
+
+impl Error for TraceError {}
+
+impl From<io::Error> for TraceError {
+    fn from(err: io::Error) -> Self {
+        TraceError::Io(err)
+    }
+}
+
+/*
+ * Reads the entries of a trace in either format, telling them apart by the magic at the start.
+ * Iteration stops after the first error.
+ */
+pub struct TraceReader<R: BufRead> {
+    input: R,
+    format: Format,
+    // Lines or binary entries read so far
+    count: usize,
+    done: bool,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    pub fn new(mut input: R) -> Result<Self, TraceError> {
+        let format = match input.fill_buf()?.starts_with(MAGIC) {
+            true => Format::Binary,
+            false => Format::Text,
+        };
+        if format == Format::Binary {
+            let mut header = [0; 6];
+            input.read_exact(&mut header)?;
+            let version = u16::from_le_bytes([header[4], header[5]]);
+            if version != TRACE_VERSION {
+                return Err(TraceError::UnsupportedVersion(version));
+            }
+        }
+        Ok(TraceReader {
+            input,
+            format,
+            count: 0,
+            done: false,
+        })
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    fn invalid(&self, message: impl Into<String>) -> TraceError {
+        TraceError::Invalid {
+            entry: self.count,
+            message: message.into(),
+        }
+    }
+
+    fn read_text(&mut self) -> Result<Option<TraceEntry>, TraceError> {
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            self.count += 1;
+            let line = line.split(';').next().unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            return parse_line(line).map(Some).map_err(|msg| self.invalid(msg));
+        }
+    }
+
+    fn read_binary(&mut self) -> Result<Option<TraceEntry>, TraceError> {
+        if self.input.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        self.count += 1;
+        let truncated = |err: io::Error| match err.kind() {
+            io::ErrorKind::UnexpectedEof => TraceError::Invalid {
+                entry: self.count,
+                message: "trace is truncated".to_string(),
+            },
+            _ => TraceError::Io(err),
+        };
+
+        let mut head = [0; 11];
+        self.input.read_exact(&mut head).map_err(truncated)?;
+        let cycle = u64::from_le_bytes(head[..8].try_into().unwrap());
+        let pc = u16::from_le_bytes([head[8], head[9]]);
+        let len = head[10] as usize;
+        if len != 2 && len != 4 {
+            return Err(self.invalid(format!("bad opcode length {}", len)));
+        }
+        let mut rest = vec![0; len + 2 * STATE_SIZE];
+        self.input.read_exact(&mut rest).map_err(truncated)?;
+
+        let instruction = Instruction::decode_at(&rest[..len], 0)
+            .filter(|instruction| instruction.size() == len)
+            .ok_or_else(|| self.invalid("opcode length doesn't match the opcode"))?;
+        let state = |bytes: &[u8]| CpuState {
+            v: bytes[..NUM_REGS].try_into().unwrap(),
+            i: u16::from_le_bytes([bytes[NUM_REGS], bytes[NUM_REGS + 1]]),
+            sp: bytes[NUM_REGS + 2],
+            dt: bytes[NUM_REGS + 3],
+            st: bytes[NUM_REGS + 4],
+        };
+        Ok(Some(TraceEntry {
+            cycle,
+            pc,
+            instruction,
+            before: state(&rest[len..len + STATE_SIZE]),
+            after: state(&rest[len + STATE_SIZE..]),
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = Result<TraceEntry, TraceError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = match self.format {
+            Format::Text => self.read_text(),
+            Format::Binary => self.read_binary(),
+        };
+        if !matches!(result, Ok(Some(_))) {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+// `CYCLE PC OPCODE STATE -> STATE`, with the mnemonic already cut off
+fn parse_line(line: &str) -> Result<TraceEntry, String> {
+    let (head, after) = line.split_once("->").ok_or("missing '->'")?;
+    let mut tokens = head.split_whitespace();
+    let mut next = |what: &str| tokens.next().ok_or(format!("missing {}", what));
+
+    let cycle = next("cycle")?;
+    let cycle = cycle
+        .parse()
+        .map_err(|_| format!("bad cycle '{}'", cycle))?;
+    let pc = parse_hex(next("PC")?, "PC")?;
+    let opcode = next("opcode")?;
+    let bytes = (0..opcode.len())
+        .step_by(2)
+        .map(|at| {
+            opcode
+                .get(at..at + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .filter(|bytes| bytes.len() == 2 || bytes.len() == 4)
+        .ok_or(format!("bad opcode '{}'", opcode))?;
+    let instruction = Instruction::decode_at(&bytes, 0)
+        .filter(|instruction| instruction.size() == bytes.len())
+        .ok_or(format!("bad opcode '{}'", opcode))?;
+
+    let before: Vec<&str> = tokens.collect();
+    let after: Vec<&str> = after.split_whitespace().collect();
+    Ok(TraceEntry {
+        cycle,
+        pc,
+        instruction,
+        before: parse_state(&before)?,
+        after: parse_state(&after)?,
+    })
+}
+
+// `V=... I=... SP=... DT=... ST=...`, in any order
+fn parse_state(tokens: &[&str]) -> Result<CpuState, String> {
+    let field = |name: &str| {
+        tokens
+            .iter()
+            .find_map(|token| token.strip_prefix(name)?.strip_prefix('='))
+            .ok_or(format!("missing {}", name))
+    };
+
+    let v = field("V")?;
+    let mut regs = [0; NUM_REGS];
+    for (x, reg) in regs.iter_mut().enumerate() {
+        *reg = v
+            .get(x * 2..x * 2 + 2)
+            .and_then(|b| u8::from_str_radix(b, 16).ok())
+            .ok_or(format!("bad registers '{}'", v))?;
+    }
+    if v.len() != NUM_REGS * 2 {
+        return Err(format!("bad registers '{}'", v));
+    }
+    let sp = field("SP")?;
+    Ok(CpuState {
+        v: regs,
+        i: parse_hex(field("I")?, "I")?,
+        sp: sp.parse().map_err(|_| format!("bad SP '{}'", sp))?,
+        dt: u8::from_str_radix(field("DT")?, 16).map_err(|_| "bad DT".to_string())?,
+        st: u8::from_str_radix(field("ST")?, 16).map_err(|_| "bad ST".to_string())?,
+    })
+}
+
+fn parse_hex(s: &str, what: &str) -> Result<u16, String> {
+    u16::from_str_radix(s, 16).map_err(|_| format!("bad {} '{}'", what, s))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
@@ -0,0 +1,157 @@
+use chip8_core::asm::{self, AsmError};
+use chip8_core::disasm::{self, Syntax};
+use chip8_core::*;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+mod common;
+
+// Assemble `source` as `main.asm`, with `files` being everything it can include
+fn assemble(source: &str, files: &[(&str, &str)]) -> Result<asm::Assembly, AsmError> {
+    let mut read = |path: &Path| {
+        files
+            .iter()
+            .find(|(name, _)| Path::new(name) == path)
+            .map(|(_, text)| text.to_string())
+            .ok_or(io::Error::from(io::ErrorKind::NotFound))
+    };
+    asm::assemble_with(source, Path::new("main.asm"), START_ADDR, &mut read)
+}
+
+// The error as it's printed: `file:line:column: message`
+fn error(source: &str, files: &[(&str, &str)]) -> String {
+    assemble(source, files).unwrap_err().to_string()
+}
+
+#[test]
+fn errors_point_at_the_line_and_column() {
+    assert_eq!(
+        error("  CLS\n  FOO V0\n", &[]),
+        "main.asm:2:3: unknown instruction 'FOO'"
+    );
+    assert_eq!(
+        error("LD V0, #1G", &[]),
+        "main.asm:1:8: invalid number '#1G'"
+    );
+    assert_eq!(
+        error("\n\nstart: JP nowhere", &[]),
+        "main.asm:3:11: unknown name 'nowhere'"
+    );
+    assert_eq!(
+        error("LD V0, 256", &[]),
+        "main.asm:1:8: byte 256 is out of range"
+    );
+    assert_eq!(
+        error("a: CLS\na: RET", &[]),
+        "main.asm:2:1: 'a' is already defined"
+    );
+    assert_eq!(
+        error("CLS\n  @", &[]),
+        "main.asm:2:3: unexpected character '@'"
+    );
+
+    // Errors in an included file are reported in that file
+    let err = assemble("CLS\ninclude \"lib.asm\"", &[("lib.asm", "RET\n DRW V0")]).unwrap_err();
+    assert_eq!(err.file, Path::new("lib.asm"));
+    assert_eq!((err.line, err.column), (2, 2));
+}
+
+#[test]
+fn labels_can_be_used_before_they_are_defined() {
+    let program = assemble(
+        "
+        JP main
+sprite: db #F0, #90
+main:   LD I, sprite
+        CALL draw
+        JP main + 2
+draw:   RET
+        dw draw, end - sprite
+end:
+        ",
+        &[],
+    )
+    .unwrap();
+    assert_eq!(program.labels["sprite"], 0x202);
+    assert_eq!(program.labels["main"], 0x204);
+    assert_eq!(program.labels["draw"], 0x20A);
+    assert_eq!(program.labels["end"], 0x210);
+    assert_eq!(
+        program.bytes,
+        [
+            0x12, 0x04, 0xF0, 0x90, 0xA2, 0x02, 0x22, 0x0A, 0x12, 0x06, 0x00, 0xEE, 0x02, 0x0A,
+            0x00, 0x0E,
+        ]
+    );
+}
+
+#[test]
+fn constants_and_includes() {
+    let program = assemble(
+        "
+SPEED = 3
+LIMIT EQU SPEED + OFFSET
+        include \"lib.asm\"
+        LD V0, SPEED
+        LD V1, LIMIT
+        LD I, font + SIZE
+        ",
+        &[
+            ("lib.asm", "OFFSET = 2\ninclude \"font.asm\""),
+            ("font.asm", "SIZE = 5\nfont: db 1, 2, 3, 4, 5"),
+        ],
+    )
+    .unwrap();
+    assert_eq!(
+        program.bytes,
+        [1, 2, 3, 4, 5, 0x60, 0x03, 0x61, 0x05, 0xA2, 0x05]
+    );
+
+    // Where each statement came from
+    let line = program.line_for_addr(0x203).unwrap();
+    assert_eq!((line.file.as_path(), line.line), (Path::new("font.asm"), 2));
+    let line = program.line_for_addr(0x207).unwrap();
+    assert_eq!((line.file.as_path(), line.line), (Path::new("main.asm"), 6));
+
+    assert_eq!(
+        error("include \"main.asm\"", &[("main.asm", "")]),
+        "main.asm:1:1: main.asm includes itself"
+    );
+    let err = error("CLS\ninclude \"missing.asm\"", &[]);
+    assert!(
+        err.starts_with("main.asm:2:1: unable to include missing.asm"),
+        "{}",
+        err
+    );
+    let err = error("FOO = BAR\nBAR = FOO + 1\nLD V0, FOO", &[]);
+    assert!(
+        err.starts_with("main.asm:3:8: 'FOO' is defined in terms of itself"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn listings_assemble_back_to_the_same_rom() {
+    let mut names: Vec<String> = fs::read_dir(common::roms_dir())
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter_map(|path| Some(path.file_stem()?.to_str()?.to_string()))
+        .collect();
+    names.sort();
+
+    for name in names {
+        let program = common::assemble(&name);
+        let listing = disasm::disassemble(&program.bytes, START_ADDR).render(Syntax::Cowgod);
+        let again = asm::assemble(&listing, START_ADDR)
+            .unwrap_or_else(|err| panic!("{}: {}\n{}", name, err, listing));
+        assert_eq!(again.bytes, program.bytes, "{}", name);
+    }
+
+    // Only the padded columns of a listing are skipped, not a label that looks like an address
+    let program = assemble("CAFE: DB #12\nBEEF: DB #34", &[]).unwrap();
+    assert_eq!(program.bytes, [0x12, 0x34]);
+    assert_eq!(program.labels["CAFE"], 0x200);
+    assert_eq!(program.labels["BEEF"], 0x201);
+}
@@ -0,0 +1,24 @@
+// Helpers shared by the integration tests. Not every test file uses all of them
+#![allow(dead_code)]
+
+use chip8_core::asm::{self, Assembly};
+use chip8_core::*;
+use std::path::{Path, PathBuf};
+
+pub fn roms_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms")
+}
+
+// `tests/roms/NAME.asm`, assembled for `START_ADDR`
+pub fn assemble(name: &str) -> Assembly {
+    let path = roms_dir().join(format!("{}.asm", name));
+    asm::assemble_file(&path, START_ADDR).unwrap_or_else(|err| panic!("{}", err))
+}
+
+// `emu` with the test ROM `name` loaded, ready to run
+pub fn boot(name: &str, mut emu: Emu) -> (Emu, Assembly) {
+    let program = assemble(name);
+    emu.load(&program.bytes)
+        .unwrap_or_else(|err| panic!("{}: {}", name, err));
+    (emu, program)
+}
@@ -0,0 +1,44 @@
+use chip8_core::coverage::{self, Coverage};
+use chip8_core::disasm;
+use chip8_core::*;
+
+mod common;
+
+#[test]
+fn coverage_tells_code_from_data() {
+    let (mut emu, program) = common::boot("memory", Emu::new());
+    let label = |name: &str| program.labels[name];
+
+    emu.set_access_log(true);
+    let mut coverage = Coverage::new(emu.ram_size());
+    while emu.pc() != label("halt") {
+        emu.tick().unwrap();
+        coverage.record(emu.last_accesses());
+    }
+
+    assert_eq!(coverage.get(label("main")), coverage::FETCHED);
+    assert_eq!(coverage.get(label("source")), coverage::READ);
+    assert_eq!(
+        coverage.get(label("digits")),
+        coverage::READ | coverage::WRITTEN
+    );
+    // The sentinel after `scratch` is only there to be read when I moves past the others
+    assert_eq!(coverage.get(label("scratch") + 3), 0);
+
+    // Saving and merging keeps everything
+    let mut saved = Vec::new();
+    coverage.write(&mut saved).unwrap();
+    let loaded = Coverage::read(&saved[..]).unwrap();
+    let mut merged = Coverage::new(16);
+    merged.merge(&loaded);
+    assert_eq!(merged, loaded);
+    for addr in START_ADDR..START_ADDR + program.bytes.len() as u16 {
+        assert_eq!(loaded.get(addr), coverage.get(addr), "{:#05X}", addr);
+    }
+
+    let listing = disasm::disassemble_with_coverage(&program.bytes, START_ADDR, &coverage);
+    let line = |addr: u16| listing.lines.iter().find(|line| line.addr == addr).unwrap();
+    assert!(line(label("bcd")).instruction.is_some());
+    assert_eq!(line(label("source")).instruction, None);
+    assert_eq!(line(label("source")).bytes, [0xA1, 0xA2, 0xA3]);
+}
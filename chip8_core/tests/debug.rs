@@ -0,0 +1,245 @@
+use chip8_core::asm::{self, Assembly};
+use chip8_core::debug::{Debugger, Register, StepTarget, StopReason, WatchKind, Watchpoint};
+use chip8_core::*;
+
+/*
+ * `sub` calls itself back through `site` until V0 runs out, so the instruction after the call is
+ * reached at depths 2 and 1 before the top level one. V2 counts the returns to `back`.
+ */
+const RECURSIVE: &str = "
+        LD V0, 2
+site:   CALL sub
+back:   ADD V2, 1
+        SE V2, 3
+        RET
+        LD V1, #AA
+done:   JP done
+sub:    SNE V0, 0
+        RET
+        ADD V0, #FF
+        JP site
+";
+
+fn debugger(source: &str) -> (Debugger, Assembly) {
+    let program = asm::assemble(source, START_ADDR).unwrap_or_else(|err| panic!("{}", err));
+    let mut emu = Emu::new();
+    emu.load(&program.bytes).unwrap();
+    (Debugger::new(emu), program)
+}
+
+#[test]
+fn step_over_waits_for_the_same_depth() {
+    let (mut debugger, program) = debugger(RECURSIVE);
+    let label = |name: &str| program.labels[name];
+
+    // Anything but a call is a plain step
+    assert_eq!(debugger.step_over(100), StopReason::Done);
+    assert_eq!(debugger.emu().pc(), label("site"));
+
+    assert_eq!(debugger.step_over(100), StopReason::Done);
+    assert_eq!(debugger.emu().pc(), label("back"));
+    assert!(debugger.emu().stack().is_empty());
+    // `back` ran twice from deeper calls before we got here
+    assert_eq!(debugger.emu().registers()[2], 2);
+
+    // Out of budget inside the call
+    let (mut debugger, _) = self::debugger(RECURSIVE);
+    debugger.step();
+    assert_eq!(debugger.step_over(5), StopReason::Limit);
+    assert!(!debugger.emu().stack().is_empty());
+}
+
+#[test]
+fn step_out_of_a_subroutine_and_the_top_level() {
+    let (mut debugger, program) = debugger(RECURSIVE);
+    let label = |name: &str| program.labels[name];
+    debugger.step();
+    debugger.step();
+    assert_eq!(debugger.emu().pc(), label("sub"));
+    assert_eq!(debugger.emu().stack().len(), 1);
+
+    // Returning to depth 2 and 1 on the way doesn't count, only leaving depth 1 does
+    assert_eq!(debugger.step_out(100), StopReason::Done);
+    assert_eq!(debugger.emu().pc(), label("back"));
+    assert!(debugger.emu().stack().is_empty());
+    assert_eq!(debugger.emu().registers()[2], 2);
+
+    // Nothing to return from at the top: it runs like `run`
+    assert_eq!(debugger.step_out(50), StopReason::Limit);
+    assert_eq!(debugger.emu().pc(), label("done"));
+    assert_eq!(debugger.emu().registers()[1], 0xAA);
+}
+
+#[test]
+fn step_targets_run_a_few_instructions_at_a_time() {
+    let (mut debugger, program) = debugger(RECURSIVE);
+    let label = |name: &str| program.labels[name];
+    assert_eq!(debugger.step_over_target(), None);
+    assert_eq!(debugger.step_out_target(), None);
+    debugger.step();
+
+    // What a frontend running 3 instructions per frame does for a step over
+    let target = debugger.step_over_target().unwrap();
+    assert_eq!(
+        target,
+        StepTarget::Return {
+            addr: label("back"),
+            depth: 0
+        }
+    );
+    let mut frames = 0;
+    while debugger.run_to(target, 3) == StopReason::Limit {
+        frames += 1;
+    }
+    assert!(frames > 1);
+    assert_eq!(debugger.emu().pc(), label("back"));
+    assert_eq!(debugger.emu().registers()[2], 2);
+
+    // And a step out from the innermost call
+    let (mut debugger, _) = self::debugger(RECURSIVE);
+    debugger.run_until(label("sub"), 100);
+    debugger.run_until(label("sub"), 100);
+    let target = debugger.step_out_target().unwrap();
+    assert_eq!(target, StepTarget::Out { depth: 2 });
+    while debugger.run_to(target, 3) == StopReason::Limit {}
+    assert_eq!(debugger.emu().pc(), label("back"));
+    assert_eq!(debugger.emu().stack().len(), 1);
+}
+
+#[test]
+fn run_until_an_address() {
+    let (mut debugger, program) = debugger(RECURSIVE);
+    let done = program.labels["done"];
+    assert_eq!(debugger.run_until(done, 5), StopReason::Limit);
+    assert_eq!(debugger.run_until(done, 100), StopReason::Done);
+    assert_eq!(debugger.emu().pc(), done);
+    assert_eq!(debugger.emu().registers()[1], 0xAA);
+}
+
+#[test]
+fn register_watch_reports_old_and_new() {
+    let (mut debugger, program) = debugger(RECURSIVE);
+    debugger.watch_register(Register::V(2));
+    assert_eq!(
+        debugger.run(100),
+        StopReason::RegisterChanged {
+            pc: program.labels["back"],
+            register: Register::V(2),
+            old: 0,
+            new: 1,
+        }
+    );
+    assert_eq!(
+        debugger.run(100),
+        StopReason::RegisterChanged {
+            pc: program.labels["back"],
+            register: Register::V(2),
+            old: 1,
+            new: 2,
+        }
+    );
+
+    assert!(debugger.unwatch_register(Register::V(2)));
+    debugger.watch_register(Register::I);
+    assert_eq!(debugger.run(100), StopReason::Limit);
+}
+
+const MEMORY: &str = "
+        LD I, data
+read:   LD V0, [I]
+        LD V0, #42
+write:  LD [I], V0
+loop:   JP loop
+data:   db 7
+";
+
+#[test]
+fn watchpoints_stop_on_their_kind_of_access() {
+    let (_, program) = debugger(MEMORY);
+    let label = |name: &str| program.labels[name];
+
+    for (kind, pc, access) in [
+        (WatchKind::Read, label("read"), AccessKind::Read),
+        (WatchKind::Write, label("write"), AccessKind::Write),
+        (WatchKind::Access, label("read"), AccessKind::Read),
+    ] {
+        let (mut debugger, _) = debugger(MEMORY);
+        let watchpoint = Watchpoint {
+            addr: label("data"),
+            len: 1,
+            kind,
+        };
+        debugger.add_watchpoint(watchpoint);
+        assert_eq!(
+            debugger.run(100),
+            StopReason::Watchpoint {
+                pc,
+                access: MemoryAccess {
+                    kind: access,
+                    addr: label("data"),
+                    len: 1,
+                },
+                watchpoint,
+            },
+            "{:?}",
+            kind
+        );
+        // Stopped right after the instruction
+        assert_eq!(debugger.emu().pc(), pc + 2);
+    }
+
+    // Running code is not an access, only reading it as data is
+    let (mut debugger, _) = debugger(MEMORY);
+    debugger.add_watchpoint(Watchpoint {
+        addr: label("loop"),
+        len: 2,
+        kind: WatchKind::Access,
+    });
+    assert_eq!(debugger.run(100), StopReason::Limit);
+}
+
+#[test]
+fn breakpoints_at_the_start_and_at_the_end_of_the_budget() {
+    let (mut debugger, program) = debugger(MEMORY);
+    let label = |name: &str| program.labels[name];
+    debugger.add_breakpoint(START_ADDR);
+    debugger.add_breakpoint(label("write"));
+
+    // The breakpoint we start on doesn't stop us
+    assert_eq!(debugger.run(1), StopReason::Limit);
+    assert_eq!(debugger.emu().pc(), label("read"));
+
+    // Running out right in front of one reports it, so the next run doesn't skip it
+    assert_eq!(debugger.run(2), StopReason::Breakpoint(label("write")));
+    assert_eq!(debugger.emu().pc(), label("write"));
+    assert_eq!(debugger.run(0), StopReason::Limit);
+    assert_eq!(debugger.run(100), StopReason::Limit);
+    assert_eq!(debugger.emu().pc(), label("loop"));
+}
+
+#[test]
+fn errors_leave_the_machine_as_it_was() {
+    let (mut debugger, program) = debugger(
+        "
+        LD V0, 1
+        LD I, bad
+bad:    dw #5001
+",
+    );
+    let bad = program.labels["bad"];
+    assert_eq!(debugger.run_until(bad, 10), StopReason::Done);
+    let before = debugger.emu().save_state();
+
+    assert_eq!(
+        debugger.run(10),
+        StopReason::Error(EmuError::UnknownOpcode {
+            opcode: 0x5001,
+            addr: bad,
+        })
+    );
+    assert_eq!(debugger.emu().pc(), bad);
+    assert!(debugger.emu().save_state() == before);
+    // And it doesn't move on when asked again
+    assert!(matches!(debugger.step(), StopReason::Error(_)));
+    assert_eq!(debugger.emu().pc(), bad);
+}
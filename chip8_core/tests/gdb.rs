@@ -0,0 +1,214 @@
+use chip8_core::debug::Debugger;
+use chip8_core::gdb::{GdbStub, SessionEnd};
+use chip8_core::*;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+const PROGRAM: &str = "
+        LD V0, 5
+        LD V1, 6
+loop:   LD I, data
+        ADD V0, 1
+write:  LD [I], V0
+        LD I, data
+        LD V0, [I]
+        JP loop
+data:   db 0
+";
+
+// The client side of the protocol, acknowledging every reply like GDB does
+struct Client {
+    stream: TcpStream,
+}
+
+impl Client {
+    fn byte(&mut self) -> u8 {
+        let mut byte = [0];
+        self.stream.read_exact(&mut byte).unwrap();
+        byte[0]
+    }
+
+    fn request(&mut self, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, b| sum.wrapping_add(b));
+        write!(self.stream, "${}#{:02x}", data, sum).unwrap();
+        assert_eq!(self.byte(), b'+', "{}", data);
+
+        assert_eq!(self.byte(), b'$', "{}", data);
+        let mut reply = Vec::new();
+        let mut sum = 0u8;
+        loop {
+            match self.byte() {
+                b'#' => break,
+                byte => {
+                    sum = sum.wrapping_add(byte);
+                    reply.push(byte);
+                }
+            }
+        }
+        let checksum = [self.byte(), self.byte()];
+        assert_eq!(checksum, format!("{:02x}", sum).as_bytes(), "{}", data);
+        self.stream.write_all(b"+").unwrap();
+        String::from_utf8(reply).unwrap()
+    }
+
+    // Register `n` as the client sees it, big-endian hex
+    fn register(&mut self, n: usize) -> String {
+        self.request(&format!("p{:x}", n))
+    }
+}
+
+/*
+ * Run a session where `talk` is the client of a stub serving the test program. The stub runs
+ * here and the client on another thread, so a failed assertion on either side ends the session.
+ */
+fn session(
+    source: &str,
+    talk: impl FnOnce(&mut Client) + Send + 'static,
+) -> (Debugger, SessionEnd) {
+    let program = asm::assemble(source, START_ADDR).unwrap();
+    let mut emu = Emu::new();
+    emu.load(&program.bytes).unwrap();
+    let mut debugger = Debugger::new(emu);
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let client = thread::spawn(move || {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut client = Client { stream };
+        talk(&mut client);
+    });
+
+    let (stream, _) = listener.accept().unwrap();
+    // Every packet waits for its ack, which Nagle's algorithm would hold back
+    stream.set_nodelay(true).unwrap();
+    let end = GdbStub::new(stream).serve(&mut debugger).unwrap();
+    client.join().unwrap();
+    (debugger, end)
+}
+
+fn label(name: &str) -> u16 {
+    asm::assemble(PROGRAM, START_ADDR).unwrap().labels[name]
+}
+
+#[test]
+fn attach_and_inspect() {
+    let (debugger, end) = session(PROGRAM, |gdb| {
+        let supported = gdb.request("qSupported:multiprocess+;swbreak+");
+        assert!(supported.contains("PacketSize=4000"), "{}", supported);
+        assert!(supported.contains("swbreak+"), "{}", supported);
+        assert!(supported.contains("qXfer:features:read+"), "{}", supported);
+        assert_eq!(gdb.request("?"), "T05");
+
+        // V0-VF, then I and PC as 16 bits, then SP, DT and ST
+        let registers = gdb.request("g");
+        assert_eq!(registers.len(), (16 + 2 + 2 + 3) * 2);
+        assert_eq!(&registers[36..40], "0200");
+
+        let mut changed = registers.clone();
+        changed.replace_range(2..4, "ab");
+        changed.replace_range(32..36, "0abc");
+        assert_eq!(gdb.request(&format!("G{}", changed)), "OK");
+        assert_eq!(gdb.request("g"), changed);
+        assert_eq!(gdb.register(1), "ab");
+        assert_eq!(gdb.register(16), "0abc");
+        assert_eq!(gdb.request("P0=42"), "OK");
+
+        // A `G` that can't be applied as a whole changes nothing: here SP says 1 instead of 0
+        let before = gdb.request("g");
+        let mut bad_sp = before.replace("0abc", "0def");
+        bad_sp.replace_range(40..42, "01");
+        assert_eq!(gdb.request(&format!("G{}", bad_sp)), "E01");
+        assert_eq!(gdb.request(&format!("G{}", &before[..20])), "E01");
+        assert_eq!(gdb.request("g"), before);
+        assert_eq!(gdb.request("P12=03"), "E01");
+        assert_eq!(gdb.request("P10=01"), "E01");
+
+        // Addresses to resume at have to be in RAM, not cut down to 16 bits
+        assert_eq!(gdb.request("c10200"), "E01");
+        assert_eq!(gdb.request("s1000"), "E01");
+        assert_eq!(gdb.register(17), "0200");
+
+        assert_eq!(gdb.request("m200,4"), "60056106");
+        assert_eq!(gdb.request("M300,3:beef01"), "OK");
+        assert_eq!(gdb.request("m300,3"), "beef01");
+        assert_eq!(gdb.request("m1000,1"), "E01");
+        assert_eq!(gdb.request("D"), "OK");
+    });
+
+    assert_eq!(end, SessionEnd::Detached);
+    let emu = debugger.emu();
+    assert_eq!(emu.registers()[..2], [0x42, 0xAB]);
+    assert_eq!(emu.i_reg(), 0xABC);
+    assert_eq!(emu.ram()[0x300..0x303], [0xBE, 0xEF, 0x01]);
+}
+
+#[test]
+fn breakpoints_and_stepping() {
+    let (write, data) = (label("write"), label("data"));
+    let (debugger, end) = session(PROGRAM, move |gdb| {
+        assert_eq!(gdb.request("s"), "T05");
+        assert_eq!(gdb.register(17), "0202");
+        assert_eq!(gdb.request("s"), "T05");
+        assert_eq!(gdb.register(1), "06");
+
+        // The target stops before running the instruction under the breakpoint
+        assert_eq!(gdb.request(&format!("Z0,{:x},2", write)), "OK");
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.register(17), format!("{:04x}", write));
+        assert_eq!(gdb.register(0), "06");
+        // Going on from it runs the loop once more
+        assert_eq!(gdb.request("c"), "T05swbreak:;");
+        assert_eq!(gdb.register(0), "07");
+        assert_eq!(gdb.request(&format!("z0,{:x},2", write)), "OK");
+
+        // Each watchpoint stops right after the instruction that touched `data`, and the reply
+        // says which kind of watchpoint it was
+        assert_eq!(gdb.request(&format!("Z2,{:x},1", data)), "OK");
+        assert_eq!(gdb.request("c"), format!("T05watch:{:x};", data));
+        assert_eq!(gdb.register(17), format!("{:04x}", write + 2));
+        assert_eq!(gdb.request(&format!("z2,{:x},1", data)), "OK");
+
+        assert_eq!(gdb.request(&format!("Z3,{:x},1", data)), "OK");
+        assert_eq!(gdb.request("c"), format!("T05rwatch:{:x};", data));
+        assert_eq!(gdb.request(&format!("z3,{:x},1", data)), "OK");
+
+        // An access watchpoint is an `awatch` even when the access is a write
+        assert_eq!(gdb.request(&format!("Z4,{:x},1", data)), "OK");
+        assert_eq!(gdb.request("c"), format!("T05awatch:{:x};", data));
+        assert_eq!(gdb.register(17), format!("{:04x}", write + 2));
+        assert_eq!(gdb.request(&format!("z4,{:x},1", data)), "OK");
+
+        assert_eq!(gdb.request("D"), "OK");
+    });
+
+    assert_eq!(end, SessionEnd::Detached);
+    assert!(debugger.breakpoints().next().is_none());
+    assert!(debugger.watchpoints().is_empty());
+    assert_eq!(debugger.emu().ram()[data as usize], 0x08);
+}
+
+#[test]
+fn watchpoint_inside_a_longer_access() {
+    // FX55 writes V0-V7 from I on, the watched byte is the one V5 goes to
+    let source = "
+        LD V5, #55
+        LD I, buffer
+        LD [I], V7
+halt:   JP halt
+buffer: db 0, 0, 0, 0, 0, 0, 0, 0
+";
+    let buffer = asm::assemble(source, START_ADDR).unwrap().labels["buffer"];
+    let (debugger, _) = session(source, move |gdb| {
+        // Run the program again from the start for each kind
+        for (kind, reply) in [("2", "watch"), ("4", "awatch")] {
+            assert_eq!(gdb.request(&format!("P11={:04x}", START_ADDR)), "OK");
+            assert_eq!(gdb.request(&format!("Z{},{:x},1", kind, buffer + 5)), "OK");
+            assert_eq!(gdb.request("c"), format!("T05{}:{:x};", reply, buffer + 5));
+            assert_eq!(gdb.request(&format!("z{},{:x},1", kind, buffer + 5)), "OK");
+        }
+        assert_eq!(gdb.request("D"), "OK");
+    });
+    assert_eq!(debugger.emu().ram()[buffer as usize + 5], 0x55);
+}
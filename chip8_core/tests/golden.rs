@@ -0,0 +1,247 @@
+use chip8_core::*;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+mod common;
+
+/*
+ * Conformance tests: every ROM in `tests/roms` is assembled, run for a fixed number of frames
+ * and the final display compared with `tests/golden/ROM.CONFIG.txt`, one character per pixel
+ * (`.` off, `#` on, `+` and `@` for the other XO-CHIP colours). Most ROMs print their results
+ * as hex bytes, and the comment at the top of each one says what the screen should read.
+ *
+ * After a change that's meant to alter the output, run the tests with `UPDATE_GOLDEN=1` to
+ * rewrite the files, and check the new screens by hand before committing them.
+ */
+
+const FRAMES: usize = 300;
+const TICKS_PER_FRAME: usize = 20;
+
+// `key` is pressed on `frame` and released `hold` frames later
+struct KeyPress {
+    frame: usize,
+    key: usize,
+    hold: usize,
+}
+
+// The machines each ROM runs on, named after the file they are compared with
+fn config(name: &str) -> Emu {
+    let quirks = Quirks::default();
+    match name {
+        "default" => Emu::new(),
+        "vip" => Emu::for_platform(Platform::CosmacVip),
+        "chip48" => Emu::for_platform(Platform::Chip48),
+        "schip" => Emu::for_platform(Platform::SuperChip),
+        "xochip" => Emu::for_platform(Platform::XoChip),
+        "shift_uses_vy" => Emu::with_quirks(Quirks {
+            shift_uses_vy: true,
+            ..quirks
+        }),
+        "increment_by_x" => Emu::with_quirks(Quirks {
+            memory_increment: MemoryIncrement::ByX,
+            ..quirks
+        }),
+        "increment_by_x_plus_one" => Emu::with_quirks(Quirks {
+            memory_increment: MemoryIncrement::ByXPlusOne,
+            ..quirks
+        }),
+        "jump_uses_vx" => Emu::with_quirks(Quirks {
+            jump_uses_vx: true,
+            ..quirks
+        }),
+        "clip_sprites" => Emu::with_quirks(Quirks {
+            clip_sprites: true,
+            ..quirks
+        }),
+        "logic_resets_vf" => Emu::with_quirks(Quirks {
+            logic_resets_vf: true,
+            ..quirks
+        }),
+        "display_wait" => Emu::with_quirks(Quirks {
+            display_wait: true,
+            ..quirks
+        }),
+        _ => panic!("unknown config '{}'", name),
+    }
+}
+
+fn tests_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+// Runs until the frames are over or the program exits, the error is returned with the machine
+fn run(rom: &str, config_name: &str, keys: &[KeyPress]) -> (Emu, Option<EmuError>) {
+    let (mut emu, _) = common::boot(rom, config(config_name));
+
+    let mut held = [0; 16];
+    for frame in 0..FRAMES {
+        for press in keys.iter().filter(|press| press.frame == frame) {
+            emu.keypress(press.key, true);
+            held[press.key] = press.hold;
+        }
+        for _ in 0..TICKS_PER_FRAME {
+            match emu.tick() {
+                Ok(StepOutcome::Exited) => return (emu, None),
+                Ok(_) => (),
+                Err(err) => return (emu, Some(err)),
+            }
+        }
+        emu.tick_timers();
+        for (key, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    emu.keypress(key, false);
+                }
+            }
+        }
+    }
+    (emu, None)
+}
+
+fn screen(emu: &Emu) -> String {
+    const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+    let width = emu.screen_width();
+    let mut text = String::with_capacity((width + 1) * emu.screen_height());
+    for row in emu.get_display().chunks(width) {
+        text.extend(row.iter().map(|&p| ASCII[p as usize & 3]));
+        text.push('\n');
+    }
+    text
+}
+
+// `None` if the screen matches its golden file, or a description of how it doesn't
+fn compare(rom: &str, config_name: &str, emu: &Emu) -> Option<String> {
+    let path = tests_dir()
+        .join("golden")
+        .join(format!("{}.{}.txt", rom, config_name));
+    let actual = screen(emu);
+
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, &actual).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        return None;
+    }
+
+    let expected = match fs::read_to_string(&path) {
+        Ok(expected) => expected,
+        Err(err) => return Some(format!("{}: {}", path.display(), err)),
+    };
+    if expected == actual {
+        return None;
+    }
+    let row = expected
+        .lines()
+        .zip(actual.lines())
+        .position(|(e, a)| e != a)
+        .unwrap_or(0);
+    Some(format!(
+        "{} on {}: screen differs from {} starting at row {}\nexpected:\n{}actual:\n{}",
+        rom,
+        config_name,
+        path.display(),
+        row,
+        expected,
+        actual
+    ))
+}
+
+// Run `rom` on every config, reporting all the mismatches at once
+fn check(rom: &str, configs: &[&str], keys: &[KeyPress]) {
+    let mut failures = Vec::new();
+    for config_name in configs {
+        let (emu, err) = run(rom, config_name, keys);
+        if let Some(err) = err {
+            failures.push(format!("{} on {}: {}", rom, config_name, err));
+        } else if let Some(failure) = compare(rom, config_name, &emu) {
+            failures.push(failure);
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
+
+#[test]
+fn arith() {
+    check("arith", &["default", "vip", "schip", "xochip"], &[]);
+}
+
+#[test]
+fn logic() {
+    check(
+        "logic",
+        &[
+            "default",
+            "vip",
+            "xochip",
+            "shift_uses_vy",
+            "logic_resets_vf",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn flow() {
+    check("flow", &["default", "chip48", "jump_uses_vx"], &[]);
+}
+
+#[test]
+fn memory() {
+    check(
+        "memory",
+        &[
+            "default",
+            "vip",
+            "chip48",
+            "increment_by_x",
+            "increment_by_x_plus_one",
+        ],
+        &[],
+    );
+}
+
+#[test]
+fn display() {
+    check("display", &["default", "vip", "clip_sprites"], &[]);
+}
+
+#[test]
+fn timing() {
+    let keys = [KeyPress {
+        frame: 5,
+        key: 7,
+        hold: 20,
+    }];
+    check("timing", &["default", "display_wait"], &keys);
+}
+
+#[test]
+fn schip() {
+    check("schip", &["schip"], &[]);
+}
+
+#[test]
+fn xochip() {
+    check("xochip", &["xochip"], &[]);
+
+    // F002 and FX3A only change the sound
+    let (emu, _) = run("xochip", "xochip", &[]);
+    let pattern = emu.audio_pattern().expect("no audio pattern loaded");
+    assert!(pattern.iter().step_by(2).all(|&b| b == 0xFF));
+    assert!(pattern.iter().skip(1).step_by(2).all(|&b| b == 0x00));
+    assert_eq!(emu.audio_pitch(), 112);
+    assert_eq!(emu.audio_sample_rate(), 8000.0);
+}
+
+#[test]
+fn unknown_opcode() {
+    let (emu, err) = run("unknown", "default", &[]);
+    match err {
+        Some(EmuError::UnknownOpcode { opcode: 0x5001, .. }) => (),
+        other => panic!("expected an unknown opcode error, got {:?}", other),
+    }
+    if let Some(failure) = compare("unknown", "default", &emu) {
+        panic!("{}", failure);
+    }
+}
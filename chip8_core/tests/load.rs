@@ -0,0 +1,73 @@
+use chip8_core::*;
+
+#[test]
+fn rejected_loads_leave_memory_alone() {
+    let mut emu = Emu::new();
+    emu.load(&[0x12, 0x00, 0xAA, 0xBB]).unwrap();
+    let ram = emu.ram().to_vec();
+
+    assert_eq!(emu.load_segment(0x300, &[]), Err(LoadError::Empty));
+    assert_eq!(
+        emu.load_segment(0x1FF, &[1]),
+        Err(LoadError::ReservedAddress { addr: 0x1FF })
+    );
+    assert_eq!(
+        emu.load_segment(0xFFE, &[1, 2, 3]),
+        Err(LoadError::TooLarge {
+            addr: 0xFFE,
+            len: 3,
+            available: 2
+        })
+    );
+    assert_eq!(
+        emu.load_segment(0x1000, &[1]),
+        Err(LoadError::TooLarge {
+            addr: 0x1000,
+            len: 1,
+            available: 0
+        })
+    );
+    assert_eq!(
+        emu.load_segment(0x203, &[1, 2]),
+        Err(LoadError::Overlap {
+            addr: 0x203,
+            other: 0x200
+        })
+    );
+    assert_eq!(
+        emu.load_program_at(0x1F0, &[0x00, 0xE0]),
+        Err(LoadError::ReservedAddress { addr: 0x1F0 })
+    );
+
+    assert_eq!(emu.ram(), &ram[..]);
+    assert_eq!(emu.pc(), START_ADDR);
+}
+
+#[test]
+fn free_ram_counts_every_segment() {
+    let mut emu = Emu::new();
+    let program_ram = RAM_SIZE - START_ADDR as usize;
+    assert_eq!(emu.free_ram(), program_ram);
+
+    let info = emu.load_program_at(ETI660_START_ADDR, &[0x00; 10]).unwrap();
+    assert_eq!(
+        info,
+        LoadInfo {
+            addr: ETI660_START_ADDR,
+            len: 10,
+            free: program_ram - 10
+        }
+    );
+    assert_eq!(emu.pc(), ETI660_START_ADDR);
+
+    // A segment right after the program is fine, and doesn't move the PC
+    let info = emu
+        .load_segment(ETI660_START_ADDR + 10, &[0xFF; 6])
+        .unwrap();
+    assert_eq!(info.free, program_ram - 16);
+    assert_eq!(emu.free_ram(), program_ram - 16);
+    assert_eq!(emu.pc(), ETI660_START_ADDR);
+
+    emu.reset();
+    assert_eq!(emu.free_ram(), program_ram);
+}
@@ -0,0 +1,147 @@
+use chip8_core::asm::{self, Assembly};
+use chip8_core::*;
+
+mod common;
+
+// `source` loaded with the predecode cache on
+fn cached(source: &str) -> (Emu, Assembly) {
+    let program = asm::assemble(source, START_ADDR).unwrap_or_else(|err| panic!("{}", err));
+    let mut emu = Emu::new();
+    emu.set_predecode(true);
+    emu.load(&program.bytes).unwrap();
+    (emu, program)
+}
+
+fn run_to(emu: &mut Emu, addr: u16) {
+    for _ in 0..1000 {
+        if emu.pc() == addr {
+            return;
+        }
+        emu.tick().unwrap();
+    }
+    panic!("never got to {:#05X}, stopped at {:#05X}", addr, emu.pc());
+}
+
+/*
+ * Each program calls `target` once so it's in the cache, changes its code and calls it again:
+ * the second call has to run the new instruction.
+ */
+
+#[test]
+fn fx55_over_cached_code() {
+    let (mut emu, program) = cached(
+        "
+        CALL target
+        LD I, target
+        LD V0, #65
+        LD V1, #77
+        LD [I], V1      ; target: LD V5, #77
+        CALL target
+done:   JP done
+target: LD V5, #11
+        RET
+",
+    );
+    run_to(&mut emu, program.labels["done"]);
+    assert_eq!(emu.registers()[5], 0x77);
+}
+
+#[test]
+fn fx33_over_cached_code() {
+    let (mut emu, program) = cached(
+        "
+        CALL target
+        LD I, target - 1
+        LD V0, 0
+        LD B, V0        ; 00 00 00 from the last byte of `pad`: target is a NOP
+        CALL target
+done:   JP done
+pad:    dw 0
+target: ADD V5, 1
+        RET
+",
+    );
+    run_to(&mut emu, program.labels["done"]);
+    assert_eq!(emu.registers()[5], 1);
+}
+
+#[test]
+fn write_memory_over_cached_code() {
+    let (mut emu, program) = cached(
+        "
+loop:   CALL target
+        JP loop
+target: LD V5, #11
+        RET
+",
+    );
+    let target = program.labels["target"];
+    run_to(&mut emu, target + 2);
+    assert_eq!(emu.registers()[5], 0x11);
+
+    // What a debugger or a frontend does
+    assert!(emu.write_memory(target, &[0x65, 0x77]));
+    run_to(&mut emu, target);
+    run_to(&mut emu, target + 2);
+    assert_eq!(emu.registers()[5], 0x77);
+}
+
+#[test]
+fn write_to_the_address_of_a_long_load() {
+    // Only the last byte of `F000 NNNN` changes, 3 bytes after where the instruction starts
+    let (mut emu, program) = cached(
+        "
+        CALL target
+        LD I, target + 3
+        LD V0, #44
+        LD [I], V0
+        CALL target
+done:   JP done
+target: LD I, LONG #0300
+        RET
+",
+    );
+    let target = program.labels["target"];
+    run_to(&mut emu, target + 4);
+    assert_eq!(emu.i_reg(), 0x300);
+    run_to(&mut emu, program.labels["done"]);
+    assert_eq!(emu.i_reg(), 0x344);
+}
+
+// Run a test ROM like the golden tests do, returning the final state and how it ended
+fn run_rom(rom: &str, emu: Emu, predecode: bool) -> (Vec<u8>, Result<StepOutcome, EmuError>) {
+    let (mut emu, _) = common::boot(rom, emu);
+    emu.set_predecode(predecode);
+    let mut outcome = Ok(StepOutcome::Executed);
+    for tick in 1..=6000 {
+        outcome = emu.tick();
+        if matches!(outcome, Ok(StepOutcome::Exited) | Err(_)) {
+            break;
+        }
+        if tick % 20 == 0 {
+            emu.tick_timers();
+        }
+    }
+    (emu.save_state(), outcome)
+}
+
+#[test]
+fn test_roms_run_the_same_with_the_cache() {
+    for (rom, platform) in [
+        ("arith", None),
+        ("logic", None),
+        ("flow", None),
+        ("memory", None),
+        ("display", None),
+        ("timing", None),
+        ("unknown", None),
+        ("schip", Some(Platform::SuperChip)),
+        ("xochip", Some(Platform::XoChip)),
+    ] {
+        let emu = || platform.map_or_else(Emu::new, Emu::for_platform);
+        let (plain, plain_end) = run_rom(rom, emu(), false);
+        let (cached, cached_end) = run_rom(rom, emu(), true);
+        assert_eq!(cached_end, plain_end, "{}", rom);
+        assert!(cached == plain, "{}: the states differ", rom);
+    }
+}
@@ -0,0 +1,53 @@
+use chip8_core::profile::Profiler;
+use chip8_core::*;
+use std::collections::BTreeMap;
+
+mod common;
+
+#[test]
+fn profile_counts_subroutines_and_folds_stacks() {
+    let (mut emu, program) = common::boot("memory", Emu::new());
+    let labels: BTreeMap<u16, String> = program
+        .labels
+        .iter()
+        .map(|(name, &addr)| (addr, name.clone()))
+        .collect();
+    let halt = program.labels["halt"];
+
+    let mut profiler = Profiler::new();
+    while emu.pc() != halt {
+        profiler.tick(&mut emu).unwrap();
+    }
+
+    // 16 values printed, 12 of them from the 4 calls to `bcd`, which jumps to `print` for the
+    // last digit instead of calling it
+    let report = profiler.report(&labels, 100);
+    let row = |name: &str| {
+        report
+            .lines()
+            .find(|line| line.ends_with(&format!("  {}", name)))
+            .unwrap_or_else(|| panic!("no {} in\n{}", name, report))
+            .split_whitespace()
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(row("print")[4], "12");
+    assert_eq!(row("bcd")[4], "4");
+    assert_eq!(row("top")[0], profiler.total().to_string());
+
+    let mut folded = Vec::new();
+    profiler.write_folded(&mut folded, &labels).unwrap();
+    let folded = String::from_utf8(folded).unwrap();
+    let count = |stack: &str| -> u64 {
+        folded
+            .lines()
+            .find_map(|line| line.strip_prefix(&format!("{} ", stack)))
+            .map_or(0, |count| count.parse().unwrap())
+    };
+    let total: u64 = folded
+        .lines()
+        .map(|line| line.rsplit(' ').next().unwrap().parse::<u64>().unwrap())
+        .sum();
+    assert_eq!(total, profiler.total());
+    assert!(count("top;bcd;print") > 0, "{}", folded);
+    assert!(count("top;print") > 0, "{}", folded);
+}
@@ -0,0 +1,115 @@
+use chip8_core::*;
+
+mod common;
+
+const FRAMES: usize = 40;
+
+// What the frontend can see of the machine, to compare against after rewinding
+#[derive(Debug, PartialEq)]
+struct Snapshot {
+    ram: Vec<u8>,
+    pc: u16,
+    i: u16,
+    v: Vec<u8>,
+    stack: Vec<u16>,
+    delay: u8,
+    display: Vec<u8>,
+}
+
+impl Snapshot {
+    fn of(emu: &Emu) -> Self {
+        Snapshot {
+            ram: emu.ram().to_vec(),
+            pc: emu.pc(),
+            i: emu.i_reg(),
+            v: emu.registers().to_vec(),
+            stack: emu.stack().to_vec(),
+            delay: emu.delay_timer(),
+            display: emu.get_display().to_vec(),
+        }
+    }
+}
+
+// Run `FRAMES` frames of a few instructions each, pushing after every one like a frontend would
+fn record(rewind: &mut Rewind) -> (Emu, Vec<Snapshot>) {
+    let (mut emu, _) = common::boot("display", Emu::new());
+    let mut frames = Vec::new();
+    for _ in 0..FRAMES {
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        emu.tick_timers();
+        rewind.push(&emu);
+        frames.push(Snapshot::of(&emu));
+    }
+    (emu, frames)
+}
+
+#[test]
+fn rewinding_goes_back_frame_by_frame() {
+    let mut rewind = Rewind::new(FRAMES, usize::MAX);
+    let (mut emu, frames) = record(&mut rewind);
+    assert_eq!(rewind.len(), FRAMES);
+    // The ROM drew something, so going back is more than just moving the PC
+    assert_ne!(frames[0].display, frames[FRAMES - 1].display);
+
+    for back in 1..FRAMES {
+        assert_eq!(rewind.rewind(&mut emu), Ok(true));
+        assert_eq!(
+            Snapshot::of(&emu),
+            frames[FRAMES - 1 - back],
+            "{} back",
+            back
+        );
+    }
+    // Nothing older than the first push, we stay there
+    assert_eq!(rewind.rewind(&mut emu), Ok(false));
+    assert_eq!(Snapshot::of(&emu), frames[0]);
+    assert_eq!(rewind.len(), 1);
+
+    // Running again from a rewound state gives the same frames as the first time
+    for frame in &frames[1..10] {
+        for _ in 0..3 {
+            emu.tick().unwrap();
+        }
+        emu.tick_timers();
+        assert_eq!(Snapshot::of(&emu), *frame);
+    }
+}
+
+#[test]
+fn snapshot_limit_drops_the_oldest() {
+    let mut rewind = Rewind::new(5, usize::MAX);
+    let (mut emu, frames) = record(&mut rewind);
+    assert_eq!(rewind.len(), 5);
+
+    for _ in 0..4 {
+        assert_eq!(rewind.rewind(&mut emu), Ok(true));
+    }
+    assert_eq!(rewind.rewind(&mut emu), Ok(false));
+    assert_eq!(Snapshot::of(&emu), frames[FRAMES - 5]);
+}
+
+#[test]
+fn byte_budget_drops_the_oldest() {
+    // Everything but the newest state has to fit in 200 bytes
+    let mut rewind = Rewind::new(FRAMES, 200);
+    let (mut emu, frames) = record(&mut rewind);
+    let kept = rewind.len();
+    assert!(1 < kept && kept < FRAMES, "{}", kept);
+    assert!(rewind.memory_usage() <= emu.save_state().len() + 200);
+
+    // What's left are the newest snapshots, in order
+    for _ in 1..kept {
+        assert_eq!(rewind.rewind(&mut emu), Ok(true));
+    }
+    assert_eq!(rewind.rewind(&mut emu), Ok(false));
+    assert_eq!(Snapshot::of(&emu), frames[FRAMES - kept]);
+
+    // Without a budget only the newest state is kept
+    let mut rewind = Rewind::new(FRAMES, 0);
+    let (mut emu, frames) = record(&mut rewind);
+    assert_eq!(rewind.len(), 1);
+    assert_eq!(rewind.rewind(&mut emu), Ok(false));
+    assert_eq!(Snapshot::of(&emu), frames[FRAMES - 1]);
+}
@@ -0,0 +1,75 @@
+use chip8_core::*;
+
+// Sixteen `CXFF`s in a row, one into each register
+fn random_bytes() -> Emu {
+    let source: String = (0..16).map(|x| format!("RND V{:X}, #FF\n", x)).collect();
+    let program = asm::assemble(&source, START_ADDR).unwrap_or_else(|err| panic!("{}", err));
+    let mut emu = Emu::new();
+    emu.load(&program.bytes).unwrap();
+    emu
+}
+
+// The bytes the next 16 `CXFF`s give
+fn sequence(emu: &mut Emu) -> Vec<u8> {
+    emu.set_pc(START_ADDR);
+    for _ in 0..16 {
+        emu.tick().unwrap();
+    }
+    emu.registers().to_vec()
+}
+
+#[test]
+fn same_seed_same_numbers() {
+    let mut one = random_bytes();
+    let mut other = random_bytes();
+    one.seed_rng(1234);
+    other.seed_rng(1234);
+    let numbers = sequence(&mut one);
+    assert_eq!(sequence(&mut other), numbers);
+    // Random enough not to be all the same byte
+    assert!(numbers.iter().any(|&n| n != numbers[0]), "{:?}", numbers);
+
+    // Without a seed every machine starts from `DEFAULT_SEED`
+    let mut unseeded = random_bytes();
+    let mut default = random_bytes();
+    default.seed_rng(DEFAULT_SEED);
+    assert_eq!(sequence(&mut unseeded), sequence(&mut default));
+}
+
+#[test]
+fn different_seeds_different_numbers() {
+    let mut one = random_bytes();
+    let mut other = random_bytes();
+    one.seed_rng(1);
+    other.seed_rng(2);
+    assert_ne!(sequence(&mut one), sequence(&mut other));
+}
+
+#[test]
+fn savestates_keep_the_generator() {
+    let mut emu = random_bytes();
+    emu.seed_rng(99);
+    sequence(&mut emu);
+    let state = emu.save_state();
+    let next = sequence(&mut emu);
+
+    // Into the same machine after it moved on, and into one seeded differently
+    emu.load_state(&state).unwrap();
+    assert_eq!(sequence(&mut emu), next);
+    let mut other = random_bytes();
+    other.seed_rng(7);
+    other.load_state(&state).unwrap();
+    assert_eq!(sequence(&mut other), next);
+}
+
+#[test]
+fn reset_replays_the_same_numbers() {
+    let mut emu = random_bytes();
+    emu.seed_rng(1234);
+    let numbers = sequence(&mut emu);
+    let rom = emu.ram()[START_ADDR as usize..START_ADDR as usize + 32].to_vec();
+
+    emu.reset();
+    emu.load(&rom).unwrap();
+    assert_eq!(sequence(&mut emu), numbers);
+}
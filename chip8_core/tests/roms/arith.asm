@@ -0,0 +1,82 @@
+; 8XY4, 8XY5 and 8XY7 with and without carry/borrow, 7XNN. Each result is followed by VF.
+;
+;   30 00 | 10 01 | 00 01 | 01 (ADD VF: flag wins)
+;   20 01 | E0 00 | 00 01 | 00 (SUB VF: flag wins)
+;   20 01 | E0 00 | 00 01 | 01 55 (7XNN wraps, VF untouched) | 15 (7XNN on VF)
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+
+        ; 8XY4
+        LD V1, #10
+        LD V2, #20
+        ADD V1, V2
+        CALL show
+        LD V1, #F0
+        LD V2, #20
+        ADD V1, V2
+        CALL show
+        LD V1, #FF
+        LD V2, #01
+        ADD V1, V2
+        CALL show
+        LD VF, #F0
+        LD V1, #20
+        ADD VF, V1
+        LD V0, VF
+        CALL print
+
+        ; 8XY5
+        LD V1, #30
+        LD V2, #10
+        SUB V1, V2
+        CALL show
+        LD V1, #10
+        LD V2, #30
+        SUB V1, V2
+        CALL show
+        LD V1, #10
+        LD V2, #10
+        SUB V1, V2
+        CALL show
+        LD VF, #10
+        LD V1, #30
+        SUB VF, V1
+        LD V0, VF
+        CALL print
+
+        ; 8XY7
+        LD V1, #10
+        LD V2, #30
+        SUBN V1, V2
+        CALL show
+        LD V1, #30
+        LD V2, #10
+        SUBN V1, V2
+        CALL show
+        LD V1, #10
+        LD V2, #10
+        SUBN V1, V2
+        CALL show
+
+        ; 7XNN
+        LD VF, #55
+        LD V1, #FF
+        ADD V1, 2
+        CALL show
+        LD VF, #10
+        ADD VF, 5
+        LD V0, VF
+        CALL print
+
+halt:   JP halt
+
+; Print V1, then VF as it was when called
+show:   LD V3, VF
+        LD V0, V1
+        CALL print
+        LD V0, V3
+        JP print
@@ -0,0 +1,55 @@
+; 00E0 and DXYN. The collision flags are printed on the top row:
+;
+;   00 (first box) | 01 (drawn again, erased) | 00 (new box) | 01 (overlapping box)
+;
+; Below them are the XOR of the two overlapping boxes, a box on the right edge and one on the
+; bottom edge, which wrap around or get clipped with `clip_sprites`, and one whose coordinates
+; are past the edges, which always wrap.
+
+        JP main
+        include "print.asm"
+
+main:   LD I, box
+        LD V1, 0
+        DRW V1, V1, 4
+        CLS
+
+        ; Collisions
+        LD V1, 8
+        LD V2, 14
+        DRW V1, V2, 4
+        LD V3, VF
+        DRW V1, V2, 4
+        LD V4, VF
+        LD V1, 8
+        DRW V1, V2, 4
+        LD V5, VF
+        LD V1, 12
+        DRW V1, V2, 4
+        LD V6, VF
+
+        ; Edges
+        LD V1, 60
+        LD V2, 10
+        DRW V1, V2, 4
+        LD V1, 52
+        LD V2, 30
+        DRW V1, V2, 4
+        LD V1, 64 + 28
+        LD V2, 32 + 20
+        DRW V1, V2, 4
+
+        LD VD, 0
+        LD VE, 0
+        LD V0, V3
+        CALL print
+        LD V0, V4
+        CALL print
+        LD V0, V5
+        CALL print
+        LD V0, V6
+        CALL print
+
+halt:   JP halt
+
+box:    db #FF, #81, #81, #FF
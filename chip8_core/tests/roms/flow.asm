@@ -0,0 +1,104 @@
+; Jumps, calls and skips. A skip that works leaves V0 as it was, one that doesn't loads #FF.
+;
+;   00 (SE taken) | 11 (SE not taken) | 00 (SNE taken) | 11 (SNE not taken) | 00 (5XY0 taken)
+;   11 (5XY0 not taken) | 00 (9XY0 taken) | 11 (9XY0 not taken) | AA (JP) | C0 (CALL) | C1 (nested)
+;   B4 (BNNN + V0), or B2 with `jump_uses_vx` (B2NN + V2) | 0E (NOP)
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+        LD V1, 5
+        LD V2, 5
+        LD V3, 6
+
+        ; 3XNN
+        LD V0, #00
+        SE V1, 5
+        LD V0, #FF
+        CALL print
+        LD V0, #11
+        SE V1, 6
+        JP se_ok
+        LD V0, #FF
+se_ok:  CALL print
+
+        ; 4XNN
+        LD V0, #00
+        SNE V1, 6
+        LD V0, #FF
+        CALL print
+        LD V0, #11
+        SNE V1, 5
+        JP sne_ok
+        LD V0, #FF
+sne_ok: CALL print
+
+        ; 5XY0
+        LD V0, #00
+        SE V1, V2
+        LD V0, #FF
+        CALL print
+        LD V0, #11
+        SE V1, V3
+        JP ser_ok
+        LD V0, #FF
+ser_ok: CALL print
+
+        ; 9XY0
+        LD V0, #00
+        SNE V1, V3
+        LD V0, #FF
+        CALL print
+        LD V0, #11
+        SNE V1, V2
+        JP sner_ok
+        LD V0, #FF
+sner_ok:
+        CALL print
+
+        ; 1NNN
+        LD V0, #AA
+        JP jumped
+        LD V0, #FF
+jumped: CALL print
+
+        ; 2NNN and 00EE
+        LD V0, #FF
+        CALL set_c0
+        CALL print
+        LD V0, #FF
+        CALL outer
+        CALL print
+
+        ; BNNN: the table is below #300, so with `jump_uses_vx` the offset comes from V2
+        LD V0, 4
+        LD V2, 2
+        JP V0, table
+table:  JP b0
+        JP b2
+        JP b4
+b0:     LD V0, #B0
+        JP b_done
+b2:     LD V0, #B2
+        JP b_done
+b4:     LD V0, #B4
+b_done: CALL print
+
+        ; 0000
+        LD V0, #0E
+        NOP
+        CALL print
+
+halt:   JP halt
+
+set_c0: LD V0, #C0
+        RET
+
+outer:  CALL inner
+        ADD V0, 1
+        RET
+
+inner:  LD V0, #C0
+        RET
@@ -0,0 +1,67 @@
+; 8XY0-8XY3 and the shifts. Each result is followed by VF, which starts at #55 for the logic
+; operations so `logic_resets_vf` shows up as 00.
+;
+;   12 | FC 55 | 30 55 | CC 55
+;   SHR #81 (VY = #06): 40 01, or 03 00 with `shift_uses_vy`
+;   SHL #81 (VY = #06): 02 01, or 0C 00 with `shift_uses_vy`
+;   SHR #01: 00 01 | SHL #40: 80 00 | SHR VF: 00 (flag wins)
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+
+        ; 8XY0
+        LD V2, #12
+        LD V1, V2
+        LD V0, V1
+        CALL print
+
+        ; 8XY1, 8XY2, 8XY3
+        LD V1, #F0
+        LD V2, #0C
+        LD VF, #55
+        OR V1, V2
+        CALL show
+        LD V1, #F0
+        LD V2, #3C
+        LD VF, #55
+        AND V1, V2
+        CALL show
+        LD V1, #F0
+        LD V2, #3C
+        LD VF, #55
+        XOR V1, V2
+        CALL show
+
+        ; 8XY6 and 8XYE, where VX and VY differ
+        LD V1, #81
+        LD V2, #06
+        SHR V1, V2
+        CALL show
+        LD V1, #81
+        LD V2, #06
+        SHL V1, V2
+        CALL show
+
+        ; Same register, so the quirk makes no difference
+        LD V1, #01
+        SHR V1, V1
+        CALL show
+        LD V1, #40
+        SHL V1, V1
+        CALL show
+        LD VF, #02
+        SHR VF, VF
+        LD V0, VF
+        CALL print
+
+halt:   JP halt
+
+; Print V1, then VF as it was when called
+show:   LD V3, VF
+        LD V0, V1
+        CALL print
+        LD V0, V3
+        JP print
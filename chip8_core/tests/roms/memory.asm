@@ -0,0 +1,70 @@
+; ANNN, FX1E, FX33, FX29 and the loads and stores.
+;
+;   04 (ANNN + FX1E) | 02 05 05 (BCD 255) | 01 00 09 (BCD 109) | 00 04 02 (BCD 42) | 00 00 00
+;   FX55 then a read at I: 01 unchanged, 03 by X, EE by X + 1
+;   FX65 then a read at I: A1 unchanged, A3 by X, A4 by X + 1
+;   F0 (first row of the `A` glyph)
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+
+        ; ANNN, FX1E
+        LD I, data
+        LD V1, 3
+        ADD I, V1
+        LD V0, [I]
+        CALL print
+
+        ; FX33
+        LD V1, 255
+        CALL bcd
+        LD V1, 109
+        CALL bcd
+        LD V1, 42
+        CALL bcd
+        LD V1, 0
+        CALL bcd
+
+        ; FX55: V0-V2 go to scratch, then see where I was left
+        LD I, scratch
+        LD V0, 1
+        LD V1, 2
+        LD V2, 3
+        LD [I], V2
+        LD V0, [I]
+        CALL print
+
+        ; FX65
+        LD I, source
+        LD V2, [I]
+        LD V0, [I]
+        CALL print
+
+        ; FX29
+        LD V1, #A
+        LD F, V1
+        LD V0, [I]
+        CALL print
+
+halt:   JP halt
+
+; Print the three BCD digits of V1
+bcd:    LD I, digits
+        LD B, V1
+        LD V2, [I]
+        LD V3, V1
+        LD V4, V2
+        CALL print
+        LD V0, V3
+        CALL print
+        LD V0, V4
+        JP print
+
+data:   db 1, 2, 3, 4, 5
+digits: db 0, 0, 0
+scratch:
+        db 0, 0, 0, #EE
+source: db #A1, #A2, #A3, #A4
@@ -0,0 +1,30 @@
+; Shared by the test ROMs, which show their results as hex bytes on the screen.
+;
+; print: draw V0 as two hex digits at (VD, VE) and move to the next slot. There are five slots
+; per row and five rows on the 64x32 screen. Clobbers V7, V8, V9, VF and I.
+;
+; Only uses instructions whose behaviour doesn't depend on the quirks, so the same output means
+; the same results whatever the platform.
+
+print:  LD V9, V0
+        LD V8, 0            ; high digit: how many times 16 fits in V0
+        LD V7, 16
+print_hi:
+        SUB V9, V7          ; VF = 1 when there was no borrow
+        SE VF, 1
+        JP print_lo
+        ADD V8, 1
+        JP print_hi
+print_lo:
+        ADD V9, V7          ; undo the subtraction that borrowed
+        LD F, V8
+        DRW VD, VE, 5
+        ADD VD, 5
+        LD F, V9
+        DRW VD, VE, 5
+        ADD VD, 7
+        SE VD, 60
+        RET
+        LD VD, 0
+        ADD VE, 6
+        RET
@@ -0,0 +1,54 @@
+; SUPER-CHIP: hires mode, 16x16 sprites, the big font, scrolling and the RPL flags.
+;
+; A 16x16 sprite and a big `A`, scrolled 4 pixels down and 4 to the right (right twice, left
+; once), then 11 22 33 read back from the RPL flags. The program ends with 00FD.
+
+        JP main
+        include "print.asm"
+
+main:   HIGH
+        LD I, square
+        LD V1, 0
+        DRW V1, V1, 0
+        ; Switching modes clears the screen, so none of this square should be left
+        LOW
+        HIGH
+
+        ; DXY0 and FX30
+        LD V1, 8
+        LD V2, 8
+        LD I, square
+        DRW V1, V2, 0
+        LD V3, #A
+        LD HF, V3
+        LD V1, 40
+        DRW V1, V2, 10
+
+        ; 00CN, 00FB, 00FC
+        SCD 4
+        SCR
+        SCR
+        SCL
+
+        ; FX75, FX85
+        LD V0, #11
+        LD V1, #22
+        LD V2, #33
+        LD R, V2
+        LD V0, 0
+        LD V1, 0
+        LD V2, 0
+        LD V2, R
+
+        LD VD, 0
+        LD VE, 40
+        CALL print
+        LD V0, V1
+        CALL print
+        LD V0, V2
+        CALL print
+
+        EXIT
+
+square: dw #FFFF, #8001, #8001, #8FF1, #8811, #8811, #8811, #8811
+        dw #8811, #8811, #8811, #8811, #8FF1, #8001, #8001, #FFFF
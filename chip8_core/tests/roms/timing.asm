@@ -0,0 +1,71 @@
+; Keys, timers and random numbers. The test presses key 7 on frame 5 and releases it on frame 25.
+;
+;   07 (FX0A) | 01 (EX9E, held) | 00 (EXA1, held) | 00 (EX9E, released) | 01 (EXA1, released)
+;   DT right after FX15 | 00 (DT ran out) | DT after 8 sprites, lower with `display_wait`
+;   00 (CXNN & 0) | CXNN with the default seed
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+
+        ; FX0A, EX9E, EXA1
+        LD V0, K
+        CALL print
+        LD V1, 7
+        LD V0, 1
+        SKP V1
+        LD V0, 0
+        CALL print
+        LD V0, 1
+        SKNP V1
+        LD V0, 0
+        CALL print
+held:   SKNP V1
+        JP held
+        LD V0, 1
+        SKP V1
+        LD V0, 0
+        CALL print
+        LD V0, 1
+        SKNP V1
+        LD V0, 0
+        CALL print
+
+        ; FX15, FX18, FX07
+        LD V1, #30
+        LD DT, V1
+        LD ST, V1
+        LD V0, DT
+        CALL print
+wait:   LD V0, DT
+        SE V0, 0
+        JP wait
+        CALL print
+
+        ; Sprites that don't change the screen but wait for the next frame with `display_wait`
+        LD V1, #20
+        LD DT, V1
+        LD I, blank
+        LD V1, 0
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        DRW V1, V1, 1
+        LD V0, DT
+        CALL print
+
+        ; CXNN
+        RND V0, #00
+        CALL print
+        RND V0, #FF
+        CALL print
+
+halt:   JP halt
+
+blank:  db 0
@@ -0,0 +1,10 @@
+; An opcode no platform knows stops the program with an error, after printing 42.
+
+        JP main
+        include "print.asm"
+
+main:   LD VD, 0
+        LD VE, 0
+        LD V0, #42
+        CALL print
+bad:    dw #5001
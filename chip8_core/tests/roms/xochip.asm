@@ -0,0 +1,88 @@
+; XO-CHIP: bit planes, register ranges, long addresses, scrolling up and audio.
+;
+;   A3 A2 A1 (5XY2, then 5XY3 backwards) | 5A (through I = #2000)
+;
+; Below: a box only left in plane 2 (`+`) after CLS on plane 1, a box in both planes (`@`)
+; scrolled up by 2, one box per plane overlapping, and a box drawn with PLANE 0, which draws
+; nothing.
+;
+; Sound doesn't show on the screen, the test checks the pattern and a pitch of 112 (8000 Hz).
+
+        JP main
+        include "print.asm"
+
+main:   ; 00E0 only clears the selected planes
+        PLANE 3
+        LD I, two_planes
+        LD V1, 56
+        LD V2, 24
+        DRW V1, V2, 4
+        PLANE 1
+        CLS
+
+        ; 00DN on both planes
+        PLANE 3
+        LD V1, 4
+        LD V2, 26
+        DRW V1, V2, 4
+        SCU 2
+
+        ; One box per plane
+        LD I, box
+        PLANE 1
+        LD V1, 24
+        LD V2, 16
+        DRW V1, V2, 4
+        PLANE 2
+        LD V1, 28
+        LD V2, 18
+        DRW V1, V2, 4
+        PLANE 0
+        LD V1, 40
+        DRW V1, V2, 4
+        PLANE 1
+
+        ; 5XY2, 5XY3
+        LD V1, #A1
+        LD V2, #A2
+        LD V3, #A3
+        LD I, scratch
+        SAVE V1 - V3
+        LOAD V6 - V4
+
+        ; F000 NNNN
+        LD I, LONG #2000
+        LD V0, #5A
+        LD [I], V0
+        LD I, LONG #2000
+        LD V0, 0
+        LD V0, [I]
+        LD V1, V0
+
+        ; F002, FX3A
+        LD I, pattern
+        AUDIO
+        LD V2, 112
+        PITCH V2
+
+        LD VD, 0
+        LD VE, 0
+        LD V0, V4
+        CALL print
+        LD V0, V5
+        CALL print
+        LD V0, V6
+        CALL print
+        LD V0, V1
+        CALL print
+
+halt:   JP halt
+
+box:    db #FF, #81, #81, #FF
+two_planes:
+        db #FF, #81, #81, #FF
+        db #FF, #FF, #FF, #FF
+scratch:
+        db 0, 0, 0
+pattern:
+        db #FF, #00, #FF, #00, #FF, #00, #FF, #00, #FF, #00, #FF, #00, #FF, #00, #FF, #00
@@ -0,0 +1,103 @@
+use chip8_core::*;
+
+mod common;
+
+// The memory test ROM halfway through, so RAM, registers, stack and display all hold something
+fn running() -> Emu {
+    let (mut emu, _) = common::boot("memory", Emu::for_platform(Platform::XoChip));
+    for _ in 0..200 {
+        emu.tick().unwrap();
+    }
+    emu.keypress(0x7, true);
+    emu.tick_timers();
+    emu
+}
+
+#[test]
+fn loading_restores_everything() {
+    let mut emu = running();
+    let state = emu.save_state();
+    let display = emu.get_display().to_vec();
+    let (pc, i, v, stack) = (
+        emu.pc(),
+        emu.i_reg(),
+        emu.registers().to_vec(),
+        emu.stack().to_vec(),
+    );
+
+    for _ in 0..500 {
+        emu.tick().unwrap();
+        emu.tick_timers();
+    }
+    emu.keypress(0x7, false);
+    assert_ne!(emu.save_state(), state);
+
+    emu.load_state(&state).unwrap();
+    assert_eq!(emu.save_state(), state);
+    assert_eq!(emu.get_display(), &display[..]);
+    assert_eq!(emu.pc(), pc);
+    assert_eq!(emu.i_reg(), i);
+    assert_eq!(emu.registers(), &v[..]);
+    assert_eq!(emu.stack(), &stack[..]);
+
+    // Into a machine that was never run, with another platform and memory size
+    let mut other = Emu::new();
+    other.load_state(&state).unwrap();
+    assert_eq!(other.save_state(), state);
+    assert_eq!(other.ram_size(), XO_RAM_SIZE);
+}
+
+// `load_state` must fail with `expected` and leave `emu` as it was
+fn rejects(data: &[u8], expected: StateError) {
+    let mut emu = running();
+    let before = emu.save_state();
+    assert_eq!(emu.load_state(data), Err(expected));
+    assert_eq!(emu.save_state(), before);
+}
+
+#[test]
+fn rejects_other_files() {
+    let mut state = running().save_state();
+    state[..4].copy_from_slice(b"C8TR");
+    rejects(&state, StateError::BadMagic);
+    rejects(b"C8", StateError::BadMagic);
+}
+
+#[test]
+fn rejects_other_versions() {
+    // A state from before the timing was saved is turned down before its payload is looked at
+    let mut state = running().save_state();
+    state[4..6].copy_from_slice(&1u16.to_le_bytes());
+    rejects(&state, StateError::UnsupportedVersion(1));
+
+    state[4..6].copy_from_slice(&(STATE_VERSION + 1).to_le_bytes());
+    rejects(&state, StateError::UnsupportedVersion(STATE_VERSION + 1));
+}
+
+#[test]
+fn rejects_truncated_states() {
+    let state = running().save_state();
+    rejects(&state[..state.len() - 1], StateError::Truncated);
+    rejects(&state[..12], StateError::Truncated);
+
+    // A length that claims more payload than there is
+    let mut longer = state.clone();
+    let len = u32::from_le_bytes(longer[6..10].try_into().unwrap());
+    longer[6..10].copy_from_slice(&(len + 1).to_le_bytes());
+    rejects(&longer, StateError::Truncated);
+    longer[6..10].copy_from_slice(&u32::MAX.to_le_bytes());
+    rejects(&longer, StateError::Truncated);
+}
+
+#[test]
+fn rejects_corrupted_states() {
+    let state = running().save_state();
+
+    let mut bad_crc = state.clone();
+    *bad_crc.last_mut().unwrap() ^= 0x01;
+    rejects(&bad_crc, StateError::ChecksumMismatch);
+
+    let mut bad_payload = state.clone();
+    bad_payload[100] ^= 0x80;
+    rejects(&bad_payload, StateError::ChecksumMismatch);
+}
@@ -0,0 +1,88 @@
+use chip8_core::*;
+use std::time::Duration;
+
+// A machine that loops forever with the delay timer counting down from 255
+fn looping() -> Emu {
+    let mut emu = Emu::new();
+    emu.load(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04]).unwrap();
+    emu
+}
+
+#[test]
+fn same_speed_on_any_refresh_rate() {
+    for hz in [30, 60, 75, 144, 240] {
+        let mut emu = looping();
+        let mut scheduler = Scheduler::new(700);
+        let mut total = Progress::default();
+        let mut elapsed = Duration::ZERO;
+        let frame = Duration::from_secs(1) / hz;
+        for _ in 0..hz * 3 {
+            let progress = scheduler.run_for(&mut emu, frame).unwrap();
+            total.instructions += progress.instructions;
+            total.frames += progress.frames;
+            elapsed += frame;
+        }
+
+        // `frame` is rounded down to whole nanoseconds, so a fraction of a tick may be missing
+        let seconds = elapsed.as_secs_f64();
+        assert!(seconds <= 3.0, "{} Hz", hz);
+        assert!(
+            total.instructions.abs_diff(2100) <= 1,
+            "{} Hz: {:?}",
+            hz,
+            total
+        );
+        assert!(total.frames.abs_diff(180) <= 1, "{} Hz: {:?}", hz, total);
+    }
+}
+
+#[test]
+fn dropped_frames_catch_up() {
+    let mut emu = looping();
+    let mut scheduler = Scheduler::default();
+    scheduler.run_frame(&mut emu).unwrap();
+    let progress = scheduler
+        .run_for(&mut emu, Duration::from_millis(100))
+        .unwrap();
+    assert_eq!(progress.frames, 6);
+    assert_eq!(progress.instructions, 60);
+    // One tick at the end of the first frame, right after `FX15`, and 6 more
+    assert_eq!(emu.delay_timer(), 255 - 7);
+
+    // Beyond `MAX_CATCH_UP` the lost time is forgotten
+    let progress = scheduler
+        .run_for(&mut emu, Duration::from_secs(10))
+        .unwrap();
+    assert_eq!(progress.frames, 15);
+}
+
+#[test]
+fn stops_when_the_program_exits() {
+    let mut emu = Emu::new();
+    emu.load(&[0x00, 0xE0, 0x00, 0xFD, 0x12, 0x00]).unwrap();
+    let progress = Scheduler::default().run_frame(&mut emu).unwrap();
+    assert!(progress.exited);
+    assert_eq!(progress.instructions, 2);
+}
+
+#[test]
+fn a_callback_for_every_frame() {
+    let mut emu = looping();
+    let mut scheduler = Scheduler::default();
+    let mut timers = Vec::new();
+    let progress = scheduler
+        .run_for_each_frame(&mut emu, Duration::from_millis(100), |emu| {
+            timers.push(emu.delay_timer())
+        })
+        .unwrap();
+    // Called after each timer tick, not once for the whole call
+    assert_eq!(progress.frames, 6);
+    assert_eq!(timers, [254, 253, 252, 251, 250, 249]);
+
+    // Not at all when no frame ended
+    let mut calls = 0;
+    scheduler
+        .run_for_each_frame(&mut emu, Duration::from_millis(5), |_| calls += 1)
+        .unwrap();
+    assert_eq!(calls, 0);
+}
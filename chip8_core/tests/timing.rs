@@ -0,0 +1,100 @@
+use chip8_core::*;
+use std::time::Duration;
+
+fn vip(rom: &[u8]) -> Emu {
+    let mut emu = Emu::for_platform(Platform::CosmacVip);
+    emu.set_timing(Timing::CosmacVip);
+    emu.load(rom).unwrap();
+    emu
+}
+
+#[test]
+fn timers_follow_the_interrupt() {
+    // Delay timer at 255, then loop forever
+    let rom = [0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04];
+    let mut emu = vip(&rom);
+    while emu.cycles() < 10 * VIP_CYCLES_PER_FRAME {
+        emu.tick().unwrap();
+    }
+    assert_eq!(emu.delay_timer(), 255 - 10);
+
+    // Nothing is counted with the default timing
+    let mut flat = Emu::new();
+    flat.load(&rom).unwrap();
+    for _ in 0..100 {
+        flat.tick().unwrap();
+    }
+    assert_eq!(flat.cycles(), 0);
+    assert_eq!(flat.delay_timer(), 0xFF);
+}
+
+#[test]
+fn draw_waits_for_the_next_frame() {
+    // Two draws of the same 5 row digit, the second one not on a byte boundary
+    let rom = [0x60, 0x03, 0xD1, 0x15, 0xD0, 0x15, 0x12, 0x06];
+    let mut emu = vip(&rom);
+    emu.tick().unwrap();
+    let start = emu.cycles();
+    emu.tick().unwrap();
+    let first = emu.cycles();
+    emu.tick().unwrap();
+    let second = emu.cycles();
+
+    // Each one starts after an interrupt and costs more than a frame of plain instructions
+    assert!(
+        first > VIP_CYCLES_PER_FRAME + VIP_INTERRUPT_CYCLES,
+        "{}",
+        first
+    );
+    assert!(
+        second > 2 * VIP_CYCLES_PER_FRAME + VIP_INTERRUPT_CYCLES,
+        "{}",
+        second
+    );
+    assert_eq!(
+        first / VIP_CYCLES_PER_FRAME - start / VIP_CYCLES_PER_FRAME,
+        1
+    );
+    // The unaligned sprite costs more to draw
+    assert!(second % VIP_CYCLES_PER_FRAME > first % VIP_CYCLES_PER_FRAME);
+}
+
+#[test]
+fn scheduler_runs_at_the_vip_clock() {
+    let mut emu = vip(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04]);
+    let mut scheduler = Scheduler::default();
+    let mut total = Progress::default();
+    for _ in 0..12 {
+        let progress = scheduler
+            .run_for(&mut emu, Duration::from_millis(250))
+            .unwrap();
+        total.instructions += progress.instructions;
+        total.frames += progress.frames;
+    }
+
+    // 3 seconds of a 1.7609 MHz 1802 with 8 clocks per machine cycle
+    let cycles = 3 * VIP_CLOCK_HZ / VIP_CLOCKS_PER_CYCLE;
+    // The last instruction may have run into an interrupt, paid for on the next call
+    let overrun = emu.cycles() - cycles;
+    assert!(overrun < VIP_INTERRUPT_CYCLES + 100, "{}", emu.cycles());
+    assert_eq!(total.frames, emu.cycles() / VIP_CYCLES_PER_FRAME);
+    assert!(total.frames.abs_diff(180) <= 1, "{:?}", total);
+    assert_eq!(emu.delay_timer() as u64, 255 - total.frames);
+}
+
+#[test]
+fn cycles_survive_save_states() {
+    let mut emu = vip(&[0x60, 0xFF, 0xF0, 0x15, 0x12, 0x04]);
+    for _ in 0..500 {
+        emu.tick().unwrap();
+    }
+    let state = emu.save_state();
+
+    let mut restored = Emu::new();
+    restored.load_state(&state).unwrap();
+    assert_eq!(restored.timing(), Timing::CosmacVip);
+    assert_eq!(restored.cycles(), emu.cycles());
+
+    restored.reset();
+    assert_eq!(restored.cycles(), 0);
+}
@@ -0,0 +1,61 @@
+use chip8_core::trace::{self, Format, TraceEntry, TraceReader, Tracer};
+use chip8_core::*;
+
+mod common;
+
+// Trace the first `ticks` instructions of a test ROM
+fn record(rom: &str, quirks: Quirks, format: Format, ticks: usize) -> Vec<u8> {
+    let (mut emu, _) = common::boot(rom, Emu::with_quirks(quirks));
+
+    let mut tracer = Tracer::new(Vec::new(), format, None);
+    for _ in 0..ticks {
+        tracer.tick(&mut emu).unwrap();
+    }
+    tracer.finish().unwrap()
+}
+
+fn read(trace: &[u8]) -> Vec<TraceEntry> {
+    TraceReader::new(trace)
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn text_and_binary_traces_read_back_the_same() {
+    let text = read(&record("memory", Quirks::default(), Format::Text, 500));
+    let binary = read(&record("memory", Quirks::default(), Format::Binary, 500));
+    assert_eq!(text.len(), 500);
+    assert_eq!(text, binary);
+    assert!(text.iter().enumerate().all(|(n, e)| e.cycle == n as u64));
+}
+
+#[test]
+fn compare_finds_the_first_divergence() {
+    let vf_reset = Quirks {
+        logic_resets_vf: true,
+        ..Quirks::default()
+    };
+    let a = read(&record("logic", Quirks::default(), Format::Text, 200));
+    let b = read(&record("logic", vf_reset, Format::Text, 200));
+
+    let (entry, mismatches) = a
+        .iter()
+        .zip(&b)
+        .map(|(a, b)| (a, trace::compare(a, b, &[])))
+        .find(|(_, mismatches)| !mismatches.is_empty())
+        .unwrap();
+    assert_eq!(
+        entry.instruction,
+        Instruction::Or { x: 1, y: 2 },
+        "{}",
+        entry
+    );
+    assert_eq!(mismatches.len(), 1);
+    assert_eq!(mismatches[0].field, "VF after");
+    assert_eq!((&*mismatches[0].left, &*mismatches[0].right), ("55", "00"));
+
+    // With VF left out the two entries agree
+    let ignore = ["VF".to_string()];
+    assert!(trace::compare(entry, &b[entry.cycle as usize], &ignore).is_empty());
+}
@@ -0,0 +1,83 @@
+mod protocol;
+mod session;
+
+use session::Session;
+use std::env;
+use std::io::{self, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::ExitCode;
+use std::sync::mpsc;
+use std::thread;
+
+const USAGE: &str = "Usage: dap [--port PORT]";
+
+/*
+ * A Debug Adapter Protocol server, for debugging ROMs (or assembly sources, with breakpoints on
+ * their lines) from any editor that speaks DAP. By default it talks over stdin/stdout, which is
+ * how editors usually start adapters. With `--port` it listens on localhost instead and serves one
+ * client after another, which is handy to attach to from an editor that's already running.
+ *
+ * Everything the client needs to know goes through the protocol: stdout is the channel itself,
+ * so errors are only ever printed on stderr.
+ */
+fn main() -> ExitCode {
+    let mut port = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next().map(|s| s.parse::<u16>()) {
+                Some(Ok(p)) => port = Some(p),
+                _ => return fail("--port needs a port number"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(port) = port else {
+        return match serve(io::stdin(), io::stdout()) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => fail(&err.to_string()),
+        };
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(err) => return fail(&format!("unable to listen on port {}: {}", port, err)),
+    };
+    eprintln!("Listening on 127.0.0.1:{} for a DAP client", port);
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| serve(stream.try_clone()?, stream));
+        if let Err(err) = result {
+            eprintln!("dap: {}", err);
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/*
+ * Requests are read on their own thread and queued, so a running program can keep going between
+ * them and still notice a `pause` as soon as it arrives.
+ */
+fn serve(input: impl Read + Send + 'static, output: impl Write) -> io::Result<()> {
+    let (requests, queue) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(input);
+        while let Ok(Some(message)) = protocol::read_message(&mut reader) {
+            if requests.send(message).is_err() {
+                break;
+            }
+        }
+    });
+    Session::new(output).run(queue)
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("dap: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
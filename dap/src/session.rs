@@ -0,0 +1,866 @@
+use chip8_core::asm::{self, Assembly, SourceLine};
+use chip8_core::debug::{Debugger, StepTarget, StopReason};
+use chip8_core::disasm::Syntax;
+use chip8_core::{Emu, Instruction, Platform, START_ADDR};
+use serde_json::{Value, json};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::io::{self, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::protocol::{base64, write_message};
+
+// Same speed as the desktop frontend while the program runs
+const FRAME: Duration = Duration::from_micros(16_667);
+const TICKS_PER_FRAME: usize = 10;
+
+// The CHIP-8 has a single thread of execution, so it always has this id
+const THREAD_ID: i64 = 1;
+
+// `variablesReference` of the only scope, the registers
+const REGISTERS_REF: i64 = 1;
+
+// How the program is running, checked after every frame
+#[derive(Clone, Copy)]
+enum Run {
+    Continue,
+    // Stepping over a call or out of a subroutine
+    To(StepTarget),
+}
+
+/*
+ * One debugging session: the launched program, its debug info if it was assembled from source,
+ * and the breakpoints the client asked for.
+ *
+ * Requests are answered in order. While the program runs it advances a frame at a time between
+ * requests, so `pause` (or new breakpoints) take effect at the next frame.
+ */
+pub struct Session<W: Write> {
+    out: W,
+    seq: i64,
+    debugger: Option<Debugger>,
+    assembly: Option<Assembly>,
+    // Addresses of the breakpoints set on each source file, by canonical path
+    source_breakpoints: HashMap<PathBuf, Vec<u16>>,
+    instruction_breakpoints: Vec<u16>,
+    stop_on_entry: bool,
+    running: Option<Run>,
+    // Events to send once the response to the current request is out
+    events: Vec<Value>,
+    done: bool,
+}
+
+impl<W: Write> Session<W> {
+    pub fn new(out: W) -> Self {
+        Session {
+            out,
+            seq: 0,
+            debugger: None,
+            assembly: None,
+            source_breakpoints: HashMap::new(),
+            instruction_breakpoints: Vec::new(),
+            stop_on_entry: false,
+            running: None,
+            events: Vec::new(),
+            done: false,
+        }
+    }
+
+    // Serve requests until the client disconnects or closes the stream
+    pub fn run(mut self, requests: Receiver<Value>) -> io::Result<()> {
+        let mut next_frame = Instant::now();
+        while !self.done {
+            if self.running.is_none() {
+                match requests.recv() {
+                    Ok(message) => self.handle(message)?,
+                    Err(_) => return Ok(()),
+                }
+                next_frame = Instant::now();
+                continue;
+            }
+
+            loop {
+                match requests.try_recv() {
+                    Ok(message) => self.handle(message)?,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => return Ok(()),
+                }
+            }
+            self.run_frame()?;
+
+            next_frame += FRAME;
+            let now = Instant::now();
+            if next_frame > now {
+                thread::sleep(next_frame - now);
+            } else {
+                next_frame = now;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, message: Value) -> io::Result<()> {
+        if message["type"] != "request" {
+            return Ok(());
+        }
+        let command = message["command"].as_str().unwrap_or_default().to_string();
+        let mut response = json!({
+            "type": "response",
+            "request_seq": message["seq"],
+            "command": command,
+        });
+        match self.request(&command, &message["arguments"]) {
+            Ok(body) => {
+                response["success"] = json!(true);
+                response["body"] = body;
+            }
+            Err(err) => {
+                response["success"] = json!(false);
+                response["message"] = json!(err);
+            }
+        }
+
+        self.send(response)?;
+        self.flush_events()
+    }
+
+    fn request(&mut self, command: &str, args: &Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsInstructionBreakpoints": true,
+                "supportsReadMemoryRequest": true,
+                "supportsDisassembleRequest": true,
+                "supportsSetVariable": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(args),
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setInstructionBreakpoints" => self.set_instruction_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({ "breakpoints": [] })),
+            "configurationDone" => self.configuration_done(),
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "CHIP-8" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({
+                "scopes": [{
+                    "name": "Registers",
+                    "presentationHint": "registers",
+                    "variablesReference": REGISTERS_REF,
+                    "expensive": false,
+                }]
+            })),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "continue" => {
+                self.resume(Run::Continue)?;
+                Ok(json!({ "allThreadsContinued": true }))
+            }
+            "next" => self.next(),
+            "stepIn" => {
+                let reason = self.debugger_mut()?.step();
+                self.stopped(reason);
+                Ok(Value::Null)
+            }
+            "stepOut" => {
+                // At the top level there's nothing to return from, so it runs like `continue`
+                let run = self
+                    .debugger_mut()?
+                    .step_out_target()
+                    .map_or(Run::Continue, Run::To);
+                self.resume(run)?;
+                Ok(Value::Null)
+            }
+            "pause" => {
+                if self.running.take().is_some() {
+                    self.stopped_event("pause", None);
+                }
+                Ok(Value::Null)
+            }
+            "readMemory" => self.read_memory(args),
+            "disassemble" => self.disassemble(args),
+            "disconnect" | "terminate" => {
+                self.done = true;
+                Ok(Value::Null)
+            }
+            _ => Err(format!("unsupported request '{}'", command)),
+        }
+    }
+
+    /*
+     * `program` is either a ROM or an assembly source (`.asm` or `.s`), which gets assembled so
+     * breakpoints can be set on its lines and the call stack shows where each frame is in it.
+     * Optional: `platform` (vip, chip48, schip, xochip), `origin` and `stopOnEntry`.
+     */
+    fn launch(&mut self, args: &Value) -> Result<Value, String> {
+        let program = args["program"]
+            .as_str()
+            .ok_or("launch needs the path of a 'program'")?;
+        // Plain `Emu::new()` quirks unless a platform is asked for, like the other frontends
+        let platform: Option<Platform> = match args["platform"].as_str() {
+            Some(name) => Some(name.parse()?),
+            None => None,
+        };
+        let origin = match &args["origin"] {
+            Value::Null => START_ADDR,
+            value => value
+                .as_u64()
+                .and_then(|origin| u16::try_from(origin).ok())
+                .ok_or("'origin' must be an address")?,
+        };
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or(false);
+
+        let path = Path::new(program);
+        let is_source = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("asm" | "s")
+        );
+        let rom = if is_source {
+            let assembly = asm::assemble_file(path, origin).map_err(|err| err.to_string())?;
+            let rom = assembly.bytes.clone();
+            self.assembly = Some(assembly);
+            rom
+        } else {
+            self.assembly = None;
+            fs::read(path).map_err(|err| format!("unable to read {}: {}", program, err))?
+        };
+
+        let mut emu = platform.map_or_else(Emu::new, Emu::for_platform);
+        emu.load_program_at(origin, &rom)
+            .map_err(|err| format!("unable to load {}: {}", program, err))?;
+        self.debugger = Some(Debugger::new(emu));
+        self.update_breakpoints();
+
+        // Now the client can send its breakpoints, then `configurationDone`
+        self.event("initialized", json!({}));
+        Ok(Value::Null)
+    }
+
+    fn configuration_done(&mut self) -> Result<Value, String> {
+        if self.stop_on_entry {
+            self.emu()?;
+            self.stopped_event("entry", None);
+        } else {
+            self.resume(Run::Continue)?;
+        }
+        Ok(Value::Null)
+    }
+
+    /*
+     * A breakpoint on a line without code moves down to the next line that has some, like most
+     * debuggers do, and the reply tells the client where it ended up.
+     */
+    fn set_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let path = args["source"]["path"]
+            .as_str()
+            .ok_or("setBreakpoints needs a source path")?;
+        let path = canonical(Path::new(path));
+        let lines: Vec<u64> = args["breakpoints"]
+            .as_array()
+            .map(|bps| bps.iter().filter_map(|bp| bp["line"].as_u64()).collect())
+            .unwrap_or_default();
+
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for line in lines {
+            match self.addr_for_line(&path, line as usize) {
+                Some((addr, line)) => {
+                    addrs.push(addr);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "line": line,
+                        "instructionReference": format_addr(addr as usize),
+                    }));
+                }
+                None => {
+                    let message = match self.assembly {
+                        Some(_) => "no code on or after this line",
+                        None => "no debug info, launch the assembly source to break on lines",
+                    };
+                    breakpoints.push(json!({
+                        "verified": false,
+                        "line": line,
+                        "message": message,
+                    }));
+                }
+            }
+        }
+
+        self.source_breakpoints.insert(path, addrs);
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn set_instruction_breakpoints(&mut self, args: &Value) -> Result<Value, String> {
+        let mut addrs = Vec::new();
+        let mut breakpoints = Vec::new();
+        for bp in args["breakpoints"].as_array().into_iter().flatten() {
+            let addr = bp["instructionReference"]
+                .as_str()
+                .and_then(parse_addr)
+                .map(|addr| addr + bp["offset"].as_i64().unwrap_or(0))
+                .and_then(|addr| u16::try_from(addr).ok());
+            match addr {
+                Some(addr) => {
+                    addrs.push(addr);
+                    breakpoints.push(json!({
+                        "verified": true,
+                        "instructionReference": format_addr(addr as usize),
+                    }));
+                }
+                None => breakpoints.push(json!({
+                    "verified": false,
+                    "message": "not an address",
+                })),
+            }
+        }
+
+        self.instruction_breakpoints = addrs;
+        self.update_breakpoints();
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    // The debugger only knows addresses, so it gets every breakpoint again after each change
+    fn update_breakpoints(&mut self) {
+        let Some(debugger) = self.debugger.as_mut() else {
+            return;
+        };
+        debugger.clear_breakpoints();
+        for &addr in self
+            .source_breakpoints
+            .values()
+            .flatten()
+            .chain(&self.instruction_breakpoints)
+        {
+            debugger.add_breakpoint(addr);
+        }
+    }
+
+    // The current instruction first, then one frame per return address on the stack
+    fn stack_trace(&self) -> Result<Value, String> {
+        let emu = self.emu()?;
+        let mut frames = vec![self.frame(0, emu.pc())];
+        for (id, ret) in emu.stack().iter().rev().enumerate() {
+            // The frame is at the call, which is the instruction before the return address
+            frames.push(self.frame(id + 1, ret.wrapping_sub(2)));
+        }
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn frame(&self, id: usize, addr: u16) -> Value {
+        let mut frame = json!({
+            "id": id,
+            "name": self.routine_name(addr),
+            "line": 0,
+            "column": 0,
+            "instructionPointerReference": format_addr(addr as usize),
+        });
+        if let Some(line) = self.source_line(addr) {
+            let name = line.file.file_name().map(|name| name.to_string_lossy());
+            frame["source"] = json!({ "name": name, "path": line.file });
+            frame["line"] = json!(line.line);
+            frame["column"] = json!(1);
+        }
+        frame
+    }
+
+    // The closest label at or before `addr`, or the address itself without debug info
+    fn routine_name(&self, addr: u16) -> String {
+        let label = self.assembly.as_ref().and_then(|assembly| {
+            assembly
+                .labels
+                .iter()
+                .filter(|(_, label_addr)| **label_addr <= addr)
+                .max_by_key(|(_, label_addr)| **label_addr)
+        });
+        match label {
+            Some((name, label_addr)) if *label_addr == addr => name.clone(),
+            Some((name, label_addr)) => format!("{}+{:#X}", name, addr - label_addr),
+            None => format_addr(addr as usize),
+        }
+    }
+
+    fn variables(&self, args: &Value) -> Result<Value, String> {
+        if args["variablesReference"].as_i64() != Some(REGISTERS_REF) {
+            return Ok(json!({ "variables": [] }));
+        }
+
+        let emu = self.emu()?;
+        let mut variables: Vec<Value> = emu
+            .registers()
+            .iter()
+            .enumerate()
+            .map(|(x, value)| variable(&format!("V{:X}", x), format!("{:#04X}", value), None))
+            .collect();
+        variables.push(variable(
+            "I",
+            format!("{:#06X}", emu.i_reg()),
+            Some(emu.i_reg()),
+        ));
+        variables.push(variable("PC", format!("{:#06X}", emu.pc()), Some(emu.pc())));
+        variables.push(variable("SP", emu.stack().len().to_string(), None));
+        variables.push(variable("DT", emu.delay_timer().to_string(), None));
+        variables.push(variable("ST", emu.sound_timer().to_string(), None));
+        Ok(json!({ "variables": variables }))
+    }
+
+    // Every register but SP can be changed, values are decimal or 0x-prefixed hex
+    fn set_variable(&mut self, args: &Value) -> Result<Value, String> {
+        let name = args["name"]
+            .as_str()
+            .unwrap_or_default()
+            .to_ascii_uppercase();
+        let value = args["value"]
+            .as_str()
+            .and_then(parse_addr)
+            .and_then(|value| u16::try_from(value).ok())
+            .ok_or("not a valid value")?;
+        let byte = || u8::try_from(value).map_err(|_| format!("{} only holds a byte", name));
+
+        let emu = self.debugger_mut()?.emu_mut();
+        let shown = match name.as_str() {
+            "I" => {
+                emu.set_i_reg(value);
+                format!("{:#06X}", value)
+            }
+            "PC" => {
+                emu.set_pc(value);
+                format!("{:#06X}", value)
+            }
+            "DT" => {
+                emu.set_delay_timer(byte()?);
+                value.to_string()
+            }
+            "ST" => {
+                emu.set_sound_timer(byte()?);
+                value.to_string()
+            }
+            _ => {
+                let x = name
+                    .strip_prefix('V')
+                    .and_then(|x| usize::from_str_radix(x, 16).ok())
+                    .filter(|x| *x < 16)
+                    .ok_or(format!("{} can't be changed", name))?;
+                emu.set_register(x, byte()?);
+                format!("{:#04X}", value)
+            }
+        };
+        Ok(json!({ "value": shown }))
+    }
+
+    // Step over: a call runs until it returns, anything else is a single step
+    fn next(&mut self) -> Result<Value, String> {
+        let debugger = self.debugger_mut()?;
+        match debugger.step_over_target() {
+            Some(target) => self.resume(Run::To(target))?,
+            None => {
+                let reason = debugger.step();
+                self.stopped(reason);
+            }
+        }
+        Ok(Value::Null)
+    }
+
+    fn resume(&mut self, run: Run) -> Result<(), String> {
+        self.emu()?;
+        self.running = Some(run);
+        Ok(())
+    }
+
+    fn run_frame(&mut self) -> io::Result<()> {
+        let (Some(debugger), Some(run)) = (self.debugger.as_mut(), self.running) else {
+            self.running = None;
+            return Ok(());
+        };
+        let reason = match run {
+            Run::Continue => debugger.run(TICKS_PER_FRAME),
+            Run::To(target) => debugger.run_to(target, TICKS_PER_FRAME),
+        };
+        debugger.emu_mut().tick_timers();
+
+        if reason != StopReason::Limit {
+            self.stopped(reason);
+            self.flush_events()?;
+        }
+        Ok(())
+    }
+
+    fn stopped(&mut self, reason: StopReason) {
+        self.running = None;
+        match reason {
+            StopReason::Exited => {
+                self.event("exited", json!({ "exitCode": 0 }));
+                self.event("terminated", json!({}));
+            }
+            StopReason::Breakpoint(_) => self.stopped_event("breakpoint", None),
+            StopReason::Error(_) => self.stopped_event("exception", Some(reason.to_string())),
+            StopReason::Watchpoint { .. } | StopReason::RegisterChanged { .. } => {
+                self.stopped_event("data breakpoint", Some(reason.to_string()))
+            }
+            StopReason::Done | StopReason::Limit => self.stopped_event("step", None),
+        }
+    }
+
+    fn stopped_event(&mut self, reason: &str, text: Option<String>) {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(text) = text {
+            body["description"] = json!(text);
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body);
+    }
+
+    // Bytes outside of RAM are left out and counted as unreadable
+    fn read_memory(&self, args: &Value) -> Result<Value, String> {
+        let ram = self.emu()?.ram();
+        let base = args["memoryReference"]
+            .as_str()
+            .and_then(parse_addr)
+            .ok_or("not a memory reference")?;
+        let start = (base + args["offset"].as_i64().unwrap_or(0)).clamp(0, ram.len() as i64);
+        let start = start as usize;
+        let count = args["count"].as_u64().unwrap_or(0) as usize;
+        let end = start.saturating_add(count).min(ram.len());
+
+        Ok(json!({
+            "address": format_addr(start),
+            "data": base64(&ram[start..end]),
+            "unreadableBytes": count - (end - start),
+        }))
+    }
+
+    /*
+     * Instructions don't have a fixed size (`F000 NNNN` takes four bytes) so there's no reliable
+     * way to disassemble backwards. Instructions before the reference are taken two bytes apart,
+     * the reference and everything after it are decoded properly.
+     */
+    fn disassemble(&self, args: &Value) -> Result<Value, String> {
+        let ram = self.emu()?.ram();
+        let base = args["memoryReference"]
+            .as_str()
+            .and_then(parse_addr)
+            .ok_or("not a memory reference")?
+            + args["offset"].as_i64().unwrap_or(0);
+        let first = args["instructionOffset"].as_i64().unwrap_or(0);
+        let count = args["instructionCount"].as_u64().unwrap_or(0) as usize;
+        let symbols = self.symbols();
+
+        let mut addr = base + first.min(0) * 2;
+        let mut skip = first.max(0);
+        let mut instructions = Vec::with_capacity(count);
+        while instructions.len() < count {
+            let decoded = usize::try_from(addr)
+                .ok()
+                .and_then(|addr| Some((addr, Instruction::decode_at(ram, addr)?)));
+            let Some((at, instruction)) = decoded else {
+                if skip > 0 {
+                    skip -= 1;
+                } else {
+                    instructions.push(json!({
+                        "address": format_addr(addr.rem_euclid(0x10000) as usize),
+                        "instruction": "",
+                        "presentationHint": "invalid",
+                    }));
+                }
+                addr += 2;
+                continue;
+            };
+
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                let mut entry = json!({
+                    "address": format_addr(at),
+                    "instructionBytes": ram[at..at + instruction.size()]
+                        .iter()
+                        .map(|b| format!("{:02X}", b))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                    "instruction": instruction.format(Syntax::Cowgod, &symbols),
+                });
+                if let Some(symbol) = symbols.get(&(at as u16)) {
+                    entry["symbol"] = json!(symbol);
+                }
+                if let Some(line) = self.source_line(at as u16) {
+                    entry["location"] = json!({ "path": line.file });
+                    entry["line"] = json!(line.line);
+                }
+                instructions.push(entry);
+            }
+            addr += match addr >= base {
+                true => instruction.size() as i64,
+                false => 2,
+            };
+        }
+        Ok(json!({ "instructions": instructions }))
+    }
+
+    // Labels by address, the way the disassembler wants them
+    fn symbols(&self) -> BTreeMap<u16, String> {
+        let mut symbols = BTreeMap::new();
+        if let Some(assembly) = &self.assembly {
+            for (name, addr) in &assembly.labels {
+                symbols.entry(*addr).or_insert_with(|| name.clone());
+            }
+        }
+        symbols
+    }
+
+    // The source line that produced the instruction at `addr`, if it's part of the program
+    fn source_line(&self, addr: u16) -> Option<&SourceLine> {
+        let assembly = self.assembly.as_ref()?;
+        let offset = addr.checked_sub(assembly.origin)? as usize;
+        if offset >= assembly.bytes.len() {
+            return None;
+        }
+        assembly.line_for_addr(addr)
+    }
+
+    // The first address of the first line with code at or after `line` in `path`
+    fn addr_for_line(&self, path: &Path, line: usize) -> Option<(u16, usize)> {
+        self.assembly
+            .as_ref()?
+            .source_map
+            .iter()
+            .filter(|entry| entry.line >= line && canonical(&entry.file) == path)
+            .min_by_key(|entry| (entry.line, entry.addr))
+            .map(|entry| (entry.addr, entry.line))
+    }
+
+    fn emu(&self) -> Result<&Emu, String> {
+        match &self.debugger {
+            Some(debugger) => Ok(debugger.emu()),
+            None => Err("no program has been launched".to_string()),
+        }
+    }
+
+    fn debugger_mut(&mut self) -> Result<&mut Debugger, String> {
+        self.debugger
+            .as_mut()
+            .ok_or("no program has been launched".to_string())
+    }
+
+    fn event(&mut self, event: &str, body: Value) {
+        self.events.push(json!({
+            "type": "event",
+            "event": event,
+            "body": body,
+        }));
+    }
+
+    fn flush_events(&mut self) -> io::Result<()> {
+        for event in mem::take(&mut self.events) {
+            self.send(event)?;
+        }
+        Ok(())
+    }
+
+    fn send(&mut self, mut message: Value) -> io::Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.out, &message)
+    }
+}
+
+fn variable(name: &str, value: String, memory: Option<u16>) -> Value {
+    let mut variable = json!({
+        "name": name,
+        "value": value,
+        "variablesReference": 0,
+    });
+    if let Some(addr) = memory {
+        variable["memoryReference"] = json!(format_addr(addr as usize));
+    }
+    variable
+}
+
+// Paths from the client and from the assembler are compared once both are canonical
+fn canonical(path: &Path) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+fn format_addr(addr: usize) -> String {
+    format!("{:#05X}", addr)
+}
+
+// Memory references and values: decimal, or hex with a 0x prefix
+fn parse_addr(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::read_message;
+    use std::collections::VecDeque;
+    use std::env;
+    use std::io::{BufReader, Read};
+    use std::sync::mpsc::{self, Sender};
+
+    const PROGRAM: &str = "        LD V0, 5
+loop:   CALL sub
+        JP loop
+
+; Counts the calls in V0
+sub:    ADD V0, 1
+        RET
+";
+
+    // What the session writes, handed over to the client as it's written
+    struct Pipe(Sender<Vec<u8>>);
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let _ = self.0.send(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    struct PipeReader {
+        chunks: Receiver<Vec<u8>>,
+        pending: VecDeque<u8>,
+    }
+
+    impl Read for PipeReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.pending.is_empty() {
+                // A session that stops answering fails the test instead of hanging it
+                match self.chunks.recv_timeout(Duration::from_secs(5)) {
+                    Ok(chunk) => self.pending.extend(chunk),
+                    Err(_) => return Ok(0),
+                }
+            }
+            self.pending.read(buf)
+        }
+    }
+
+    // The editor's side: requests go in, responses come back in order, events are kept for later
+    struct Client {
+        requests: Sender<Value>,
+        replies: BufReader<PipeReader>,
+        seq: i64,
+        events: VecDeque<Value>,
+    }
+
+    impl Client {
+        fn message(&mut self) -> Value {
+            read_message(&mut self.replies)
+                .unwrap()
+                .expect("the session stopped answering")
+        }
+
+        // The body of the response, which has to be a success
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let request = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            });
+            self.requests.send(request).unwrap();
+            loop {
+                let message = self.message();
+                if message["type"] == "event" {
+                    self.events.push_back(message);
+                    continue;
+                }
+                assert_eq!(message["request_seq"], self.seq, "{}", message);
+                assert_eq!(message["success"], true, "{}", message);
+                return message["body"].clone();
+            }
+        }
+
+        // The body of the next event, which has to be `event`
+        fn event(&mut self, event: &str) -> Value {
+            let message = match self.events.pop_front() {
+                Some(message) => message,
+                None => self.message(),
+            };
+            assert_eq!(message["event"], event, "{}", message);
+            message["body"].clone()
+        }
+    }
+
+    #[test]
+    fn break_on_a_line_and_look_around() {
+        let path = env::temp_dir().join(format!("dap-session-{}.asm", std::process::id()));
+        fs::write(&path, PROGRAM).unwrap();
+
+        let (requests, queue) = mpsc::channel();
+        let (output, chunks) = mpsc::channel();
+        let session = thread::spawn(move || Session::new(Pipe(output)).run(queue));
+        let mut client = Client {
+            requests,
+            replies: BufReader::new(PipeReader {
+                chunks,
+                pending: VecDeque::new(),
+            }),
+            seq: 0,
+            events: VecDeque::new(),
+        };
+
+        client.request("initialize", json!({ "adapterID": "chip8" }));
+        client.request("launch", json!({ "program": path }));
+        client.event("initialized");
+
+        // Line 4 is blank, the breakpoint moves down to the first instruction of `sub`
+        let body = client.request(
+            "setBreakpoints",
+            json!({
+                "source": { "path": path },
+                "breakpoints": [{ "line": 4 }],
+            }),
+        );
+        assert_eq!(body["breakpoints"][0]["verified"], true);
+        assert_eq!(body["breakpoints"][0]["line"], 6);
+
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+
+        // In `sub`, called from line 2
+        let frames = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        let frames = frames["stackFrames"].as_array().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0]["name"], "sub");
+        assert_eq!(frames[0]["line"], 6);
+        assert_eq!(frames[1]["name"], "loop");
+        assert_eq!(frames[1]["line"], 2);
+
+        let registers = |client: &mut Client| {
+            let body = client.request("variables", json!({ "variablesReference": REGISTERS_REF }));
+            body["variables"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| (v["name"].as_str().unwrap().to_string(), v["value"].clone()))
+                .collect::<HashMap<_, _>>()
+        };
+        let values = registers(&mut client);
+        assert_eq!(values["V0"], "0x05");
+        assert_eq!(values["SP"], "1");
+
+        // Once around the loop and back at the breakpoint
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+        assert_eq!(registers(&mut client)["V0"], "0x06");
+
+        client.request("disconnect", json!({}));
+        session.join().unwrap().unwrap();
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use chip8_core::Emu;
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::f32::consts::PI;
+use std::str::FromStr;
+
+/*
+ * The CHIP-8 only has a buzzer: it sounds while the sound timer is above zero, and that's it. The
+ * spec says nothing about what it sounds like, so the tone is up to us. These are the settings
+ * that can be changed.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AudioConfig {
+    // Pitch of the tone in Hz
+    pub frequency: f32,
+    // From 0.0 (silent) to 1.0 (full scale)
+    pub volume: f32,
+    pub waveform: Waveform,
+    pub muted: bool,
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        AudioConfig {
+            frequency: 440.0,
+            volume: 0.25,
+            waveform: Waveform::Square,
+            muted: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    Square,
+    Sine,
+    Triangle,
+    Sawtooth,
+}
+
+impl Waveform {
+    // Value of the wave at `phase` (from 0.0 to 1.0 over one period), between -1.0 and 1.0
+    fn sample(self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            _ => Err(format!(
+                "unknown waveform '{}' (expected square, sine, triangle or sawtooth)",
+                s
+            )),
+        }
+    }
+}
+
+/*
+ * SDL asks for audio from its own thread through this callback, filling the buffer a few
+ * milliseconds ahead of time. The main loop only flips `playing` (and updates the XO-CHIP pattern)
+ * once per frame through `AudioDevice::lock`, the callback takes care of generating the samples.
+ */
+struct Tone {
+    config: AudioConfig,
+    sample_rate: f32,
+    playing: bool,
+    // Position inside the current period (or inside the 128-bit XO-CHIP pattern), 0.0 to 1.0
+    phase: f32,
+    pattern: Option<[u8; 16]>,
+    pattern_rate: f32,
+}
+
+impl Tone {
+    fn next_sample(&mut self) -> f32 {
+        match self.pattern {
+            // XO-CHIP: play the pattern one bit at a time, at `pattern_rate` bits per second
+            Some(pattern) => {
+                let bit = (self.phase * 128.0) as usize % 128;
+                let on = pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+                self.phase = (self.phase + self.pattern_rate / 128.0 / self.sample_rate).fract();
+                if on { 1.0 } else { -1.0 }
+            }
+            None => {
+                let value = self.config.waveform.sample(self.phase);
+                self.phase = (self.phase + self.config.frequency / self.sample_rate).fract();
+                value
+            }
+        }
+    }
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.playing && !self.config.muted {
+                self.next_sample() * self.config.volume
+            } else {
+                0.0
+            };
+        }
+    }
+}
+
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+}
+
+impl Beeper {
+    pub fn new(audio: &AudioSubsystem, config: AudioConfig) -> Result<Self, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio.open_playback(None, &spec, |spec| Tone {
+            config,
+            sample_rate: spec.freq as f32,
+            playing: false,
+            phase: 0.0,
+            pattern: None,
+            pattern_rate: 4000.0,
+        })?;
+        device.resume();
+
+        Ok(Beeper { device })
+    }
+
+    // Called once per frame, after the timers were updated
+    pub fn update(&mut self, emu: &Emu) {
+        let mut tone = self.device.lock();
+        tone.playing = emu.beeper_active();
+        tone.pattern = emu.audio_pattern().copied();
+        tone.pattern_rate = emu.audio_sample_rate();
+    }
+
+    pub fn stop(&mut self) {
+        self.device.lock().playing = false;
+    }
+
+    // Returns whether the beeper is muted after the change
+    pub fn toggle_mute(&mut self) -> bool {
+        let mut tone = self.device.lock();
+        tone.config.muted = !tone.config.muted;
+        tone.config.muted
+    }
+}
@@ -0,0 +1,314 @@
+use crate::audio::Waveform;
+use chip8_core::{Platform, Quirks};
+use sdl2::pixels::Color;
+
+pub const USAGE: &str = "Usage: desktop [options] path/to/game.ch8
+
+Options:
+  --platform vip|chip48|schip|xochip  quirks and memory of the machine
+  --quirks vip|chip48|schip|xochip    only the quirks of a platform, with 4 KiB of RAM
+  --speed HZ|vip          instructions per second (default 600), or the speed of a COSMAC VIP
+  --scale N               size of each CHIP-8 pixel in the window (default 15)
+  --fullscreen            use the whole screen
+  --palette NAME|COLOURS  classic, amber, green or lcd, or 2 to 4 hex colours: 000000,ffffff
+  --mute                  start with the sound off (M toggles it)
+  --waveform square|sine|triangle|sawtooth  sound of the buzzer (default square)
+  --seed N                seed of the random generator (default: from the clock)
+  --paused                start paused (P toggles it)
+  --config FILE           per-ROM settings that override the database
+                          (default ~/.config/chip8/roms.json)
+  --no-database           don't look the ROM up to pick its platform, speed and colours
+  -h, --help              show this help
+
+Options given here win over the settings found for the ROM in the database.
+
+Keys: 1234/QWER/ASDF/ZXCV keypad, P pause, M mute, Backspace rewind,
+F1-F4 save state, Shift+F1-F4 load state.";
+
+pub const DEFAULT_SCALE: u32 = 15;
+// Anything bigger doesn't fit on any screen
+const MAX_SCALE: u32 = 64;
+
+/*
+ * Colours for each value a pixel can have. Classic CHIP-8 only uses the first two (background and
+ * foreground), XO-CHIP games can light the second bitplane (2) or both at once (3).
+ */
+pub type Palette = [Color; 4];
+
+pub const DEFAULT_PALETTE: Palette = PALETTES[0].1;
+
+const PALETTES: [(&str, Palette); 4] = [
+    (
+        "classic",
+        [
+            Color::RGB(0, 0, 0),
+            Color::RGB(255, 255, 255),
+            Color::RGB(170, 170, 170),
+            Color::RGB(85, 85, 85),
+        ],
+    ),
+    (
+        "amber",
+        [
+            Color::RGB(20, 12, 0),
+            Color::RGB(255, 176, 0),
+            Color::RGB(170, 110, 0),
+            Color::RGB(90, 55, 0),
+        ],
+    ),
+    (
+        "green",
+        [
+            Color::RGB(0, 20, 0),
+            Color::RGB(51, 255, 51),
+            Color::RGB(30, 160, 30),
+            Color::RGB(15, 90, 15),
+        ],
+    ),
+    (
+        "lcd",
+        [
+            Color::RGB(155, 188, 15),
+            Color::RGB(15, 56, 15),
+            Color::RGB(48, 98, 48),
+            Color::RGB(139, 172, 15),
+        ],
+    ),
+];
+
+// How fast the CPU runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speed {
+    InstructionsPerSecond(u64),
+    // Each instruction takes as long as on the COSMAC VIP, see `Timing::CosmacVip`
+    CosmacVip,
+}
+
+/*
+ * The settings the ROM database can also choose are optional, so `main` can tell what the user
+ * asked for from what's left to the database.
+ */
+pub struct Options {
+    pub rom: String,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub speed: Option<Speed>,
+    pub scale: u32,
+    pub fullscreen: bool,
+    pub palette: Option<Palette>,
+    pub mute: bool,
+    pub waveform: Waveform,
+    pub seed: Option<u64>,
+    pub paused: bool,
+    pub config: Option<String>,
+    pub database: bool,
+}
+
+impl Options {
+    // `None` when only the help was asked for
+    pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options {
+            rom: String::new(),
+            platform: None,
+            quirks: None,
+            speed: None,
+            scale: DEFAULT_SCALE,
+            fullscreen: false,
+            palette: None,
+            mute: false,
+            waveform: Waveform::Square,
+            seed: None,
+            paused: false,
+            config: None,
+            database: true,
+        };
+        let mut rom = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--platform" => options.platform = Some(value()?.parse()?),
+                "--quirks" => options.quirks = Some(value()?.parse::<Platform>()?.quirks()),
+                "--speed" => options.speed = Some(parse_speed(&value()?)?),
+                "--scale" => {
+                    let scale = value()?;
+                    options.scale = scale
+                        .parse()
+                        .ok()
+                        .filter(|scale| (1..=MAX_SCALE).contains(scale))
+                        .ok_or(format!(
+                            "bad scale '{}', expected 1 to {}",
+                            scale, MAX_SCALE
+                        ))?
+                }
+                "--fullscreen" => options.fullscreen = true,
+                "--palette" => options.palette = Some(parse_palette(&value()?)?),
+                "--mute" => options.mute = true,
+                "--waveform" => options.waveform = value()?.parse()?,
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|_| format!("bad seed '{}'", seed))?)
+                }
+                "--paused" => options.paused = true,
+                "--config" => options.config = Some(value()?),
+                "--no-database" => options.database = false,
+                "-h" | "--help" => return Ok(None),
+                _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        options.rom = rom.ok_or("missing ROM path")?;
+        Ok(Some(options))
+    }
+}
+
+fn parse_speed(s: &str) -> Result<Speed, String> {
+    if s.eq_ignore_ascii_case("vip") {
+        return Ok(Speed::CosmacVip);
+    }
+    match s.parse() {
+        Ok(hz) if hz > 0 => Ok(Speed::InstructionsPerSecond(hz)),
+        _ => Err(format!(
+            "bad speed '{}', expected instructions per second or vip",
+            s
+        )),
+    }
+}
+
+/*
+ * A palette by name, or its colours as `RRGGBB` separated by commas: background, foreground and
+ * the two XO-CHIP plane colours. Those two can be left out and are then taken from the classic
+ * palette.
+ */
+pub fn parse_palette(s: &str) -> Result<Palette, String> {
+    if let Some((_, palette)) = PALETTES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(s))
+    {
+        return Ok(*palette);
+    }
+
+    let colours: Vec<&str> = s.split(',').collect();
+    if !(2..=4).contains(&colours.len()) {
+        let names: Vec<&str> = PALETTES.iter().map(|(name, _)| *name).collect();
+        return Err(format!(
+            "unknown palette '{}' (expected {} or 2 to 4 hex colours)",
+            s,
+            names.join(", ")
+        ));
+    }
+    palette_from(&colours)
+}
+
+// The first colours of the classic palette replaced by `colours`, which can't be more than 4
+pub fn palette_from(colours: &[&str]) -> Result<Palette, String> {
+    let mut palette = DEFAULT_PALETTE;
+    for (slot, colour) in palette.iter_mut().zip(colours) {
+        *slot = parse_colour(colour)?;
+    }
+    Ok(palette)
+}
+
+// `RRGGBB`, with or without a leading `#`
+fn parse_colour(s: &str) -> Result<Color, String> {
+    let bad = || format!("bad colour '{}', expected RRGGBB", s);
+    let hex = s.trim().trim_start_matches('#');
+    // `from_str_radix` would also take a sign, as in `+fffff`
+    if hex.len() != 6 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(bad());
+    }
+    let rgb = u32::from_str_radix(hex, 16).map_err(|_| bad())?;
+    Ok(Color::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args = args.iter().map(|arg| arg.to_string());
+        Options::parse(args.chain(["game.ch8".to_string()])).map(|options| options.unwrap())
+    }
+
+    // The message for `args`, which have to be turned down
+    fn error(args: &[&str]) -> String {
+        parse(args).err().expect("the arguments were accepted")
+    }
+
+    #[test]
+    fn defaults_leave_the_rest_to_the_database() {
+        let options = parse(&[]).unwrap();
+        assert_eq!(options.rom, "game.ch8");
+        assert_eq!(options.scale, DEFAULT_SCALE);
+        assert!(options.platform.is_none() && options.speed.is_none());
+        assert!(options.palette.is_none());
+        assert!(
+            Options::parse(["--help".to_string()].into_iter())
+                .unwrap()
+                .is_none()
+        );
+        assert_eq!(
+            Options::parse(std::iter::empty()).err().unwrap(),
+            "missing ROM path"
+        );
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(parse(&["--scale", "1"]).unwrap().scale, 1);
+        assert_eq!(parse(&["--scale", "64"]).unwrap().scale, MAX_SCALE);
+        for bad in ["0", "65", "-3", "big"] {
+            assert_eq!(
+                error(&["--scale", bad]),
+                format!("bad scale '{}', expected 1 to 64", bad)
+            );
+        }
+    }
+
+    #[test]
+    fn speed() {
+        let speed = |value| parse(&["--speed", value]).unwrap().speed;
+        assert_eq!(speed("vip"), Some(Speed::CosmacVip));
+        assert_eq!(speed("VIP"), Some(Speed::CosmacVip));
+        assert_eq!(speed("1000"), Some(Speed::InstructionsPerSecond(1000)));
+        assert!(error(&["--speed", "0"]).starts_with("bad speed '0'"));
+        assert!(error(&["--speed", "fast"]).starts_with("bad speed 'fast'"));
+    }
+
+    #[test]
+    fn palettes_by_name_and_by_colour() {
+        let palette = |value| parse(&["--palette", value]).unwrap().palette.unwrap();
+        assert_eq!(palette("amber"), PALETTES[1].1);
+        assert_eq!(palette("LCD"), PALETTES[3].1);
+
+        // The plane colours left out come from the classic palette
+        let colours = palette("102030,#FFFFFF");
+        assert_eq!(colours[0], Color::RGB(0x10, 0x20, 0x30));
+        assert_eq!(colours[1], Color::RGB(255, 255, 255));
+        assert_eq!(colours[2..], DEFAULT_PALETTE[2..]);
+        let colours = palette("000000,111111,222222,333333");
+        assert_eq!(colours[3], Color::RGB(0x33, 0x33, 0x33));
+
+        assert!(error(&["--palette", "sepia"]).starts_with("unknown palette 'sepia'"));
+        assert!(error(&["--palette", "000000"]).starts_with("unknown palette"));
+        for bad in ["+fffff", "-fffff", "12345", "1234567", "00000g"] {
+            assert_eq!(
+                error(&["--palette", &format!("000000,{}", bad)]),
+                format!("bad colour '{}', expected RRGGBB", bad)
+            );
+        }
+    }
+
+    #[test]
+    fn options_without_their_value() {
+        for option in ["--scale", "--speed", "--palette", "--platform", "--seed"] {
+            let args = [option.to_string()];
+            assert_eq!(
+                Options::parse(args.into_iter()).err().unwrap(),
+                format!("{} needs a value", option)
+            );
+        }
+        assert_eq!(error(&["--fast"]), "unexpected argument '--fast'");
+    }
+}
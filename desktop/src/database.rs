@@ -0,0 +1,444 @@
+use crate::cli::{self, Palette};
+use chip8_core::{MemoryIncrement, Platform, Quirks};
+use serde_json::{Map, Value};
+use std::env;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+/*
+ * Which platform, quirks, speed and colours a ROM needs can't be told from its bytes, so we look
+ * it up by the SHA-1 of the file. The bundled database uses the layout of the community CHIP-8
+ * database (https://github.com/chip-8/chip-8-database), and its `programs.json` and
+ * `sha1-hashes.json` can be copied over the ones in `database/` as they are. Until then the
+ * bundled files only list the test ROMs of `chip8_core`, assembled with `chip8-asm`, and have to
+ * be updated when those change (the tests below say so):
+ *
+ *   sha1-hashes.json   { "<sha1>": <index in programs.json>, ... }
+ *   programs.json      [ { "title": "...", "roms": { "<sha1>": { ...settings... } } }, ... ]
+ *
+ * where the settings of a ROM are, all optional:
+ *
+ *   "platforms": ["superchip", "xochip"]          the ones it runs on, best first
+ *   "quirkyPlatforms": { "superchip": { "shift": false } }   quirks unlike the platform's
+ *   "tickrate": 30                                instructions per frame
+ *   "colors": { "pixels": ["#000000", "#ffffff"] }
+ *   "keys": { "up": 5, "down": 8 }                what the game uses each CHIP-8 key for
+ *
+ * On top of that the user can keep a local file with the same settings keyed by hash, plus an
+ * optional "title". Each setting found there replaces the one from the database:
+ *
+ *   { "<sha1>": { "title": "My game", "platforms": ["chip48"], "tickrate": 20 } }
+ */
+const PROGRAMS: &str = include_str!("../database/programs.json");
+const HASHES: &str = include_str!("../database/sha1-hashes.json");
+
+// What we know about a ROM, anything missing is left to the command line or the defaults
+#[derive(Debug, Default)]
+pub struct RomSettings {
+    pub title: Option<String>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    // Instructions per 60 Hz frame
+    pub tickrate: Option<u64>,
+    pub palette: Option<Palette>,
+    // What each key does in the game, e.g. ("up", 0x5)
+    pub keys: Vec<(String, u8)>,
+}
+
+pub struct Database {
+    programs: Vec<Value>,
+    hashes: Map<String, Value>,
+    overrides: Map<String, Value>,
+}
+
+impl Database {
+    pub fn bundled() -> Result<Self, String> {
+        let programs = serde_json::from_str(PROGRAMS)
+            .map_err(|err| format!("bundled programs.json: {}", err))?;
+        let hashes = serde_json::from_str(HASHES)
+            .map_err(|err| format!("bundled sha1-hashes.json: {}", err))?;
+        Ok(Database {
+            programs,
+            hashes,
+            overrides: Map::new(),
+        })
+    }
+
+    // Read the user's settings from `path`. A file that doesn't exist is the same as an empty one
+    pub fn load_overrides(&mut self, path: &Path) -> Result<(), String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(format!("unable to read {}: {}", path.display(), err)),
+        };
+        self.overrides =
+            serde_json::from_str(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+        Ok(())
+    }
+
+    // The settings of the ROM with this hash (lowercase hex), `None` if nobody knows about it
+    pub fn lookup(&self, hash: &str) -> Result<Option<RomSettings>, String> {
+        let program = self
+            .hashes
+            .get(hash)
+            .and_then(Value::as_u64)
+            .and_then(|index| self.programs.get(index as usize));
+        let mut entry = program
+            .and_then(|program| program["roms"][hash].as_object())
+            .cloned()
+            .unwrap_or_default();
+        if let Some(title) = program.and_then(|program| program.get("title")) {
+            entry.insert("title".to_string(), title.clone());
+        }
+        match self.overrides.get(hash) {
+            Some(Value::Object(settings)) => entry.extend(settings.clone()),
+            Some(_) => return Err(format!("the settings for {} are not an object", hash)),
+            None => (),
+        }
+
+        if program.is_none() && entry.is_empty() {
+            return Ok(None);
+        }
+        parse_settings(&entry)
+            .map(Some)
+            .map_err(|err| format!("ROM {}: {}", hash, err))
+    }
+}
+
+// Where the user's settings are when `--config` doesn't say: `~/.config/chip8/roms.json`
+pub fn default_config() -> Option<PathBuf> {
+    let config = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(config.join("chip8").join("roms.json"))
+}
+
+pub fn sha1(rom: &[u8]) -> String {
+    sha1_smol::Sha1::from(rom).digest().to_string()
+}
+
+fn parse_settings(entry: &Map<String, Value>) -> Result<RomSettings, String> {
+    let mut settings = RomSettings {
+        title: entry.get("title").and_then(Value::as_str).map(String::from),
+        ..RomSettings::default()
+    };
+
+    // The first platform we can emulate, with the quirks the ROM changes for it
+    let platforms = entry.get("platforms").and_then(Value::as_array);
+    if let Some((id, (platform, mut quirks))) = platforms
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .find_map(|id| platform_for(id).map(|platform| (id, platform)))
+    {
+        let changes = entry.get("quirkyPlatforms").map(|quirky| &quirky[id]);
+        if let Some(changes) = changes.and_then(Value::as_object) {
+            apply_quirks(&mut quirks, changes)?;
+        }
+        settings.platform = platform;
+        settings.quirks = Some(quirks);
+    }
+
+    if let Some(tickrate) = entry.get("tickrate") {
+        let tickrate = tickrate
+            .as_u64()
+            .filter(|rate| *rate > 0)
+            .ok_or(format!("bad tickrate {}", tickrate))?;
+        settings.tickrate = Some(tickrate);
+    }
+
+    let pixels = entry.get("colors").map(|colors| &colors["pixels"]);
+    if let Some(pixels) = pixels.and_then(Value::as_array) {
+        let colours: Vec<&str> = pixels.iter().filter_map(Value::as_str).collect();
+        if colours.len() != pixels.len() || !(2..=4).contains(&colours.len()) {
+            return Err("colors.pixels should be 2 to 4 colours".to_string());
+        }
+        settings.palette = Some(cli::palette_from(&colours)?);
+    }
+
+    if let Some(keys) = entry.get("keys").and_then(Value::as_object) {
+        for (action, key) in keys {
+            let key = key
+                .as_u64()
+                .filter(|key| *key < 16)
+                .ok_or(format!("bad key {} for {}", key, action))?;
+            settings.keys.push((action.clone(), key as u8));
+        }
+    }
+    Ok(settings)
+}
+
+/*
+ * The database's platform ids that we can run, and the quirks they start from. Both SUPER-CHIP
+ * versions are close enough to run as one, and the "modern" CHIP-8 that most new games target is
+ * what our default quirks were made for, with no platform to pick the memory.
+ */
+fn platform_for(id: &str) -> Option<(Option<Platform>, Quirks)> {
+    let platform = match id {
+        "modernChip8" => return Some((None, Quirks::default())),
+        "originalChip8" | "hybridVIP" => Platform::CosmacVip,
+        "chip48" => Platform::Chip48,
+        "superchip1" | "superchip" => Platform::SuperChip,
+        "xochip" => Platform::XoChip,
+        _ => return None,
+    };
+    Some((Some(platform), platform.quirks()))
+}
+
+// The database's quirk names, each one true when the behaviour is the "quirky" one
+fn apply_quirks(quirks: &mut Quirks, changes: &Map<String, Value>) -> Result<(), String> {
+    // The two memory quirks are one setting for us, so they're only looked at together
+    let mut by_x = quirks.memory_increment == MemoryIncrement::ByX;
+    let mut unchanged = quirks.memory_increment == MemoryIncrement::Unchanged;
+
+    for (name, value) in changes {
+        let on = value
+            .as_bool()
+            .ok_or(format!("quirk {} should be true or false", name))?;
+        match name.as_str() {
+            "shift" => quirks.shift_uses_vy = !on,
+            "memoryIncrementByX" => by_x = on,
+            "memoryLeaveIUnchanged" => unchanged = on,
+            "wrap" => quirks.clip_sprites = !on,
+            "jump" => quirks.jump_uses_vx = on,
+            "vblank" => quirks.display_wait = on,
+            "logic" => quirks.logic_resets_vf = on,
+            // Quirks of machines we don't emulate
+            _ => (),
+        }
+    }
+
+    quirks.memory_increment = match (unchanged, by_x) {
+        (true, _) => MemoryIncrement::Unchanged,
+        (false, true) => MemoryIncrement::ByX,
+        (false, false) => MemoryIncrement::ByXPlusOne,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::{START_ADDR, asm};
+    use sdl2::pixels::Color;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(map) => map,
+            _ => panic!("not an object: {}", value),
+        }
+    }
+
+    fn quirks_after(start: Quirks, changes: Value) -> Quirks {
+        let mut quirks = start;
+        apply_quirks(&mut quirks, &object(changes)).unwrap();
+        quirks
+    }
+
+    #[test]
+    fn bundled_database_knows_the_test_roms() {
+        let database = Database::bundled().unwrap();
+        let roms = Path::new(env!("CARGO_MANIFEST_DIR")).join("../chip8_core/tests/roms");
+        for (name, platform) in [
+            ("arith", None),
+            ("schip", Some(Platform::SuperChip)),
+            ("xochip", Some(Platform::XoChip)),
+        ] {
+            let program = asm::assemble_file(&roms.join(format!("{}.asm", name)), START_ADDR)
+                .unwrap_or_else(|err| panic!("{}", err));
+            let settings = database
+                .lookup(&sha1(&program.bytes))
+                .unwrap()
+                .unwrap_or_else(|| panic!("{}.asm changed, update database/", name));
+            assert_eq!(
+                settings.title.as_deref(),
+                Some(format!("chip8_core test: {}", name).as_str())
+            );
+            assert_eq!(settings.platform, platform);
+            assert_eq!(settings.tickrate, Some(20));
+        }
+        assert!(database.lookup(&sha1(b"not a ROM")).unwrap().is_none());
+    }
+
+    #[test]
+    fn sha1_is_lowercase_hex() {
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+    }
+
+    #[test]
+    fn parse_settings_reads_everything() {
+        let settings = parse_settings(&object(json!({
+            "title": "Game",
+            // Platforms we can't run are skipped
+            "platforms": ["megachip8", "chip48", "xochip"],
+            "quirkyPlatforms": {
+                "chip48": { "jump": false },
+                "xochip": { "shift": false },
+            },
+            "tickrate": 30,
+            "colors": { "pixels": ["#102030", "405060"] },
+            "keys": { "up": 5, "a": 10 },
+        })))
+        .unwrap();
+
+        assert_eq!(settings.title.as_deref(), Some("Game"));
+        assert_eq!(settings.platform, Some(Platform::Chip48));
+        let quirks = settings.quirks.unwrap();
+        assert!(!quirks.jump_uses_vx);
+        assert_eq!(quirks.memory_increment, MemoryIncrement::ByX);
+        assert_eq!(settings.tickrate, Some(30));
+        let palette = settings.palette.unwrap();
+        assert_eq!(
+            palette[..2],
+            [Color::RGB(16, 32, 48), Color::RGB(64, 80, 96)]
+        );
+        assert_eq!(palette[2..], cli::DEFAULT_PALETTE[2..]);
+        assert_eq!(
+            settings.keys,
+            [("a".to_string(), 10), ("up".to_string(), 5)]
+        );
+
+        // "modernChip8" is what our defaults were made for, with no platform to pick the memory
+        let settings = parse_settings(&object(json!({ "platforms": ["modernChip8"] }))).unwrap();
+        assert_eq!(settings.platform, None);
+        assert_eq!(settings.quirks, Some(Quirks::default()));
+
+        let empty = parse_settings(&Map::new()).unwrap();
+        assert!(empty.platform.is_none() && empty.quirks.is_none() && empty.palette.is_none());
+    }
+
+    #[test]
+    fn parse_settings_rejects_bad_values() {
+        for entry in [
+            json!({ "tickrate": 0 }),
+            json!({ "tickrate": "fast" }),
+            json!({ "colors": { "pixels": ["#000000"] } }),
+            json!({ "colors": { "pixels": ["#000000", "white"] } }),
+            json!({ "keys": { "up": 16 } }),
+            json!({ "platforms": ["xochip"], "quirkyPlatforms": { "xochip": { "wrap": 1 } } }),
+        ] {
+            assert!(parse_settings(&object(entry.clone())).is_err(), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn apply_quirks_inverts_shift_and_wrap() {
+        let quirks = quirks_after(
+            Quirks::default(),
+            json!({ "shift": true, "wrap": true, "jump": true, "vblank": true, "logic": true }),
+        );
+        assert!(!quirks.shift_uses_vy);
+        // Sprites wrap around the edges when they aren't clipped
+        assert!(!quirks.clip_sprites);
+        assert!(quirks.jump_uses_vx && quirks.display_wait && quirks.logic_resets_vf);
+
+        let quirks = quirks_after(
+            Quirks::XO_CHIP,
+            json!({ "shift": false, "wrap": false, "scroll": true }),
+        );
+        assert!(quirks.shift_uses_vy);
+        assert!(quirks.clip_sprites);
+    }
+
+    #[test]
+    fn apply_quirks_maps_both_memory_quirks() {
+        for (changes, expected) in [
+            (
+                json!({ "memoryLeaveIUnchanged": false, "memoryIncrementByX": false }),
+                MemoryIncrement::ByXPlusOne,
+            ),
+            (
+                json!({ "memoryLeaveIUnchanged": false, "memoryIncrementByX": true }),
+                MemoryIncrement::ByX,
+            ),
+            (
+                json!({ "memoryLeaveIUnchanged": true, "memoryIncrementByX": false }),
+                MemoryIncrement::Unchanged,
+            ),
+            // Leaving I alone wins, whatever order the keys are in
+            (
+                json!({ "memoryIncrementByX": true, "memoryLeaveIUnchanged": true }),
+                MemoryIncrement::Unchanged,
+            ),
+        ] {
+            for start in [Quirks::COSMAC_VIP, Quirks::CHIP_48, Quirks::SUPER_CHIP] {
+                let quirks = quirks_after(start, changes.clone());
+                assert_eq!(
+                    quirks.memory_increment, expected,
+                    "{} from {:?}",
+                    changes, start
+                );
+            }
+        }
+
+        // Only one of them given, the other one is what the platform does
+        let quirks = quirks_after(
+            Quirks::SUPER_CHIP,
+            json!({ "memoryLeaveIUnchanged": false }),
+        );
+        assert_eq!(quirks.memory_increment, MemoryIncrement::ByXPlusOne);
+        let quirks = quirks_after(Quirks::CHIP_48, json!({ "memoryIncrementByX": false }));
+        assert_eq!(quirks.memory_increment, MemoryIncrement::ByXPlusOne);
+        let quirks = quirks_after(Quirks::COSMAC_VIP, json!({ "memoryIncrementByX": true }));
+        assert_eq!(quirks.memory_increment, MemoryIncrement::ByX);
+    }
+
+    fn database(overrides: Value) -> Database {
+        Database {
+            programs: serde_json::from_value(json!([{
+                "title": "Game",
+                "roms": {
+                    "aaaa": {
+                        "platforms": ["superchip"],
+                        "tickrate": 15,
+                        "colors": { "pixels": ["#000000", "#ff0000"] },
+                    },
+                },
+            }]))
+            .unwrap(),
+            hashes: object(json!({ "aaaa": 0 })),
+            overrides: object(overrides),
+        }
+    }
+
+    #[test]
+    fn lookup_merges_the_overrides() {
+        let plain = database(json!({})).lookup("aaaa").unwrap().unwrap();
+        assert_eq!(plain.title.as_deref(), Some("Game"));
+        assert_eq!(plain.platform, Some(Platform::SuperChip));
+        assert_eq!(plain.tickrate, Some(15));
+
+        // Each setting given replaces the database's, the rest is kept
+        let database = database(json!({
+            "aaaa": { "title": "My game", "platforms": ["chip48"] },
+            "bbbb": { "tickrate": 40 },
+        }));
+        let merged = database.lookup("aaaa").unwrap().unwrap();
+        assert_eq!(merged.title.as_deref(), Some("My game"));
+        assert_eq!(merged.platform, Some(Platform::Chip48));
+        assert_eq!(merged.quirks, Some(Quirks::CHIP_48));
+        assert_eq!(merged.tickrate, Some(15));
+        assert_eq!(merged.palette.unwrap()[1], Color::RGB(255, 0, 0));
+
+        // A ROM only the user knows about
+        let local = database.lookup("bbbb").unwrap().unwrap();
+        assert_eq!(local.title, None);
+        assert_eq!(local.tickrate, Some(40));
+        assert_eq!(local.platform, None);
+
+        assert!(database.lookup("cccc").unwrap().is_none());
+    }
+
+    #[test]
+    fn lookup_reports_bad_overrides() {
+        let err = database(json!({ "aaaa": 5 })).lookup("aaaa").unwrap_err();
+        assert_eq!(err, "the settings for aaaa are not an object");
+        let err = database(json!({ "aaaa": { "tickrate": -1 } }))
+            .lookup("aaaa")
+            .unwrap_err();
+        assert_eq!(err, "ROM aaaa: bad tickrate -1");
+    }
+}
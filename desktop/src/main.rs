@@ -1,47 +1,86 @@
+mod audio;
+mod cli;
+mod database;
+mod slots;
+
+use audio::{AudioConfig, Beeper};
 use chip8_core::*;
+use cli::{DEFAULT_PALETTE, Options, Palette, Speed, USAGE};
+use database::{Database, RomSettings};
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
+use sdl2::keyboard::{KeyboardState, Keycode, Mod};
 use sdl2::rect::Rect;
 use sdl2::render::Canvas;
 use sdl2::video::Window;
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// One frame of the original machine, how often the timers tick and rewind snapshots are taken
+const FRAME: Duration = Duration::from_nanos(16_666_667);
 
 /*
- * Inside `chip8_core`, we created public constants to hold the screen size, which we are now
- * importing. However, a 64x32 game window is really small on today's monitors, so let's go ahead
- * and scale it up a bit.
- *
- * Recall that `SCREEN_WIDTH` and `SCREEN_HEIGHT` were public constants we defined in our backend
- * and are now included into this create via the `use chip8_core::*` statement. SDL will require
- * screen size to be `u32` rather than `usize` so we'll cast them here.
+ * How much history we keep for rewinding: 30 seconds at 60 frames per second, and never more than
+ * 16 MiB of deltas, whichever runs out first.
  */
-const SCALE: u32 = 15;
-const WINDOW_WIDTH: u32 = (SCREEN_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (SCREEN_HEIGHT as u32) * SCALE;
-const TICKS_PER_FRAME: usize = 10;
+const REWIND_FRAMES: usize = 30 * 60;
+const REWIND_MAX_BYTES: usize = 16 * 1024 * 1024;
 
-fn main() {
+fn main() -> ExitCode {
     /*
-     * We need to read the command line args to receive the path to our game ROM file. We could
-     * create several flags for additional configuration, but we'll keep it simple and say that
-     * we'll require exactly one argument - the path to the argument. Any other number and we'll
-     * exit out with an error.
-     *
-     * This grabs all of the passed command line parameters into a vector, and if there isn't two
-     * (the name of the program is always stored in args[0]), then we print out the correct input
-     * and exit. The path passed in by the user is now stored in args[1]. We'll have to make sure
-     * that's a valid file once we attempt to open it, but first, we have some other stuff to
-     * setup.
+     * We need to read the command line args to receive the path to our game ROM file, plus a few
+     * options to pick the machine, its speed and how it looks (see `cli.rs`, or run with
+     * `--help`). The name of the program is always the first argument, so we skip it. Anything
+     * wrong with them is reported with the usage and we exit before opening any window.
      */
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: cargo run path/to/game");
-        return;
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => return usage_error(&err),
+    };
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(err) => return fail(&format!("unable to read {}: {}", options.rom, err)),
+    };
+    if let Some(path) = &options.config
+        && !Path::new(path).is_file()
+    {
+        return fail(&format!("no config file at {}", path));
     }
 
+    /*
+     * Most ROMs only run right with the platform, quirks and speed they were written for. The
+     * database knows them for many games (see `database.rs`), but anything given on the command
+     * line wins. `--platform` and `--quirks` replace both the platform and the quirks it found.
+     */
+    let settings = match options.database {
+        true => rom_settings(&options, &rom),
+        false => RomSettings::default(),
+    };
+    let (platform, quirks) = match (options.platform, options.quirks) {
+        (None, None) => (settings.platform, settings.quirks),
+        chosen => chosen,
+    };
+    let speed = options
+        .speed
+        .or(settings
+            .tickrate
+            .map(|rate| Speed::InstructionsPerSecond(rate * 60)))
+        .unwrap_or(Speed::InstructionsPerSecond(Scheduler::DEFAULT_SPEED));
+    let palette = options
+        .palette
+        .or(settings.palette)
+        .unwrap_or(DEFAULT_PALETTE);
+    let title = match &settings.title {
+        Some(title) => format!("CHIP-8 Emulator - {}", title),
+        None => "CHIP-8 Emulator".to_string(),
+    };
+
     /*
      * It's time to create our SDL window! The following code simply creates a new SDL context,
      * then makes the window itself and the canvas that we'll draw upon.
@@ -65,37 +104,133 @@ fn main() {
      * the `Quit` event is detected, then the program breaks out of the loop.
      */
 
-    let sdl_context = sdl2::init().unwrap();
-    let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window("CHIP-8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+    let sdl_context = match sdl2::init() {
+        Ok(sdl) => sdl,
+        Err(err) => return fail(&format!("unable to initialise SDL: {}", err)),
+    };
+    let video_subsystem = match sdl_context.video() {
+        Ok(video) => video,
+        Err(err) => return fail(&format!("unable to initialise the video: {}", err)),
+    };
+
+    /*
+     * A 64x32 game window is really small on today's monitors, so each CHIP-8 pixel is `--scale`
+     * pixels wide. SDL will require the window size to be `u32` rather than `usize` so we'll cast
+     * `SCREEN_WIDTH` and `SCREEN_HEIGHT` here.
+     */
+    let mut window = video_subsystem.window(
+        &title,
+        SCREEN_WIDTH as u32 * options.scale,
+        SCREEN_HEIGHT as u32 * options.scale,
+    );
+    window.position_centered().opengl();
+    if options.fullscreen {
+        window.fullscreen_desktop();
+    }
+    let window = match window.build() {
+        Ok(window) => window,
+        Err(err) => return fail(&format!("unable to open the window: {}", err)),
+    };
 
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+    let mut canvas = match window.into_canvas().present_vsync().build() {
+        Ok(canvas) => canvas,
+        Err(err) => return fail(&format!("unable to create the canvas: {}", err)),
+    };
     canvas.clear();
     canvas.present();
 
-    let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut event_pump = match sdl_context.event_pump() {
+        Ok(pump) => pump,
+        Err(err) => return fail(&format!("unable to read events: {}", err)),
+    };
+
+    /*
+     * Sound is nice to have but not essential, so if there's no audio device (or SDL can't open
+     * it) we print a warning and keep going without a beeper. `M` toggles mute while playing.
+     */
+    let audio_config = AudioConfig {
+        waveform: options.waveform,
+        muted: options.mute,
+        ..AudioConfig::default()
+    };
+    let mut beeper = match sdl_context
+        .audio()
+        .and_then(|audio| Beeper::new(&audio, audio_config))
+    {
+        Ok(beeper) => Some(beeper),
+        Err(err) => {
+            eprintln!("Audio disabled: {}", err);
+            None
+        }
+    };
 
     /*
      * Creating the `Emu` object needs to go somewhere prior to our main game loop, as that is
-     * where the emulation drawing and key press handling will go.
+     * where the emulation drawing and key press handling will go. `--quirks` picks the quirks
+     * alone, so it can be combined with the memory of `--platform`.
      */
-    let mut chip8 = Emu::new();
+    let mut chip8 = match (platform, quirks) {
+        (Some(platform), Some(quirks)) => Emu::with_memory(quirks, platform.ram_size()),
+        (Some(platform), None) => Emu::for_platform(platform),
+        (None, Some(quirks)) => Emu::with_quirks(quirks),
+        (None, None) => Emu::new(),
+    };
+    if speed == Speed::CosmacVip {
+        chip8.set_timing(Timing::CosmacVip);
+    }
 
     /*
-     * A few things to nothe here. In the event that Rust is unable to open the file from the path
-     * the user gaves then the `expect` condition will fail and the program will exit with that
-     * message.
+     * The core's random generator always starts from the same seed so runs can be reproduced.
+     * When playing we want a different game every time, so seed it from the clock unless
+     * `--seed` asked for a given one.
      */
-    let mut rom = File::open(&args[1]).expect("Unable to open file");
-    let mut buffer = Vec::new();
+    let seed = options.seed.unwrap_or_else(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos() as u64)
+            .unwrap_or(DEFAULT_SEED)
+    });
+    chip8.seed_rng(seed);
 
-    rom.read_to_end(&mut buffer).unwrap();
-    chip8.load(&buffer);
+    match chip8.load(&rom) {
+        Ok(info) => println!("Loaded {} bytes, {} bytes of RAM free", info.len, info.free),
+        Err(err) => return fail(&format!("unable to load {}: {}", options.rom, err)),
+    }
+    let rom_path = Path::new(&options.rom);
+
+    /*
+     * If the ROM does something the emulator can't handle, `tick` gives us back an error instead
+     * of crashing. We report it once, stop executing and keep the window open with the last frame
+     * so the user can see where the game was when it failed.
+     */
+    let mut halted = false;
+
+    // Nothing runs while paused, but the screen is still drawn and states can be saved
+    let mut paused = options.paused;
+    if paused {
+        println!("Paused, press P to start");
+    }
+
+    /*
+     * A snapshot is recorded at the end of every frame. While Backspace is held the game doesn't
+     * run, and instead every frame goes one snapshot back in time.
+     */
+    let mut rewind = Rewind::new(REWIND_FRAMES, REWIND_MAX_BYTES);
+    let mut rewinding = false;
+    // Time spent rewinding that hasn't gone back a snapshot yet
+    let mut rewind_time = Duration::ZERO;
+
+    /*
+     * The canvas is presented with vsync, so each time around the loop takes one refresh of the
+     * monitor, which may be 60 Hz or 144 Hz or anything else, and sometimes a frame is dropped.
+     * The scheduler turns however much real time went by into the right number of instructions
+     * and timer ticks.
+     */
+    let mut scheduler = match speed {
+        Speed::InstructionsPerSecond(hz) => Scheduler::new(hz),
+        Speed::CosmacVip => Scheduler::default(),
+    };
+    let mut last_frame = Instant::now();
 
     /*
      * At this point the game has been loaded into RAM and our main loop is running. Now we need to
@@ -104,11 +239,68 @@ fn main() {
      * called, so let's add that to our loop.
      */
     'gameloop: loop {
+        let now = Instant::now();
+        let elapsed = now - last_frame;
+        last_frame = now;
+        // A state was loaded from a slot while handling the events below
+        let mut loaded_state = false;
+
         for evt in event_pump.poll_iter() {
             match evt {
                 Event::Quit { .. } => {
                     break 'gameloop;
                 }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    if let Some(beeper) = beeper.as_mut() {
+                        let muted = beeper.toggle_mute();
+                        println!("Sound {}", if muted { "muted" } else { "unmuted" });
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    paused = !paused;
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = true,
+                Event::KeyUp {
+                    keycode: Some(Keycode::Backspace),
+                    ..
+                } => rewinding = false,
+                // F1-F4 save the machine to a slot, Shift+F1-F4 load it back
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    repeat: false,
+                    ..
+                } if slots::slot_for_key(key).is_some() => {
+                    let slot = slots::slot_for_key(key).unwrap();
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        match slots::load(&mut chip8, rom_path, slot) {
+                            Ok(path) => {
+                                println!("Loaded state from {}", path.display());
+                                halted = false;
+                                rewind.clear();
+                                loaded_state = true;
+                            }
+                            Err(err) => eprintln!("Unable to load state: {}", err),
+                        }
+                    } else {
+                        match slots::save(&chip8, rom_path, slot) {
+                            Ok(path) => println!("Saved state to {}", path.display()),
+                            Err(err) => eprintln!("Unable to save state: {}", err),
+                        }
+                    }
+                }
                 /*
                  * Each event will check if the pressed key gives a `Some` value from our `key2btn`
                  * function, and if so pass it to the emulator via the public `keypress` function
@@ -132,6 +324,10 @@ fn main() {
                 _ => (),
             }
         }
+        if loaded_state {
+            // The state has the keys that were held when it was saved, use the ones held now
+            sync_keys(&mut chip8, &event_pump.keyboard_state());
+        }
 
         /*
          * The emulation `tick` speed should probably run faster than the canvas refresh rate. If
@@ -141,56 +337,152 @@ fn main() {
          * we will allow the emulator to tick several times before redrawing.
          *
          * The CHIP-8 specification says nothing about how quickly the system should actually run.
-         * Even leaving it is now so it runs at 60Hz is a valid solution. We'll simply allow our
-         * `tick` function to loop several times before moving on to drawing the screen.
-         * Personally, I find that 10 ticks per frame is a nice sweet spot
+         * Personally, I find that 10 ticks per 60 Hz frame (600 per second) is a nice sweet spot,
+         * which is the scheduler's default. The timers run once per 60 Hz frame, rather than at
+         * the clock speed, and the scheduler takes care of them too.
+         *
+         * Rewinding goes back one snapshot per 60 Hz frame as well, so it's as fast as the game
+         * was on any monitor.
          */
-        for _ in 0..TICKS_PER_FRAME {
-            chip8.tick();
+        if rewinding {
+            rewind_time += elapsed.min(Scheduler::MAX_CATCH_UP);
+            while rewind_time >= FRAME {
+                rewind_time -= FRAME;
+                match rewind.rewind(&mut chip8) {
+                    Ok(_) => {
+                        halted = false;
+                        // The snapshot has the keys that were held back then, use the ones held now
+                        sync_keys(&mut chip8, &event_pump.keyboard_state());
+                    }
+                    Err(err) => {
+                        eprintln!("Unable to rewind: {}", err);
+                        rewind.clear();
+                    }
+                }
+            }
+        } else if !halted && !paused {
+            rewind_time = Duration::ZERO;
+            // A snapshot at the end of every frame the game got through, as rewinding pops them
+            match scheduler.run_for_each_frame(&mut chip8, elapsed, |emu| rewind.push(emu)) {
+                // The game asked to quit (SUPER-CHIP `00FD`)
+                Ok(progress) if progress.exited => break 'gameloop,
+                Ok(_) => (),
+                Err(err) => {
+                    eprintln!("Emulation stopped: {}", err);
+                    halted = true;
+                }
+            }
         }
 
-        /*
-         * If you run again, you might notice that it doesn't get very far before pausing. This is
-         * likely due to the fact that we never update our two timers, so the emulator has no
-         * concept of how long time has passed for its games. I mentioned earlier that the timers
-         * run once per frame, rather than at the clock speed, so we can modify the timers at the
-         * same point as when we modify the screen.
-         */
-        chip8.tick_timers();
+        if let Some(beeper) = beeper.as_mut() {
+            if halted || rewinding || paused {
+                beeper.stop();
+            } else {
+                beeper.update(&chip8);
+            }
+        }
+
+        if let Err(err) = draw_screen(&chip8, &mut canvas, &palette) {
+            eprintln!("desktop: unable to draw: {}", err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
 
-        draw_screen(&chip8, &mut canvas);
+// What the database and the user's config say about `rom`. Problems with them are only warnings
+fn rom_settings(options: &Options, rom: &[u8]) -> RomSettings {
+    let hash = database::sha1(rom);
+    let config = options
+        .config
+        .as_ref()
+        .map(PathBuf::from)
+        .or_else(database::default_config);
+    let found = Database::bundled().and_then(|mut db| {
+        if let Some(path) = &config {
+            db.load_overrides(path)?;
+        }
+        db.lookup(&hash)
+    });
+
+    match found {
+        Ok(Some(settings)) => {
+            let name = settings.title.as_deref().unwrap_or("this ROM");
+            println!("Found settings for {} (SHA-1 {})", name, hash);
+            for (action, key) in &settings.keys {
+                let keyboard = btn2key(*key as usize).map_or("?".to_string(), |key| key.name());
+                println!("  {}: key {:X} ({})", action, key, keyboard);
+            }
+            settings
+        }
+        Ok(None) => RomSettings::default(),
+        Err(err) => {
+            eprintln!("ROM database: {}", err);
+            RomSettings::default()
+        }
     }
 }
 
+// Anything that went wrong after the arguments were understood, the usage wouldn't help there
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("desktop: {}", msg);
+    ExitCode::FAILURE
+}
+
+fn usage_error(msg: &str) -> ExitCode {
+    eprintln!("desktop: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
+
 /*
  * This function will take in a reference to our `Emu` object, as well as a mutable reference to
  * our SDL canvas. Drawing the screen requires a few steps. First, we clear the canvas to erase the
- * previous frame. Then, we iterate through the screen buffer, drawing a white rectangle anytime
- * the given value is true. Sinche CHIP-8 only supports black and white, if we clear the screen as
- * black, we only have to worry about drawing the white squares.
+ * previous frame. Then, we iterate through the screen buffer, drawing a rectangle in the palette
+ * colour of every pixel that isn't 0. If we clear the screen with the background colour, we only
+ * have to worry about drawing the lit squares.
+ *
+ * The game is drawn in the largest 2:1 area that fits in the window, centred, so it keeps its
+ * shape in fullscreen on any monitor. SUPER-CHIP games can switch to 128x64 while running, and
+ * then each pixel is drawn at half the size.
  */
-fn draw_screen(emu: &Emu, canvas: &mut Canvas<Window>) {
-    // Clear canvas as black
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
+fn draw_screen(emu: &Emu, canvas: &mut Canvas<Window>, palette: &Palette) -> Result<(), String> {
+    // Clear canvas with the background colour
+    canvas.set_draw_color(palette[0]);
     canvas.clear();
 
+    let (out_width, out_height) = canvas.output_size()?;
+    let view_width = out_width.min(out_height * 2);
+    let view_height = view_width / 2;
+    let view_left = (out_width - view_width) / 2;
+    let view_top = (out_height - view_height) / 2;
+
     let screen_buf = emu.get_display();
 
-    // Now set draw color to white, iterate through each point and see if it should be drawn
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    // The edges of every pixel are computed separately so odd sizes still fill the whole area
+    let width = emu.screen_width();
+    let height = emu.screen_height() as u32;
 
+    // Now iterate through each point and see if it should be drawn
     for (i, pixel) in screen_buf.iter().enumerate() {
-        if *pixel {
+        if *pixel != 0 {
+            canvas.set_draw_color(palette[*pixel as usize & 0b11]);
+
             // Convert our 1D array's index into a 2D (x, y) position
-            let x = (i % SCREEN_WIDTH) as u32;
-            let y = (i / SCREEN_HEIGHT) as u32;
+            let x = (i % width) as u32;
+            let y = (i / width) as u32;
 
-            // Draw a rectable at (x, y), scaled up by our SCALE value
-            let rect = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rect).unwrap();
+            // Draw a rectable at (x, y), scaled up to the drawing area
+            let left = view_left + x * view_width / width as u32;
+            let right = view_left + (x + 1) * view_width / width as u32;
+            let top = view_top + y * view_height / height;
+            let bottom = view_top + (y + 1) * view_height / height;
+            let rect = Rect::new(left as i32, top as i32, right - left, bottom - top);
+            canvas.fill_rect(rect)?;
         }
     }
     canvas.present();
+    Ok(())
 }
 
 /*
@@ -228,3 +520,39 @@ fn key2btn(key: Keycode) -> Option<usize> {
         _ => None,
     }
 }
+
+// The keyboard key for a CHIP-8 key, the other way around from `key2btn`
+fn btn2key(btn: usize) -> Option<Keycode> {
+    [
+        Keycode::Num1,
+        Keycode::Num2,
+        Keycode::Num3,
+        Keycode::Num4,
+        Keycode::Q,
+        Keycode::W,
+        Keycode::E,
+        Keycode::R,
+        Keycode::A,
+        Keycode::S,
+        Keycode::D,
+        Keycode::F,
+        Keycode::Z,
+        Keycode::X,
+        Keycode::C,
+        Keycode::V,
+    ]
+    .into_iter()
+    .find(|&key| key2btn(key) == Some(btn))
+}
+
+// Set every CHIP-8 key to whether its keyboard key is held right now
+fn sync_keys(emu: &mut Emu, keyboard: &KeyboardState) {
+    for idx in 0..16 {
+        emu.keypress(idx, false);
+    }
+    for scancode in keyboard.pressed_scancodes() {
+        if let Some(k) = Keycode::from_scancode(scancode).and_then(key2btn) {
+            emu.keypress(k, true);
+        }
+    }
+}
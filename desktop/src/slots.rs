@@ -0,0 +1,39 @@
+use chip8_core::Emu;
+use sdl2::keyboard::Keycode;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/*
+ * Numbered save state slots. Each slot is a file next to the ROM with the slot number in the
+ * extension, so `games/pong.ch8` saves slot 1 to `games/pong.state1`. F1-F4 save to slots 1-4
+ * and Shift+F1-F4 load them back.
+ */
+pub fn slot_for_key(key: Keycode) -> Option<u8> {
+    match key {
+        Keycode::F1 => Some(1),
+        Keycode::F2 => Some(2),
+        Keycode::F3 => Some(3),
+        Keycode::F4 => Some(4),
+        _ => None,
+    }
+}
+
+fn slot_path(rom: &Path, slot: u8) -> PathBuf {
+    rom.with_extension(format!("state{}", slot))
+}
+
+pub fn save(emu: &Emu, rom: &Path, slot: u8) -> Result<PathBuf, String> {
+    let path = slot_path(rom, slot);
+    fs::write(&path, emu.save_state())
+        .map_err(|err| format!("unable to write {}: {}", path.display(), err))?;
+    Ok(path)
+}
+
+pub fn load(emu: &mut Emu, rom: &Path, slot: u8) -> Result<PathBuf, String> {
+    let path = slot_path(rom, slot);
+    let data =
+        fs::read(&path).map_err(|err| format!("unable to read {}: {}", path.display(), err))?;
+    emu.load_state(&data)
+        .map_err(|err| format!("{}: {}", path.display(), err))?;
+    Ok(path)
+}
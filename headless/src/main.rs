@@ -0,0 +1,494 @@
+mod screen;
+
+use chip8_core::coverage::Coverage;
+use chip8_core::profile::Profiler;
+use chip8_core::trace::{self, Tracer};
+use chip8_core::*;
+use screen::Format;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::process::ExitCode;
+
+const USAGE: &str = "Usage: chip8-headless [options] path/to/game.ch8
+
+Options:
+  --platform vip|chip48|schip|xochip  quirks and memory of the machine
+  --frames N              frames to run at 60 Hz (default 600)
+  --ticks N               instructions per frame (default 10)
+  --vip-timing            run at the speed of a COSMAC VIP instead of --ticks per frame
+  --seed N                seed of the random number generator
+  --key FRAME:KEY[:HOLD]  press hex KEY at FRAME and hold it for HOLD frames (default 5)
+  --keys FILE             read --key entries from FILE, whitespace separated, # comments
+  --until COND            stop once COND holds: exit, pc=ADDR, i=ADDR or vX=NN
+  --screen ascii|pbm|png|none  format of the final display (default ascii)
+  --scale N               size of each pixel in the PNG (default 1)
+  -o, --output FILE       write the display to FILE instead of stdout
+  --regs                  print the registers at the end
+  --trace FILE            write every instruction run to FILE
+  --trace-format text|binary  format of the trace (default text)
+  --profile FILE          write a profile of where the time went to FILE
+  --folded FILE           write the profiled call stacks to FILE, for flame graphs
+  --coverage FILE         add the RAM used as code and data to the coverage map in FILE
+
+Exit status: 0 if all frames ran or a condition held, 1 if emulation failed, 2 for bad
+arguments or files, 3 if --until was given and none of the conditions held in time.";
+
+const DEFAULT_FRAMES: usize = 600;
+const TICKS_PER_FRAME: usize = 10;
+const DEFAULT_HOLD: usize = 5;
+// Rows in each table of the profile
+const PROFILE_ROWS: usize = 20;
+
+const EXIT_ERROR: u8 = 1;
+const EXIT_USAGE: u8 = 2;
+const EXIT_TIMEOUT: u8 = 3;
+
+/*
+ * Run a ROM without a window, sound or keyboard, for tests and CI. The machine runs for a fixed
+ * number of frames (or until one of the `--until` conditions), with key presses scripted on
+ * given frames. The display is written to stdout (or `-o`) at the end, anything else goes to
+ * stderr so the screen can be piped or compared as is.
+ *
+ * Unlike the desktop frontend the random generator keeps its fixed seed unless `--seed` says
+ * otherwise, so two runs of the same ROM and script always end the same way.
+ */
+fn main() -> ExitCode {
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(err) => return usage_error(&err),
+    };
+
+    let rom = match fs::read(&options.rom) {
+        Ok(rom) => rom,
+        Err(err) => return fail(&format!("unable to read {}: {}", options.rom, err)),
+    };
+    let mut emu = match options.platform {
+        Some(platform) => Emu::for_platform(platform),
+        None => Emu::new(),
+    };
+    if let Some(seed) = options.seed {
+        emu.seed_rng(seed);
+    }
+    if options.vip_timing {
+        emu.set_timing(Timing::CosmacVip);
+    }
+    if let Err(err) = emu.load(&rom) {
+        return fail(&format!("unable to load {}: {}", options.rom, err));
+    }
+
+    let mut tracer = match &options.trace {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(Tracer::new(
+                BufWriter::new(file),
+                options.trace_format,
+                options.platform,
+            )),
+            Err(err) => return fail(&format!("unable to create {}: {}", path, err)),
+        },
+        None => None,
+    };
+
+    let mut profiler = (options.profile.is_some() || options.folded.is_some()).then(Profiler::new);
+
+    let mut coverage = options.coverage.as_ref().map(|_| {
+        emu.set_access_log(true);
+        Coverage::new(emu.ram_size())
+    });
+
+    let (stop, frames) = run(
+        &mut emu,
+        &options,
+        tracer.as_mut(),
+        profiler.as_mut(),
+        coverage.as_mut(),
+    );
+    eprintln!("Stopped after {} frames: {}", frames, stop.describe());
+
+    if let Some(tracer) = tracer {
+        let cycles = tracer.cycles();
+        if let Err(err) = tracer.finish() {
+            eprintln!("chip8-headless: unable to write the trace: {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+        eprintln!("Traced {} instructions", cycles);
+    }
+    if let Some(profiler) = &profiler {
+        let labels = Default::default();
+        if let Some(path) = &options.profile
+            && let Err(err) = fs::write(path, profiler.report(&labels, PROFILE_ROWS))
+        {
+            eprintln!("chip8-headless: unable to write {}: {}", path, err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+        if let Some(path) = &options.folded {
+            let result = File::create(path).and_then(|file| {
+                let mut out = BufWriter::new(file);
+                profiler.write_folded(&mut out, &labels)?;
+                out.flush()
+            });
+            if let Err(err) = result {
+                eprintln!("chip8-headless: unable to write {}: {}", path, err);
+                return ExitCode::from(EXIT_USAGE);
+            }
+        }
+    }
+
+    if let Some(format) = options.screen {
+        let result = match &options.output {
+            Some(path) => File::create(path).and_then(|file| {
+                let mut out = BufWriter::new(file);
+                screen::write(&emu, format, options.scale, &mut out)?;
+                out.flush()
+            }),
+            None => screen::write(&emu, format, options.scale, &mut io::stdout().lock()),
+        };
+        if let Err(err) = result {
+            eprintln!("chip8-headless: unable to write the screen: {}", err);
+            return ExitCode::from(EXIT_USAGE);
+        }
+    }
+    if let (Some(coverage), Some(path)) = (&mut coverage, &options.coverage)
+        && let Err(err) = save_coverage(coverage, path)
+    {
+        eprintln!("chip8-headless: unable to update {}: {}", path, err);
+        return ExitCode::from(EXIT_USAGE);
+    }
+    if options.regs {
+        print_registers(&emu);
+    }
+
+    match stop {
+        Stop::Error(_) => ExitCode::from(EXIT_ERROR),
+        Stop::Frames | Stop::Exited if !options.until.is_empty() => ExitCode::from(EXIT_TIMEOUT),
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+// Why the run ended
+enum Stop {
+    Frames,
+    Condition(Until),
+    Exited,
+    Error(EmuError),
+}
+
+impl Stop {
+    fn describe(&self) -> String {
+        match self {
+            Stop::Frames => "ran every frame".to_string(),
+            Stop::Condition(until) => format!("{} holds", until),
+            Stop::Exited => "the program exited".to_string(),
+            Stop::Error(err) => format!("emulation failed: {}", err),
+        }
+    }
+}
+
+// Returns why it stopped and how many frames ran (a frame cut short counts)
+fn run(
+    emu: &mut Emu,
+    options: &Options,
+    mut tracer: Option<&mut Tracer<BufWriter<File>>>,
+    mut profiler: Option<&mut Profiler>,
+    mut coverage: Option<&mut Coverage>,
+) -> (Stop, usize) {
+    // Frames left before each key is released
+    let mut held = [0; 16];
+
+    for frame in 0..options.frames {
+        for press in options.keys.iter().filter(|press| press.frame == frame) {
+            emu.keypress(press.key, true);
+            held[press.key] = held[press.key].max(press.hold.max(1));
+        }
+
+        // With VIP timing a frame lasts until the next interrupt, which ticks the timers itself
+        let vip_frame =
+            (emu.timing() == Timing::CosmacVip).then(|| emu.cycles() / VIP_CYCLES_PER_FRAME + 1);
+        let mut ticks = 0;
+        while match vip_frame {
+            Some(end) => emu.cycles() / VIP_CYCLES_PER_FRAME < end,
+            None => ticks < options.ticks,
+        } {
+            ticks += 1;
+            let result = match (tracer.as_mut(), profiler.as_mut()) {
+                (Some(tracer), Some(profiler)) => profiler.tick_with(emu, |emu| tracer.tick(emu)),
+                (Some(tracer), None) => tracer.tick(emu),
+                (None, Some(profiler)) => profiler.tick(emu),
+                (None, None) => emu.tick(),
+            };
+            if let Some(coverage) = coverage.as_mut() {
+                coverage.record(emu.last_accesses());
+            }
+            match result {
+                Ok(StepOutcome::Exited) => {
+                    let stop = match options.until.contains(&Until::Exit) {
+                        true => Stop::Condition(Until::Exit),
+                        false => Stop::Exited,
+                    };
+                    return (stop, frame + 1);
+                }
+                Ok(_) => (),
+                Err(err) => return (Stop::Error(err), frame + 1),
+            }
+            if let Some(until) = options.until.iter().find(|until| until.holds(emu)) {
+                return (Stop::Condition(*until), frame + 1);
+            }
+        }
+        if vip_frame.is_none() {
+            emu.tick_timers();
+        }
+
+        for (key, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    emu.keypress(key, false);
+                }
+            }
+        }
+    }
+    (Stop::Frames, options.frames)
+}
+
+// Merge `coverage` with what's already in the file at `path`, so sessions add up
+fn save_coverage(coverage: &mut Coverage, path: &str) -> Result<(), String> {
+    match File::open(path) {
+        Ok(file) => {
+            let old = Coverage::read(BufReader::new(file)).map_err(|err| err.to_string())?;
+            coverage.merge(&old);
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+        Err(err) => return Err(err.to_string()),
+    }
+    let file = File::create(path).map_err(|err| err.to_string())?;
+    let mut out = BufWriter::new(file);
+    coverage
+        .write(&mut out)
+        .and_then(|_| out.flush())
+        .map_err(|err| err.to_string())
+}
+
+fn print_registers(emu: &Emu) {
+    for (row, regs) in emu.registers().chunks(8).enumerate() {
+        let cells: Vec<String> = regs
+            .iter()
+            .enumerate()
+            .map(|(col, value)| format!("V{:X}={:02X}", row * 8 + col, value))
+            .collect();
+        eprintln!("{}", cells.join(" "));
+    }
+    eprintln!(
+        "PC={:04X} I={:04X} SP={} DT={:02X} ST={:02X}",
+        emu.pc(),
+        emu.i_reg(),
+        emu.stack().len(),
+        emu.delay_timer(),
+        emu.sound_timer()
+    );
+    if !emu.stack().is_empty() {
+        let stack: Vec<String> = emu.stack().iter().map(|a| format!("{:04X}", a)).collect();
+        eprintln!("Stack: {}", stack.join(" "));
+    }
+}
+
+// A condition that ends the run early, checked after every instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Until {
+    Exit,
+    Pc(u16),
+    I(u16),
+    Reg(usize, u8),
+}
+
+impl Until {
+    fn parse(s: &str) -> Result<Self, String> {
+        let bad = || format!("unknown condition '{}' (exit, pc=ADDR, i=ADDR, vX=NN)", s);
+        if s == "exit" {
+            return Ok(Until::Exit);
+        }
+        let (name, value) = s.split_once('=').ok_or_else(bad)?;
+        let value = parse_number(value).ok_or_else(bad)?;
+        match name.to_ascii_lowercase().as_str() {
+            "pc" => Ok(Until::Pc(value)),
+            "i" => Ok(Until::I(value)),
+            reg => {
+                let x = reg
+                    .strip_prefix('v')
+                    .and_then(|x| usize::from_str_radix(x, 16).ok())
+                    .filter(|x| *x < 16)
+                    .ok_or_else(bad)?;
+                let value = u8::try_from(value).map_err(|_| bad())?;
+                Ok(Until::Reg(x, value))
+            }
+        }
+    }
+
+    fn holds(&self, emu: &Emu) -> bool {
+        match *self {
+            Until::Exit => emu.has_exited(),
+            Until::Pc(addr) => emu.pc() == addr,
+            Until::I(addr) => emu.i_reg() == addr,
+            Until::Reg(x, value) => emu.registers()[x] == value,
+        }
+    }
+}
+
+impl std::fmt::Display for Until {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            Until::Exit => f.write_str("exit"),
+            Until::Pc(addr) => write!(f, "pc={:#05X}", addr),
+            Until::I(addr) => write!(f, "i={:#05X}", addr),
+            Until::Reg(x, value) => write!(f, "v{:x}={:#04X}", x, value),
+        }
+    }
+}
+
+// `FRAME:KEY[:HOLD]`: press `KEY` (a hex digit) at `FRAME` for `HOLD` frames
+struct KeyPress {
+    frame: usize,
+    key: usize,
+    hold: usize,
+}
+
+impl KeyPress {
+    fn parse(s: &str) -> Result<Self, String> {
+        let bad = || format!("bad key press '{}', expected FRAME:KEY[:HOLD]", s);
+        let mut fields = s.split(':');
+        let frame = fields.next().and_then(|f| f.parse().ok()).ok_or_else(bad)?;
+        let key = fields
+            .next()
+            .and_then(|k| usize::from_str_radix(k, 16).ok())
+            .filter(|k| *k < 16)
+            .ok_or_else(bad)?;
+        let hold = match fields.next() {
+            Some(hold) => hold.parse().map_err(|_| bad())?,
+            None => DEFAULT_HOLD,
+        };
+        if fields.next().is_some() {
+            return Err(bad());
+        }
+        Ok(KeyPress { frame, key, hold })
+    }
+}
+
+struct Options {
+    rom: String,
+    platform: Option<Platform>,
+    frames: usize,
+    ticks: usize,
+    vip_timing: bool,
+    seed: Option<u64>,
+    keys: Vec<KeyPress>,
+    until: Vec<Until>,
+    screen: Option<Format>,
+    scale: usize,
+    output: Option<String>,
+    regs: bool,
+    trace: Option<String>,
+    trace_format: trace::Format,
+    profile: Option<String>,
+    folded: Option<String>,
+    coverage: Option<String>,
+}
+
+impl Options {
+    // `None` when only the help was asked for
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Self>, String> {
+        let mut options = Options {
+            rom: String::new(),
+            platform: None,
+            frames: DEFAULT_FRAMES,
+            ticks: TICKS_PER_FRAME,
+            vip_timing: false,
+            seed: None,
+            keys: Vec::new(),
+            until: Vec::new(),
+            screen: Some(Format::Ascii),
+            scale: 1,
+            output: None,
+            regs: false,
+            trace: None,
+            trace_format: trace::Format::Text,
+            profile: None,
+            folded: None,
+            coverage: None,
+        };
+        let mut rom = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+            match arg.as_str() {
+                "--platform" => options.platform = Some(value()?.parse()?),
+                "--frames" => options.frames = parse_count(&value()?)?,
+                "--ticks" => options.ticks = parse_count(&value()?)?,
+                "--vip-timing" => options.vip_timing = true,
+                "--seed" => {
+                    let seed = value()?;
+                    options.seed = Some(seed.parse().map_err(|_| format!("bad seed '{}'", seed))?)
+                }
+                "--key" => options.keys.push(KeyPress::parse(&value()?)?),
+                "--keys" => {
+                    let path = value()?;
+                    let script = fs::read_to_string(&path)
+                        .map_err(|err| format!("unable to read {}: {}", path, err))?;
+                    for line in script.lines() {
+                        let line = line.split('#').next().unwrap_or_default();
+                        for entry in line.split_whitespace() {
+                            options.keys.push(KeyPress::parse(entry)?);
+                        }
+                    }
+                }
+                "--until" => options.until.push(Until::parse(&value()?)?),
+                "--screen" => {
+                    options.screen = match value()?.as_str() {
+                        "none" => None,
+                        format => Some(format.parse()?),
+                    }
+                }
+                "--scale" => options.scale = parse_count(&value()?)?,
+                "-o" | "--output" => options.output = Some(value()?),
+                "--regs" => options.regs = true,
+                "--trace" => options.trace = Some(value()?),
+                "--trace-format" => options.trace_format = value()?.parse()?,
+                "--profile" => options.profile = Some(value()?),
+                "--folded" => options.folded = Some(value()?),
+                "--coverage" => options.coverage = Some(value()?),
+                "-h" | "--help" => return Ok(None),
+                _ if rom.is_none() && !arg.starts_with('-') => rom = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        options.rom = rom.ok_or("missing ROM path")?;
+        Ok(Some(options))
+    }
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    s.parse().map_err(|_| format!("'{}' isn't a number", s))
+}
+
+// Decimal, or hex with a 0x prefix
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Files that can't be read or written, for which the usage would only hide the message
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("chip8-headless: {}", msg);
+    ExitCode::from(EXIT_USAGE)
+}
+
+fn usage_error(msg: &str) -> ExitCode {
+    eprintln!("chip8-headless: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::from(EXIT_USAGE)
+}
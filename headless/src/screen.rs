@@ -0,0 +1,92 @@
+use chip8_core::Emu;
+use std::fmt::Write as _;
+use std::io::{self, Write};
+use std::iter;
+use std::str::FromStr;
+
+// How to write the final contents of the display
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    // One character per pixel: `.` when off, `#` when on, `+` and `@` for the other XO-CHIP colours
+    Ascii,
+    // Plain (P1) portable bitmap, any lit pixel is black
+    Pbm,
+    // Greyscale PNG with the colours of the desktop frontend
+    Png,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ascii" => Ok(Format::Ascii),
+            "pbm" => Ok(Format::Pbm),
+            "png" => Ok(Format::Png),
+            _ => Err(format!("unknown screen format '{}' (ascii, pbm, png)", s)),
+        }
+    }
+}
+
+// Grey level of each colour index, same as `PALETTE` in the desktop frontend
+const GREYS: [u8; 4] = [0, 255, 170, 85];
+
+const ASCII: [char; 4] = ['.', '#', '+', '@'];
+
+// Plain PBM lines can't be longer than 70 characters, so each row is split after this many pixels
+const PBM_PIXELS_PER_LINE: usize = 35;
+
+/*
+ * Write the display at its current resolution (64x32, or 128x64 in hires mode). Each pixel of
+ * the PNG becomes a `scale` x `scale` square so screenshots aren't tiny, the text formats are
+ * always one character per pixel.
+ */
+pub fn write(emu: &Emu, format: Format, scale: usize, out: &mut impl Write) -> io::Result<()> {
+    let (width, height) = (emu.screen_width(), emu.screen_height());
+    let screen = emu.get_display();
+
+    match format {
+        Format::Ascii => {
+            let mut text = String::with_capacity((width + 1) * height);
+            for row in screen.chunks(width) {
+                text.extend(row.iter().map(|&p| ASCII[p as usize & 3]));
+                text.push('\n');
+            }
+            out.write_all(text.as_bytes())
+        }
+        Format::Pbm => {
+            let mut text = format!("P1\n{} {}\n", width, height);
+            for line in screen
+                .chunks(width)
+                .flat_map(|row| row.chunks(PBM_PIXELS_PER_LINE))
+            {
+                let bits: Vec<&str> = line
+                    .iter()
+                    .map(|&p| if p != 0 { "1" } else { "0" })
+                    .collect();
+                let _ = writeln!(text, "{}", bits.join(" "));
+            }
+            out.write_all(text.as_bytes())
+        }
+        Format::Png => {
+            let scale = scale.max(1);
+            let mut pixels = Vec::with_capacity(width * height * scale * scale);
+            for row in screen.chunks(width) {
+                let line: Vec<u8> = row
+                    .iter()
+                    .flat_map(|&p| iter::repeat_n(GREYS[p as usize & 3], scale))
+                    .collect();
+                for _ in 0..scale {
+                    pixels.extend_from_slice(&line);
+                }
+            }
+
+            let mut encoder =
+                png::Encoder::new(out, (width * scale) as u32, (height * scale) as u32);
+            encoder.set_color(png::ColorType::Grayscale);
+            encoder.set_depth(png::BitDepth::Eight);
+            let mut writer = encoder.write_header().map_err(io::Error::other)?;
+            writer.write_image_data(&pixels).map_err(io::Error::other)
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use chip8_core::{START_ADDR, asm};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+// Shows the font digit of the first key pressed in the top left corner
+const SHOW_KEY: &str = "
+        LD V0, K
+        LD F, V0
+        LD V1, 0
+        DRW V1, V1, 5
+done:   JP done
+";
+
+// Assemble `source` into a ROM file named after the test, returning its path and labels
+fn rom(name: &str, source: &str) -> (PathBuf, asm::Assembly) {
+    let program = asm::assemble(source, START_ADDR).unwrap_or_else(|err| panic!("{}", err));
+    let path = env::temp_dir().join(format!("headless-{}-{}.ch8", name, std::process::id()));
+    fs::write(&path, &program.bytes).unwrap();
+    (path, program)
+}
+
+fn headless(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8-headless"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+fn stderr(output: &Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
+
+#[test]
+fn key_press_until_a_condition() {
+    let (path, program) = rom("key", SHOW_KEY);
+    let rom = path.to_str().unwrap();
+    let until = format!("pc={:#x}", program.labels["done"]);
+
+    let output = headless(&["--key", "3:7", "--until", &until, rom]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stderr(&output).contains("holds"), "{}", stderr(&output));
+
+    // The 7 of the font, on a 64x32 screen
+    let screen = stdout(&output);
+    let rows: Vec<&str> = screen.lines().collect();
+    assert_eq!(rows.len(), 32);
+    assert!(rows.iter().all(|row| row.len() == 64));
+    let glyph: Vec<&str> = rows[..6].iter().map(|row| &row[..5]).collect();
+    assert_eq!(
+        glyph,
+        ["####.", "...#.", "..#..", ".#...", ".#...", "....."]
+    );
+    assert!(rows[5..].iter().all(|row| !row.contains('#')));
+
+    // Without the key it never gets there
+    let output = headless(&["--frames", "30", "--until", &until, rom]);
+    assert_eq!(output.status.code(), Some(3), "{}", stderr(&output));
+    assert!(!stdout(&output).contains('#'));
+
+    // Without --until, running out of frames is a success
+    let output = headless(&["--frames", "30", "--screen", "none", rom]);
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    assert!(stdout(&output).is_empty());
+
+    // Each row of a plain PBM is split into lines of at most 70 characters
+    let output = headless(&["--key", "3:7", "--until", &until, "--screen", "pbm", rom]);
+    let pbm = stdout(&output);
+    let lines: Vec<&str> = pbm.lines().collect();
+    assert_eq!(lines[..2], ["P1", "64 32"]);
+    assert!(lines.iter().all(|line| line.len() <= 70), "{}", pbm);
+    let bits: Vec<&str> = lines[2..].iter().flat_map(|l| l.split(' ')).collect();
+    assert_eq!(bits.len(), 64 * 32);
+    assert_eq!(bits[..5], ["1", "1", "1", "1", "0"]);
+    assert_eq!(bits[64..69], ["0", "0", "0", "1", "0"]);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn failures_have_their_own_exit_status() {
+    let (path, _) = rom("error", "LD V0, 1\ndw #5001\n");
+    let rom = path.to_str().unwrap();
+
+    let output = headless(&[rom]);
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("emulation failed"),
+        "{}",
+        stderr(&output)
+    );
+
+    // Bad arguments come with the usage, a missing file doesn't
+    let output = headless(&["--until", "never", rom]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(stderr(&output).contains("Usage:"), "{}", stderr(&output));
+
+    let missing = format!("{}.missing", rom);
+    let output = headless(&[&missing]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("unable to read"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(!stderr(&output).contains("Usage:"), "{}", stderr(&output));
+    fs::remove_file(&path).unwrap();
+}
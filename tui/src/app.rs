@@ -0,0 +1,335 @@
+use chip8_core::debug::{Debugger, StopReason, WatchKind, Watchpoint};
+use chip8_core::{Emu, Platform};
+use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+// Instructions run per frame while the program is running, same as the desktop frontend
+const TICKS_PER_FRAME: usize = 10;
+
+/*
+ * Terminals only tell us when a key is pressed, not when it's released. A pressed key is kept
+ * down for this many frames, and the terminal's auto-repeat keeps refreshing it while the key is
+ * actually held, which is close enough for games.
+ */
+const KEY_HOLD_FRAMES: u8 = 8;
+
+// Instruction budget for `next` and `finish`, so a subroutine that never returns can't hang us
+const STEP_BUDGET: usize = 1_000_000;
+
+pub const HELP: &str = "F5 run/pause  F9 breakpoint  F10 next  F11 step  F8 finish  \
+    PgUp/PgDn memory  : command  Ctrl-C quit";
+
+const COMMANDS: &str = "commands (numbers in hex): step [N] | next | finish | continue | pause | \
+    break ADDR | delete ADDR | watch ADDR [LEN] [r|w|rw] | set v0-vf|i|pc|dt|st VALUE | \
+    poke ADDR BYTE... | mem ADDR | display blocks|braille | reset | quit";
+
+pub struct App {
+    pub debugger: Debugger,
+    pub platform: Option<Platform>,
+    rom: Vec<u8>,
+    pub running: bool,
+    pub status: String,
+    // Text typed after `:`, `None` when not typing a command
+    pub command: Option<String>,
+    // First address shown in the memory view
+    pub mem_addr: u16,
+    pub braille: bool,
+    // Frames left before each CHIP-8 key is released
+    held: [u8; 16],
+    pub quit: bool,
+}
+
+impl App {
+    pub fn new(platform: Option<Platform>, rom: Vec<u8>) -> Result<Self, String> {
+        let mut emu = platform.map_or_else(Emu::new, Emu::for_platform);
+        emu.load(&rom).map_err(|err| err.to_string())?;
+
+        Ok(App {
+            debugger: Debugger::new(emu),
+            platform,
+            rom,
+            running: false,
+            status: HELP.to_string(),
+            command: None,
+            mem_addr: chip8_core::START_ADDR,
+            braille: false,
+            held: [0; 16],
+            quit: false,
+        })
+    }
+
+    pub fn emu(&self) -> &Emu {
+        self.debugger.emu()
+    }
+
+    // Called once per frame (60 times a second), whether the program is running or not
+    pub fn update(&mut self) {
+        for (key, frames) in self.held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    self.debugger.emu_mut().keypress(key, false);
+                }
+            }
+        }
+
+        if self.running {
+            let reason = self.debugger.run(TICKS_PER_FRAME);
+            self.debugger.emu_mut().tick_timers();
+            if reason != StopReason::Limit {
+                self.stop(reason);
+            }
+        }
+    }
+
+    fn stop(&mut self, reason: StopReason) {
+        self.running = false;
+        self.status = format!("Stopped: {}", reason);
+    }
+
+    pub fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+
+        if let Some(command) = self.command.as_mut() {
+            match key.code {
+                KeyCode::Enter => {
+                    let command = self.command.take().unwrap_or_default();
+                    self.run_command(&command);
+                }
+                KeyCode::Esc => self.command = None,
+                KeyCode::Backspace => {
+                    command.pop();
+                }
+                KeyCode::Char(c) => command.push(c),
+                _ => (),
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char(':') => self.command = Some(String::new()),
+            KeyCode::F(5) => {
+                if self.running {
+                    self.running = false;
+                    self.status = "Paused".to_string();
+                } else {
+                    self.resume();
+                }
+            }
+            KeyCode::Esc if self.running => {
+                self.running = false;
+                self.status = "Paused".to_string();
+            }
+            KeyCode::F(9) => self.toggle_breakpoint(self.emu().pc()),
+            KeyCode::F(10) => self.step_over(),
+            KeyCode::F(11) => self.step(1),
+            KeyCode::F(8) => self.step_out(),
+            KeyCode::PageUp => self.mem_addr = self.mem_addr.wrapping_sub(0x80),
+            KeyCode::PageDown => self.mem_addr = self.mem_addr.wrapping_add(0x80),
+            KeyCode::Up => self.mem_addr = self.mem_addr.wrapping_sub(0x10),
+            KeyCode::Down => self.mem_addr = self.mem_addr.wrapping_add(0x10),
+            KeyCode::Char(c) => {
+                if let Some(k) = key2btn(c) {
+                    self.debugger.emu_mut().keypress(k, true);
+                    self.held[k] = KEY_HOLD_FRAMES;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn resume(&mut self) {
+        self.running = true;
+        self.status = "Running (F5 or Esc to pause)".to_string();
+    }
+
+    fn step(&mut self, count: usize) {
+        self.running = false;
+        let mut reason = StopReason::Done;
+        for _ in 0..count {
+            reason = self.debugger.step();
+            if reason != StopReason::Done {
+                break;
+            }
+        }
+        self.status = format!("Stopped: {}", reason);
+    }
+
+    fn step_over(&mut self) {
+        self.running = false;
+        let reason = self.debugger.step_over(STEP_BUDGET);
+        self.status = format!("Stopped: {}", reason);
+    }
+
+    fn step_out(&mut self) {
+        self.running = false;
+        let reason = self.debugger.step_out(STEP_BUDGET);
+        self.status = format!("Stopped: {}", reason);
+    }
+
+    fn toggle_breakpoint(&mut self, addr: u16) {
+        if self.debugger.remove_breakpoint(addr) {
+            self.status = format!("Removed breakpoint at {:03X}", addr);
+        } else {
+            self.debugger.add_breakpoint(addr);
+            self.status = format!("Breakpoint at {:03X}", addr);
+        }
+    }
+
+    fn run_command(&mut self, line: &str) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if let Err(err) = self.command_words(&words) {
+            self.status = format!("Error: {}", err);
+        }
+    }
+
+    fn command_words(&mut self, words: &[&str]) -> Result<(), String> {
+        match words {
+            [] => (),
+            ["s" | "step"] => self.step(1),
+            ["s" | "step", count] => self.step(parse_count(count)?),
+            ["n" | "next"] => self.step_over(),
+            ["f" | "finish"] => self.step_out(),
+            ["c" | "continue"] => self.resume(),
+            ["p" | "pause"] => {
+                self.running = false;
+                self.status = "Paused".to_string();
+            }
+            ["b" | "break", addr] => {
+                let addr = parse_hex(addr)?;
+                self.debugger.add_breakpoint(addr);
+                self.status = format!("Breakpoint at {:03X}", addr);
+            }
+            ["d" | "delete", addr] => {
+                let addr = parse_hex(addr)?;
+                let mut removed = self.debugger.remove_breakpoint(addr);
+                let watchpoints: Vec<Watchpoint> = self
+                    .debugger
+                    .watchpoints()
+                    .iter()
+                    .filter(|w| w.addr == addr)
+                    .copied()
+                    .collect();
+                for watchpoint in watchpoints {
+                    removed |= self.debugger.remove_watchpoint(watchpoint);
+                }
+                self.status = match removed {
+                    true => format!("Deleted breakpoints and watchpoints at {:03X}", addr),
+                    false => format!("Nothing to delete at {:03X}", addr),
+                };
+            }
+            ["w" | "watch", addr, rest @ ..] => {
+                let addr = parse_hex(addr)?;
+                let (len, kind) = match rest {
+                    [] => (1, "rw"),
+                    [kind @ ("r" | "w" | "rw")] => (1, *kind),
+                    [len] => (parse_hex(len)? as usize, "rw"),
+                    [len, kind] => (parse_hex(len)? as usize, *kind),
+                    _ => return Err("usage: watch ADDR [LEN] [r|w|rw]".to_string()),
+                };
+                let kind = match kind {
+                    "r" => WatchKind::Read,
+                    "w" => WatchKind::Write,
+                    "rw" => WatchKind::Access,
+                    _ => return Err(format!("unknown watch kind '{}'", kind)),
+                };
+                self.debugger.add_watchpoint(Watchpoint { addr, len, kind });
+                self.status = format!("Watching {} bytes at {:03X}", len, addr);
+            }
+            ["set", register, value] => {
+                let value = parse_hex(value)?;
+                let emu = self.debugger.emu_mut();
+                match register.to_ascii_lowercase().as_str() {
+                    "i" => emu.set_i_reg(value),
+                    "pc" => emu.set_pc(value),
+                    "dt" => emu.set_delay_timer(byte(value)?),
+                    "st" => emu.set_sound_timer(byte(value)?),
+                    name => {
+                        let x = name
+                            .strip_prefix('v')
+                            .and_then(|x| usize::from_str_radix(x, 16).ok())
+                            .filter(|x| *x < 16)
+                            .ok_or(format!("unknown register '{}'", register))?;
+                        emu.set_register(x, byte(value)?);
+                    }
+                }
+                self.status = format!("{} = {:X}", register.to_ascii_uppercase(), value);
+            }
+            ["poke", addr, bytes @ ..] if !bytes.is_empty() => {
+                let addr = parse_hex(addr)?;
+                let data = bytes
+                    .iter()
+                    .map(|b| parse_hex(b).and_then(byte))
+                    .collect::<Result<Vec<u8>, _>>()?;
+                if !self.debugger.emu_mut().write_memory(addr, &data) {
+                    return Err(format!("{:03X} is outside of RAM", addr));
+                }
+                self.mem_addr = addr & !0xF;
+                self.status = format!("Wrote {} bytes at {:03X}", data.len(), addr);
+            }
+            ["m" | "mem", addr] => self.mem_addr = parse_hex(addr)? & !0xF,
+            ["display", "braille"] => self.braille = true,
+            ["display", "blocks"] => self.braille = false,
+            ["reset"] => {
+                let emu = self.debugger.emu_mut();
+                emu.reset();
+                emu.load(&self.rom).map_err(|err| err.to_string())?;
+                self.running = false;
+                self.status = "Reset".to_string();
+            }
+            ["q" | "quit"] => self.quit = true,
+            ["h" | "help"] => self.status = COMMANDS.to_string(),
+            _ => {
+                return Err(format!(
+                    "unknown command '{}' (try 'help')",
+                    words.join(" ")
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+// Addresses and values are written in hex, with or without a 0x, # or $ prefix
+fn parse_hex(s: &str) -> Result<u16, String> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix('#'))
+        .or_else(|| s.strip_prefix('$'))
+        .unwrap_or(s);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("'{}' isn't a hex number", s))
+}
+
+fn parse_count(s: &str) -> Result<usize, String> {
+    s.parse()
+        .map_err(|_| format!("'{}' isn't a number of steps", s))
+}
+
+fn byte(value: u16) -> Result<u8, String> {
+    u8::try_from(value).map_err(|_| format!("{:X} doesn't fit in a byte", value))
+}
+
+// Same layout as the desktop frontend: the left-hand 4x4 block of a QWERTY keyboard
+fn key2btn(key: char) -> Option<usize> {
+    match key.to_ascii_lowercase() {
+        '1' => Some(0x1),
+        '2' => Some(0x2),
+        '3' => Some(0x3),
+        '4' => Some(0xC),
+        'q' => Some(0x4),
+        'w' => Some(0x5),
+        'e' => Some(0x6),
+        'r' => Some(0xD),
+        'a' => Some(0x7),
+        's' => Some(0x8),
+        'd' => Some(0x9),
+        'f' => Some(0xE),
+        'z' => Some(0xA),
+        'x' => Some(0x0),
+        'c' => Some(0xB),
+        'v' => Some(0xF),
+        _ => None,
+    }
+}
@@ -0,0 +1,99 @@
+mod app;
+mod ui;
+
+use app::App;
+use chip8_core::Platform;
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyEventKind};
+use std::env;
+use std::fs;
+use std::io;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const USAGE: &str = "Usage: tui [--platform vip|chip48|schip|xochip] path/to/game";
+
+// One frame at 60 Hz, the rate of the CHIP-8 timers
+const FRAME: Duration = Duration::from_micros(16_667);
+
+/*
+ * A debugger that runs entirely in the terminal, for when there's no window to open (e.g. over
+ * SSH). The program starts paused: step through it with the function keys or type `:help` to see
+ * the commands. While it runs, the left-hand 4x4 block of the keyboard is the CHIP-8 keypad, like
+ * in the desktop frontend.
+ */
+fn main() -> ExitCode {
+    // Plain `Emu::new()` quirks unless a platform is asked for, like the other frontends
+    let mut platform: Option<Platform> = None;
+    let mut path = None;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--platform" => match args.next().map(|s| s.parse()) {
+                Some(Ok(p)) => platform = Some(p),
+                Some(Err(err)) => return fail(&err),
+                None => return fail("--platform needs a value"),
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                return ExitCode::SUCCESS;
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return fail(&format!("unexpected argument '{}'", arg)),
+        }
+    }
+
+    let Some(path) = path else {
+        return fail("missing ROM path");
+    };
+    let rom = match fs::read(&path) {
+        Ok(rom) => rom,
+        Err(err) => return fail(&format!("unable to read {}: {}", path, err)),
+    };
+    let mut app = match App::new(platform, rom) {
+        Ok(app) => app,
+        Err(err) => return fail(&format!("unable to load {}: {}", path, err)),
+    };
+
+    // `init` switches to the alternate screen and raw mode, `restore` undoes it (also on panic)
+    let mut terminal = ratatui::init();
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("tui: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> io::Result<()> {
+    while !app.quit {
+        terminal.draw(|frame| ui::draw(frame, app))?;
+
+        // Handle input until it's time for the next frame
+        let deadline = Instant::now() + FRAME;
+        loop {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if !event::poll(timeout)? {
+                break;
+            }
+            if let Event::Key(key) = event::read()?
+                && key.kind != KeyEventKind::Release
+            {
+                app.handle_key(key);
+            }
+        }
+        app.update();
+    }
+    Ok(())
+}
+
+fn fail(msg: &str) -> ExitCode {
+    eprintln!("tui: {}", msg);
+    eprintln!("{}", USAGE);
+    ExitCode::FAILURE
+}
@@ -0,0 +1,269 @@
+use std::collections::BTreeMap;
+
+use chip8_core::disasm::Syntax;
+use chip8_core::{Emu, Instruction, Platform};
+use ratatui::Frame;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+
+use crate::app::App;
+
+// Same colours as the desktop frontend: black, white and two greys for the XO-CHIP bitplanes
+const PALETTE: [Color; 4] = [
+    Color::Rgb(0, 0, 0),
+    Color::Rgb(255, 255, 255),
+    Color::Rgb(170, 170, 170),
+    Color::Rgb(85, 85, 85),
+];
+
+const BYTES_PER_ROW: usize = 16;
+
+/*
+ * Screen layout:
+ *
+ *   +-----------------+-----------+
+ *   | display         | registers |
+ *   |                 | stack     |
+ *   +-----------------+-----------+
+ *   | disassembly     | memory    |
+ *   +-----------------+-----------+
+ *   status or : command line
+ */
+pub fn draw(frame: &mut Frame, app: &App) {
+    let emu = app.emu();
+    let display = display_lines(emu, app.braille);
+    let display_width = display.first().map_or(0, |line| line.width()) as u16;
+
+    let [main, status] =
+        Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+    let [top, bottom] = Layout::vertical([
+        Constraint::Length(display.len() as u16 + 2),
+        Constraint::Min(0),
+    ])
+    .areas(main);
+    let [screen, side] =
+        Layout::horizontal([Constraint::Length(display_width + 2), Constraint::Min(0)]).areas(top);
+    let [registers, stack] =
+        Layout::vertical([Constraint::Length(8), Constraint::Min(0)]).areas(side);
+    let [code, memory] =
+        Layout::horizontal([Constraint::Length(40), Constraint::Min(0)]).areas(bottom);
+
+    let title = format!(" {} ", app.platform.map_or("chip-8", Platform::name));
+    frame.render_widget(
+        Paragraph::new(display).block(Block::bordered().title(title)),
+        screen,
+    );
+    frame.render_widget(registers_widget(emu), registers);
+    frame.render_widget(stack_widget(emu), stack);
+    frame.render_widget(disassembly_widget(app, code), code);
+    frame.render_widget(memory_widget(app, memory), memory);
+
+    let status_line = match &app.command {
+        Some(command) => Line::from(format!(":{}", command)),
+        None => Line::styled(
+            app.status.as_str(),
+            Style::new().add_modifier(Modifier::DIM),
+        ),
+    };
+    frame.render_widget(Paragraph::new(status_line), status);
+    if let Some(command) = &app.command {
+        frame.set_cursor_position((status.x + 1 + command.len() as u16, status.y));
+    }
+}
+
+/*
+ * A terminal cell is about twice as tall as it is wide, so with half blocks each cell shows two
+ * pixels stacked vertically: the upper one as the foreground of `▀` and the lower one as the
+ * background. That keeps the XO-CHIP colours and the aspect ratio, at the cost of a 128 column
+ * wide panel in hires mode.
+ *
+ * Braille packs 2x4 pixels into each cell, which fits hires games in 64 columns but only has one
+ * colour: any pixel that isn't 0 is lit.
+ */
+fn display_lines(emu: &Emu, braille: bool) -> Vec<Line<'static>> {
+    let (width, height) = (emu.screen_width(), emu.screen_height());
+    let screen = emu.get_display();
+    let pixel = |x: usize, y: usize| screen[x + y * width];
+
+    if braille {
+        // Bit of each dot in the braille pattern, indexed by [row][column]
+        const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+        return (0..height)
+            .step_by(4)
+            .map(|y| {
+                let row: String = (0..width)
+                    .step_by(2)
+                    .map(|x| {
+                        let mut bits = 0;
+                        for (dy, dots) in DOTS.iter().enumerate() {
+                            for (dx, dot) in dots.iter().enumerate() {
+                                if pixel(x + dx, y + dy) != 0 {
+                                    bits |= dot;
+                                }
+                            }
+                        }
+                        char::from_u32(0x2800 + bits).unwrap_or(' ')
+                    })
+                    .collect();
+                Line::styled(row, Style::new().fg(PALETTE[1]).bg(PALETTE[0]))
+            })
+            .collect();
+    }
+
+    (0..height)
+        .step_by(2)
+        .map(|y| {
+            let spans: Vec<Span> = (0..width)
+                .map(|x| {
+                    let style = Style::new()
+                        .fg(PALETTE[pixel(x, y) as usize & 3])
+                        .bg(PALETTE[pixel(x, y + 1) as usize & 3]);
+                    Span::styled("▀", style)
+                })
+                .collect();
+            Line::from(spans)
+        })
+        .collect()
+}
+
+fn registers_widget(emu: &Emu) -> Paragraph<'static> {
+    let mut lines: Vec<Line> = emu
+        .registers()
+        .chunks(4)
+        .enumerate()
+        .map(|(row, regs)| {
+            let cells: Vec<String> = regs
+                .iter()
+                .enumerate()
+                .map(|(col, value)| format!("V{:X}={:02X}", row * 4 + col, value))
+                .collect();
+            Line::from(cells.join("  "))
+        })
+        .collect();
+    lines.push(Line::from(format!(
+        "PC={:04X} I={:04X}",
+        emu.pc(),
+        emu.i_reg()
+    )));
+    lines.push(Line::from(format!(
+        "DT={:02X}   ST={:02X}",
+        emu.delay_timer(),
+        emu.sound_timer()
+    )));
+
+    Paragraph::new(lines).block(Block::bordered().title(" registers "))
+}
+
+fn stack_widget(emu: &Emu) -> Paragraph<'static> {
+    // Innermost return address first
+    let lines: Vec<Line> = emu
+        .stack()
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(depth, addr)| Line::from(format!("{:2}: {:04X}", depth, addr)))
+        .collect();
+
+    let title = format!(" stack ({}) ", emu.stack().len());
+    Paragraph::new(lines).block(Block::bordered().title(title))
+}
+
+/*
+ * Instructions don't have a fixed size (`F000 NNNN` takes four bytes), so there's no reliable way
+ * to disassemble backwards from the PC. The lines above it are just every other address, the PC
+ * and everything after it are decoded properly.
+ */
+fn disassembly_widget(app: &App, area: Rect) -> Paragraph<'static> {
+    let emu = app.emu();
+    let pc = emu.pc();
+    let rows = area.height.saturating_sub(2) as usize;
+    let before = (rows / 3).min(pc as usize / 2);
+    let breakpoints: Vec<u16> = app.debugger.breakpoints().collect();
+    let labels = BTreeMap::new();
+
+    let mut lines = Vec::with_capacity(rows);
+    let mut addr = pc as usize - before * 2;
+    while lines.len() < rows && addr < emu.ram().len() {
+        let instruction = Instruction::decode_at(emu.ram(), addr);
+        let text = match instruction {
+            Some(instruction) => instruction.format(Syntax::Cowgod, &labels),
+            None => "??".to_string(),
+        };
+        let marker = match (addr == pc as usize, breakpoints.contains(&(addr as u16))) {
+            (true, true) => ">*",
+            (true, false) => "> ",
+            (false, true) => " *",
+            (false, false) => "  ",
+        };
+
+        let style = if addr == pc as usize {
+            Style::new().add_modifier(Modifier::REVERSED)
+        } else if breakpoints.contains(&(addr as u16)) {
+            Style::new().fg(Color::Red)
+        } else {
+            Style::new()
+        };
+        lines.push(Line::styled(
+            format!("{}{:04X}  {}", marker, addr, text),
+            style,
+        ));
+
+        addr += match instruction {
+            Some(instruction) if addr >= pc as usize => instruction.size(),
+            _ => 2,
+        };
+    }
+
+    Paragraph::new(lines).block(Block::bordered().title(" disassembly "))
+}
+
+// Hex dump starting at `mem_addr`, with the bytes at the PC and at I highlighted
+fn memory_widget(app: &App, area: Rect) -> Paragraph<'static> {
+    let emu = app.emu();
+    let ram = emu.ram();
+    let (pc, i) = (emu.pc() as usize, emu.i_reg() as usize);
+    let rows = area.height.saturating_sub(2) as usize;
+
+    let mut lines = Vec::with_capacity(rows);
+    for row in 0..rows {
+        let start = app.mem_addr as usize + row * BYTES_PER_ROW;
+        let Some(bytes) = ram.get(start..(start + BYTES_PER_ROW).min(ram.len())) else {
+            break;
+        };
+        if bytes.is_empty() {
+            break;
+        }
+
+        let mut spans = vec![Span::raw(format!("{:04X}:", start))];
+        for (offset, byte) in bytes.iter().enumerate() {
+            let addr = start + offset;
+            let style = if (pc..pc + 2).contains(&addr) {
+                Style::new().add_modifier(Modifier::REVERSED)
+            } else if addr == i {
+                Style::new().fg(Color::Yellow)
+            } else {
+                Style::new()
+            };
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(format!("{:02X}", byte), style));
+        }
+
+        let text: String = bytes
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7E => b as char,
+                _ => '.',
+            })
+            .collect();
+        spans.push(Span::styled(
+            format!("  {}", text),
+            Style::new().add_modifier(Modifier::DIM),
+        ));
+        lines.push(Line::from(spans));
+    }
+
+    Paragraph::new(lines).block(Block::bordered().title(" memory "))
+}